@@ -1,10 +1,12 @@
 use gravitydb::schema::{SchemaElement, Property};
-use crate::{FileStoreError, FsKvStore};
+use crate::{FileStoreError, FsKvStore, LockMode};
 use anyhow::bail;
 use std::io::{self, Write};
-use gravitydb::GraphStore;
-use gravitydb::kv_graph_store::{KvGraphStore, SerialisationError, Uuid};
+use gravitydb::{DeletionMode, GraphStore, PropertyFilter, PropertyGraphReader};
+use gravitydb::versioning::{self, Versioned};
+use gravitydb::kv_graph_store::{KvGraphStore, QueryResult, SerialisationError, Uuid};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use clap::Parser;
 use std::io::Read;
 use anyhow::Result;
@@ -51,6 +53,10 @@ where
     output: Option<PathBuf>,
     #[clap(action = clap::ArgAction::Count, short)]
     verbosity: u8,
+    /// seconds to wait at most if another process is using the database
+    /// (waits until it is done otherwise)
+    #[clap(long)]
+    wait: Option<u64>,
     #[clap(subcommand)]
     cmd: CmdOpts,
   }
@@ -72,6 +78,10 @@ where
     DeleteNode {
       #[clap(long)]
       id: uuid::Uuid,
+      /// delete the edges of the node as well (otherwise nodes with edges
+      /// can not be deleted)
+      #[clap(long)]
+      cascade: bool,
     },
     /// create a new edge
     CreateEdge {
@@ -85,12 +95,35 @@ where
     /// create property storage blob from content
     PropertyBlob,
     /// run a query on the database
-    QueryDb,
+    QueryDb {
+      /// the language the query is written in
+      #[clap(long, value_enum, default_value_t = QueryLanguage::Json)]
+      lang: QueryLanguage,
+      /// continue a query ending with a limit at the cursor of the previous
+      /// page
+      #[clap(long)]
+      cursor: Option<String>,
+      /// rewrite the query to be evaluated faster before running it
+      #[clap(long)]
+      optimize: bool,
+      /// show how the query is evaluated instead of its result
+      #[clap(long)]
+      explain: bool,
+      /// fill in the ids of the properties of all vertices and edges (so
+      /// that `result-data` does not have to read them again)
+      #[clap(long)]
+      property_ids: bool,
+    },
     /// lua repl for the database
     Repl,
     /// run a lua script
     Script,
-    /// get property data for query result
+    /// extend a query result by the neighbours of its vertices and all
+    /// edges between them
+    ResultOuterHull,
+    /// restrict a query result to its vertices and all edges between them
+    ResultInnerHull,
+    /// get property data for query result (the output of query-db)
     ResultData,
     /// initialize a new database
     Init,
@@ -99,6 +132,8 @@ where
   let opt = Opt::parse();
   simple_logger::init_with_level(log_level(opt.verbosity))?;
 
+  let timeout = opt.wait.map(Duration::from_secs);
+
   use CmdOpts::*;
   match opt.cmd {
     CreateNode {id, create_id, update, get_or_create} => {
@@ -112,68 +147,28 @@ where
 
       let properties = read_input(opt.input)?;
       let properties: T = SchemaElement::deserialize(&properties)?;
+      let mut db = open(&opt.db_path, LockMode::Exclusive, timeout)?;
       let id = match id {
         Some(id) => id,
         None => {
-          let hash = properties.get_key();
-          if opt
-            .db_path
-            .join("props/")
-            .join(&hash)
-            .exists()
-          {
-            if create_id {
-              uuid::Uuid::new_v4()
-            } else if get_or_create {
-              let index_path = opt.db_path.join("indexes/").join(hash + "/");
-              let mut nodes: Vec<uuid::Uuid> = std::fs::read_dir(&index_path)?.into_iter()
-                .filter(|entry| {
-                  match entry {
-                    Ok(entry) => {
-                      let reference = entry
-                        .file_name()
-                        .into_string()
-                        .unwrap();
-                      let (prefix, _reference) = reference
-                        .split_once("_")
-                        .unwrap();
-                      if prefix == "nodes" {
-                        true
-                      } else {
-                        false
-                      }
-                    }
-                    Err(_) => false
-                  }
-                })
-                .take(2)
-                .map(|entry| {
-                  let entry = entry.unwrap();
-                  let reference = entry
-                    .file_name()
-                    .into_string()
-                    .unwrap();
-                  let (_prefix, reference) = reference
-                    .split_once("_")
-                    .unwrap();
-                  uuid::Uuid::parse_str(reference).unwrap()
-                })
-                .collect();
-              if nodes.len() == 1 {
-                nodes.pop().unwrap()
-              } else {
-                bail!("There are several nodes with the same properties. Can't deside which one to use. Please use `--id` to specify the exact node");
-              }
+          let mut nodes: Vec<Uuid> = db
+            .nodes(PropertyFilter::Only(properties.get_key()))?
+            .take(2)
+            .collect();
+          if nodes.is_empty() || create_id {
+            uuid::Uuid::new_v4()
+          } else if get_or_create {
+            if nodes.len() == 1 {
+              nodes.pop().unwrap().0
             } else {
-              bail!("node allready exists. Please use `--create-id` to create a node with equal data anyway");
+              bail!("There are several nodes with the same properties. Can't deside which one to use. Please use `--id` to specify the exact node");
             }
           } else {
-            uuid::Uuid::new_v4()
+            bail!("node allready exists. Please use `--create-id` to create a node with equal data anyway");
           }
         }
       };
 
-      let mut db = open(&opt.db_path)?;
       if !update {
         db.create_node(Uuid(id), &properties)?;
       } else {
@@ -182,16 +177,21 @@ where
 
       println!("{}", id); // TODO opt.output, opt.output_fmt
     }
-    DeleteNode {id} => {
-      let mut db = open::<T>(&opt.db_path)?;
-      db.delete_node(Uuid(id))?;
+    DeleteNode {id, cascade} => {
+      let mode = if cascade {
+        DeletionMode::Cascade
+      } else {
+        DeletionMode::Reject
+      };
+      let mut db = open::<T>(&opt.db_path, LockMode::Exclusive, timeout)?;
+      db.delete_node(Uuid(id), mode)?;
       log::info!("deleted node {}", id);
     }
     CreateEdge { n1, n2 } => {
       let properties = read_input(opt.input)?;
       let properties: T = SchemaElement::deserialize(&properties)?;
 
-      let mut db = open(&opt.db_path)?;
+      let mut db = open(&opt.db_path, LockMode::Exclusive, timeout)?;
       let id = db.create_edge(Uuid(n1), Uuid(n2), &properties)?;
 
       println!("{}", id); // TODO opt.output, opt.output_fmt
@@ -209,35 +209,57 @@ where
 
       io::stdout().write_all(&SchemaElement::serialize(&properties)?)?;
     }
-    QueryDb => {
+    QueryDb { lang, cursor, optimize, explain, property_ids } => {
       let query = read_input(opt.input)?;
-      let query = to_query(&query)?;
+      let query = to_query::<T>(&query, lang)?;
 
-      let db = open::<T>(&opt.db_path)?;
-      let result = db.query(query)?;
+      let db = open::<T>(&opt.db_path, LockMode::Shared, timeout)?;
+      let query = if optimize { db.optimize(query)? } else { query };
+      if explain {
+        print!("{}", db.explain(query)?);
+        return Ok(());
+      }
+      let result = db.query_page(query, cursor.as_deref())?;
+      let result = if property_ids { db.with_property_ids(result)? } else { result };
 
 
       // TODO verschiedene output formate
-      println!("{}", serde_json::to_string_pretty(&result)?); // TODO wenn kein Terminal sondern eine pipe verwendet wird kann man kompakteres json ausgeben.
-
-      // TODO Umschliessende Huelle? Alle miteinander verbundenen Edges und Vertices?
+      println!("{}", serde_json::to_string_pretty(&Versioned::new(&result))?); // TODO wenn kein Terminal sondern eine pipe verwendet wird kann man kompakteres json ausgeben.
     }
     Repl => {
-      let db = open::<T>(&opt.db_path)?;
+      let db = open::<T>(&opt.db_path, LockMode::Exclusive, timeout)?;
       gravitydb::lua::lua_repl::<T, FsKvStore, _, anyhow::Error>(db, init_fn)?;
     }
     Script => {
       let path = opt.input.expect("script needs an input parameter");
       let code = std::fs::read_to_string(&path)?;
-      let db = open::<T>(&opt.db_path)?;
+      let db = open::<T>(&opt.db_path, LockMode::Exclusive, timeout)?;
       gravitydb::lua::lua_run::<T, FsKvStore, _, _ , _>(db, init_fn, code, path.to_string_lossy())?;
     }
+    ResultOuterHull => {
+      let data = read_input(opt.input)?;
+      let result: QueryResult = versioning::result_from_slice(&data)?;
+
+      let db = open::<T>(&opt.db_path, LockMode::Shared, timeout)?;
+      let hull = db.outer_hull(&result)?;
+
+      println!("{}", serde_json::to_string_pretty(&Versioned::new(&hull))?);
+    }
+    ResultInnerHull => {
+      let data = read_input(opt.input)?;
+      let result: QueryResult = versioning::result_from_slice(&data)?;
+
+      let db = open::<T>(&opt.db_path, LockMode::Shared, timeout)?;
+      let hull = db.inner_hull(&result)?;
+
+      println!("{}", serde_json::to_string_pretty(&Versioned::new(&hull))?);
+    }
     ResultData => {
       let data = read_input(opt.input)?;
-      //let mut data: crate::ql::QueryResult = serde_json::from_slice(&data)?;
+      let result: QueryResult = versioning::result_from_slice(&data)?;
 
-      let db = open::<T>(&opt.db_path)?;
-      //TODO Über die db die Variablen im mit den Properties füllen
+      let db = open::<T>(&opt.db_path, LockMode::Shared, timeout)?;
+      let data = db.resolve(&result, |db, id| db.read_property_json(id))?;
 
       // TODO verschiedene output formate
       println!("{}", serde_json::to_string_pretty(&data)?); // TODO wenn kein Terminal sondern eine pipe verwendet wird kann man kompakteres json ausgeben.
@@ -250,11 +272,11 @@ where
   Ok(())
 }
 
-fn open<T>(path: &Path) -> Result<KvGraphStore<T, FsKvStore, FileStoreError>, FileStoreError>
+fn open<T>(path: &Path, mode: LockMode, timeout: Option<Duration>) -> Result<KvGraphStore<T, FsKvStore, FileStoreError>, FileStoreError>
 where
   T: Prop,
 {
-  let kv = FsKvStore::open(path)?;
+  let kv = FsKvStore::open_locked(path, mode, timeout)?;
   Ok(KvGraphStore::from_kv(kv))
 }
 
@@ -268,10 +290,31 @@ where
 
 type BasicQuery = gravitydb::kv_graph_store::BasicQuery;
 
-fn to_query(data: &Vec<u8>) -> Result<BasicQuery, SerialisationError> {
-  // TODO Verschiedene Query Sprachen über zweiten Parameter
+/// The languages a query can be written in
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum QueryLanguage {
+  /// the serialized query structure
+  Json,
+  /// the textual syntax of zoe
+  Zoe,
+}
+
+fn to_query<T: Prop>(data: &[u8], lang: QueryLanguage) -> Result<BasicQuery> {
   // TODO Internes Schema verwenden um Abfragen zu verbessern
-  let query = serde_json::from_slice(data)?;
+  let query = match lang {
+    QueryLanguage::Json => versioning::query_from_slice(data)?,
+    QueryLanguage::Zoe => {
+      let text = std::str::from_utf8(data)?;
+      let resolve = |value: &serde_json::Value| {
+        let property: T = SchemaElement::deserialize(&serde_json::to_vec(value).ok()?).ok()?;
+        Some(property.get_key())
+      };
+      match gravitydb::zoe::parse_with(text, resolve) {
+        Ok(query) => query,
+        Err(e) => bail!("{}", e.report(text)),
+      }
+    }
+  };
 
   Ok(query)
 }
@@ -1,118 +1,616 @@
 use vfs::{MemoryFS, PhysicalFS, VfsError, VfsPath, VfsResult};
-use gravitydb::KVStore;
-use std::path::Path;
+use gravitydb::{KVStore, RecordIterator, ScanDirection, ScanRange, TransactionalKVStore};
+use std::ops::Bound::{Excluded, Included};
+use std::ops::RangeBounds;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{File, TryLockError};
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 pub mod cli_helpers;
 
+/// One of the two copies of the data
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Store {
+  A,
+  B,
+}
+
+impl Store {
+  fn other(self) -> Self {
+    match self {
+      Store::A => Store::B,
+      Store::B => Store::A,
+    }
+  }
+
+  fn dir_name(self) -> &'static str {
+    match self {
+      Store::A => "a",
+      Store::B => "b",
+    }
+  }
+}
+
+/// The content of the pointer file
+#[derive(Serialize, Deserialize)]
+struct Head {
+  /// the copy which is valid for reading
+  current: Store,
+  /// the process which is writing into the other copy (if any)
+  writer: Option<u32>,
+  /// the paths of the records changed by the last transaction
+  ///
+  /// These are the only records in which both copies differ. They need
+  /// to be synchronised before the next transaction can start.
+  pending: BTreeSet<String>,
+}
+
+/// The records and buckets changed in a running transaction
+#[derive(Default)]
+struct Transaction {
+  /// the paths of the records relative to the copy
+  changed: BTreeSet<String>,
+}
+
+/// The way a process accesses the database
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LockMode {
+  /// read only access, which can be shared with other processes
+  Shared,
+  /// read and write access, only one process at a time can write
+  Exclusive,
+}
+
+struct Locks {
+  mode: LockMode,
+  /// how long to wait for a lock held by another process
+  timeout: Option<Duration>,
+  /// held as long as a writing process has the database open
+  writer: Option<File>,
+  /// a writer holds an exclusive lock on the copy it writes to during a
+  /// transaction
+  store: Option<File>,
+}
+
+/// A key value store on top of a (virtual) file system
+///
+/// The database directory contains two copies of the data (`a/` and
+/// `b/`) and a pointer file (`HEAD`) naming the copy which is currently
+/// valid. Writers only ever change the other copy and make it the valid
+/// one by atomically replacing the pointer file. That way a crash in the
+/// middle of a transaction always leaves a consistent database behind.
+///
+/// Files are shared between both copies via hard links. Therefore a file
+/// is never changed in place but always removed and written anew.
+///
+/// Several processes can use the same database on disk. Only one of them
+/// can write at a time while readers lock the copy they are reading for
+/// as long as a read takes, so that no writer changes it under their
+/// feet. Every read starts at the copy which is valid at that moment.
 pub struct FsKvStore {
-  base_path: VfsPath,
+  /// the copy holding the valid data
+  current: Store,
+  transaction: Option<Transaction>,
+  /// the file locks held by this process (`None` if the store lives in
+  /// memory)
+  locks: Option<Locks>,
+  /// the directory containing the pointer file and both copies
+  root: VfsPath,
+  /// the location of `root` on disk (`None` if the store lives in memory)
+  physical_root: Option<PathBuf>,
 }
 
+const BUCKETS: [&str; 4] = ["nodes", "edges", "props", "indexes"];
+const HEAD: &str = "HEAD";
+const NEW_HEAD: &str = "HEAD.new";
+const WRITER_LOCK: &str = "writer.lock";
+
 impl KVStore<FileStoreError> for FsKvStore
 {
   fn create_bucket(&mut self, key: &[u8]) -> Result<(), FileStoreError> {
-    Ok(self.key_to_path(key)?.create_dir_all()?)
+    self.autocommit(|store| {
+      let path = store.change(key)?;
+      Ok(path.create_dir_all()?)
+    })
   }
 
   fn delete_record(&mut self, key: &[u8]) -> Result<(), FileStoreError> {
-    Ok(self.key_to_path(key)?.remove_file()?)
+    self.autocommit(|store| {
+      let path = store.change(key)?;
+      Ok(path.remove_file()?)
+    })
   }
 
   fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), FileStoreError> {
-    Ok(self.key_to_path(key)?.create_file()?.write_all(value)?)
+    self.autocommit(|store| {
+      let path = store.change(key)?;
+      // the file might be shared with the other copy
+      if path.exists()? {
+        path.remove_file()?;
+      }
+      Ok(path.create_file()?.write_all(value)?)
+    })
   }
 
   fn fetch_record(&self, key: &[u8]) -> Result<Vec<u8>, FileStoreError> {
+    let (store, _lock) = self.reader()?;
     let mut content = vec![];
-    self.key_to_path(key)?.open_file()?.read_to_end(&mut content)?;
+    self.key_to_path(store, key)?.open_file()?.read_to_end(&mut content)?;
     Ok(content)
   }
 
   fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, FileStoreError> {
-    let to_path = if to.len() != 0 {
-      self.key_to_path(to)?
-    } else {
-      let mut to: Vec<u8> = from.to_vec();
-      *to.last_mut().unwrap() += 1;
-      self.key_to_path(&to)?
-    };
-    let from_path = self.key_to_path(from)?;
-    let base = match longest_shared_path(&from_path, &to_path) {
-      Some(base) => base,
-      None => return Err(FileStoreError::InvalidParameters),
-    };
-    Ok(list_files(&base)?
-      .into_iter()
-      .filter(|key| **key < *from || **key > *to)
-      .collect())
+    let (store, _lock) = self.reader()?;
+    self.list_range(store, ScanRange::Between(from, to))
+  }
+
+  fn scan<'a>(&'a self, range: ScanRange<'_>, direction: ScanDirection) -> Result<RecordIterator<'a, FileStoreError>, FileStoreError>
+  where
+    FileStoreError: 'a,
+  {
+    // all records are read from the same copy, even if a transaction
+    // starts in the meantime
+    let (store, lock) = self.reader()?;
+    let mut keys = self.list_range(store, range)?;
+    keys.sort();
+    if direction == ScanDirection::Reverse {
+      keys.reverse();
+    }
+    Ok(Box::new(keys.into_iter().map(move |key| {
+      // readers keep the copy locked until the scan is dropped
+      let _lock = &lock;
+      let mut value = vec![];
+      self.key_to_path(store, &key)?.open_file()?.read_to_end(&mut value)?;
+      Ok((key, value))
+    })))
   }
 
   fn exists(&self, key: &[u8]) -> Result<bool, FileStoreError> {
-    Ok(self.key_to_path(key)?.exists()?)
+    let (store, _lock) = self.reader()?;
+    Ok(self.key_to_path(store, key)?.exists()?)
+  }
+}
+
+impl TransactionalKVStore<FileStoreError> for FsKvStore
+{
+  fn begin_transaction(&mut self) -> Result<(), FileStoreError> {
+    if self.transaction.is_some() {
+      return Err(FileStoreError::TransactionRunning);
+    }
+    // readers can not write
+    if self.locks.as_ref().is_some_and(|locks| locks.mode == LockMode::Shared) {
+      return Err(FileStoreError::ReadOnly);
+    }
+
+    // another process might have committed in the meantime
+    let head = read_head(&self.root)?;
+
+    if let Some(locks) = self.locks.as_ref() {
+      // wait until all readers of the copy we are going to change are done
+      let store_lock = lock_file(&self.lock_path(head.current.other()), LockMode::Exclusive, locks.timeout)?;
+      if let Some(locks) = self.locks.as_mut() {
+        locks.store = Some(store_lock);
+      }
+    }
+    self.current = head.current;
+    self.write_head(&Head {
+      current: head.current,
+      writer: Some(std::process::id()),
+      pending: head.pending.clone(),
+    })?;
+
+    // bring the copy we are going to write to up to date
+    for path in head.pending.iter() {
+      self.sync_record(path, head.current, head.current.other())?;
+    }
+
+    self.transaction = Some(Transaction::default());
+    Ok(())
+  }
+
+  fn commit_transaction(&mut self) -> Result<(), FileStoreError> {
+    let transaction = self.transaction.take().ok_or(FileStoreError::NoTransaction)?;
+    let written = self.current.other();
+
+    if self.physical_root.is_some() {
+      for path in transaction.changed.iter() {
+        let path = self.record_path(written, path)?;
+        if path.is_file()? {
+          self.sync_file(&path)?;
+        }
+        self.sync_file(&path.parent())?;
+      }
+    }
+
+    self.write_head(&Head {
+      current: written,
+      writer: None,
+      pending: transaction.changed,
+    })?;
+    self.current = written;
+    self.release_store_lock();
+    Ok(())
+  }
+
+  fn rollback_transaction(&mut self) -> Result<(), FileStoreError> {
+    let transaction = self.transaction.take().ok_or(FileStoreError::NoTransaction)?;
+
+    for path in transaction.changed.iter() {
+      self.sync_record(path, self.current, self.current.other())?;
+    }
+
+    self.write_head(&Head {
+      current: self.current,
+      writer: None,
+      pending: BTreeSet::new(),
+    })?;
+    self.release_store_lock();
+    Ok(())
   }
 }
 
 impl FsKvStore {
-  fn key_to_path(&self, key: &[u8]) -> Result<VfsPath, FileStoreError> {
-    let mut path = self.base_path.clone();
-    for component in String::from_utf8_lossy(key).split("/") {
-      path = path.join(component)?;
+  fn store_path(&self, store: Store) -> Result<VfsPath, FileStoreError> {
+    Ok(self.root.join(store.dir_name())?)
+  }
+
+  /// the file of a record given by its path relative to the copy
+  fn record_path(&self, store: Store, path: &str) -> Result<VfsPath, FileStoreError> {
+    Ok(self.store_path(store)?.join(path)?)
+  }
+
+  fn key_to_path(&self, store: Store, key: &[u8]) -> Result<VfsPath, FileStoreError> {
+    self.record_path(store, &encode_key(key))
+  }
+
+  /// all keys of a copy within a range
+  fn list_range(&self, store: Store, range: ScanRange<'_>) -> Result<Vec<Vec<u8>>, FileStoreError> {
+    let (from, to) = range.bounds();
+    let root = self.store_path(store)?;
+    // only the directory containing both ends has to be searched
+    let base = match (&from, &to) {
+      (Included(from), Included(to) | Excluded(to)) => {
+        longest_shared_path(&self.key_to_path(store, from)?, &self.key_to_path(store, to)?)
+          .unwrap_or_else(|| root.clone())
+      }
+      _ => root.clone(),
+    };
+    let mut keys = list_files(&base, &(root.as_str().to_string() + "/"))?;
+    let range = (from, to);
+    keys.retain(|key| range.contains(key));
+    Ok(keys)
+  }
+
+  /// atomically replace the pointer file
+  fn write_head(&self, head: &Head) -> Result<(), FileStoreError> {
+    let new_head = self.root.join(NEW_HEAD)?;
+    new_head.create_file()?.write_all(&serde_json::to_vec(head)?)?;
+
+    match self.physical_root.as_ref() {
+      Some(root) => {
+        self.sync_file(&new_head)?;
+        std::fs::rename(root.join(NEW_HEAD), root.join(HEAD))?;
+        self.sync_file(&self.root)?;
+      }
+      None => {
+        let head = self.root.join(HEAD)?;
+        if head.exists()? {
+          head.remove_file()?;
+        }
+        new_head.move_file(&head)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// make sure a file (or directory) is written to disk
+  fn sync_file(&self, path: &VfsPath) -> Result<(), FileStoreError> {
+    if let Some(root) = self.physical_root.as_ref() {
+      // directories can only be synced on unix like systems
+      if cfg!(unix) || path.is_file()? {
+        std::fs::File::open(physical_path(root, path))?.sync_all()?;
+      }
+    }
+    Ok(())
+  }
+
+  /// the copy which reflects the latest changes
+  fn read_store(&self) -> Store {
+    if self.transaction.is_some() {
+      self.current.other()
+    } else {
+      self.current
+    }
+  }
+
+  /// make a record in one copy equal to the one in the other copy
+  fn sync_record(&self, path: &str, from: Store, to: Store) -> Result<(), FileStoreError> {
+    let source = self.record_path(from, path)?;
+    let target = self.record_path(to, path)?;
+
+    if source.is_dir()? {
+      target.create_dir_all()?;
+    } else if source.exists()? {
+      if target.is_dir()? {
+        target.remove_dir_all()?;
+      } else if target.exists()? {
+        target.remove_file()?;
+      }
+      target.parent().create_dir_all()?;
+      self.link_file(&source, &target)?;
+    } else if target.is_dir()? {
+      target.remove_dir_all()?;
+    } else if target.exists()? {
+      target.remove_file()?;
+    }
+    Ok(())
+  }
+
+  fn link_file(&self, source: &VfsPath, target: &VfsPath) -> Result<(), FileStoreError> {
+    match self.physical_root.as_ref() {
+      Some(root) => std::fs::hard_link(physical_path(root, source), physical_path(root, target))?,
+      None => source.copy_file(target)?,
+    }
+    Ok(())
+  }
+
+  /// make a copy equal to the other copy by linking all its files
+  fn sync_store(&self, from: Store, to: Store) -> Result<(), FileStoreError> {
+    let target = self.store_path(to)?;
+    if target.exists()? {
+      target.remove_dir_all()?;
+    }
+    target.create_dir_all()?;
+    self.link_dir(&self.store_path(from)?, &target)
+  }
+
+  fn link_dir(&self, source: &VfsPath, target: &VfsPath) -> Result<(), FileStoreError> {
+    for entry in source.read_dir()? {
+      let entry_target = target.join(entry.filename())?;
+      if entry.is_dir()? {
+        entry_target.create_dir()?;
+        self.link_dir(&entry, &entry_target)?;
+      } else {
+        self.link_file(&entry, &entry_target)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// remember a record as changed and get its path in the written copy
+  fn change(&mut self, key: &[u8]) -> Result<VfsPath, FileStoreError> {
+    let relative = encode_key(key);
+    let path = self.record_path(self.current.other(), &relative)?;
+    if let Some(transaction) = self.transaction.as_mut() {
+      transaction.changed.insert(relative);
     }
     Ok(path)
   }
 
+  /// run a single change in its own transaction if none is running
+  fn autocommit<F>(&mut self, f: F) -> Result<(), FileStoreError>
+  where
+    F: FnOnce(&mut Self) -> Result<(), FileStoreError>,
+  {
+    if self.transaction.is_some() {
+      return f(self);
+    }
+
+    self.begin_transaction()?;
+    match f(self) {
+      Ok(()) => self.commit_transaction(),
+      Err(e) => {
+        self.rollback_transaction()?;
+        Err(e)
+      }
+    }
+  }
+
+  fn lock_path(&self, store: Store) -> PathBuf {
+    let root = self.physical_root.clone().unwrap_or_default();
+    root.join(format!("{}.lock", store.dir_name()))
+  }
+
+  fn release_store_lock(&mut self) {
+    if let Some(locks) = self.locks.as_mut() {
+      locks.store = None;
+    }
+  }
+
+  /// lock the currently valid copy for reading
+  fn register_reader(&self) -> Result<(Head, File), FileStoreError> {
+    let timeout = self.locks.as_ref().and_then(|locks| locks.timeout);
+    loop {
+      let head = read_head(&self.root)?;
+      let store_lock = lock_file(&self.lock_path(head.current), LockMode::Shared, timeout)?;
+      // the writer might have switched the copies before we got the lock
+      if read_head(&self.root)?.current == head.current {
+        return Ok((head, store_lock));
+      }
+    }
+  }
+
+  /// the copy to read from and, for readers, the lock which keeps
+  /// writers away from it while reading
+  fn reader(&self) -> Result<(Store, Option<File>), FileStoreError> {
+    match self.locks.as_ref() {
+      Some(locks) if locks.mode == LockMode::Shared => {
+        let (head, lock) = self.register_reader()?;
+        Ok((head.current, Some(lock)))
+      }
+      _ => Ok((self.read_store(), None)),
+    }
+  }
+
+  /// Open an existing database for reading and writing
+  ///
+  /// Waits until no other process is writing.
   pub fn open(path: &Path) -> Result<Self, FileStoreError> {
+    Self::open_locked(path, LockMode::Exclusive, None)
+  }
+
+  /// Open an existing database
+  ///
+  /// With `LockMode::Shared` the database can only be read but several
+  /// processes can do so at the same time. If a lock is held by another
+  /// process, we wait for it to be released. With a `timeout` we fail
+  /// with `FileStoreError::Locked` if it is not released in time.
+  ///
+  /// If the last writing process did not finish its transaction, the
+  /// changes it made are thrown away. Databases created before the
+  /// introduction of the pointer file are converted.
+  pub fn open_locked(path: &Path, mode: LockMode, timeout: Option<Duration>) -> Result<Self, FileStoreError> {
     let root = VfsPath::new(PhysicalFS::new(path.to_path_buf()));
 
     if !root.is_dir()? {
       return Err(FileStoreError::MalformedDB);
     }
 
-    let check_dirs = ["nodes", "edges", "props", "indexes"];
-    for dir in &check_dirs {
-      if !root.join(dir)?.is_dir()? {
+    let writer = match mode {
+      LockMode::Exclusive => Some(lock_file(&path.join(WRITER_LOCK), LockMode::Exclusive, timeout)?),
+      LockMode::Shared => None,
+    };
+    let mut store = FsKvStore {
+      root,
+      physical_root: Some(path.to_path_buf()),
+      current: Store::A,
+      transaction: None,
+      locks: Some(Locks {
+        mode,
+        timeout,
+        writer,
+        store: None,
+      }),
+    };
+
+    if !store.root.join(HEAD)?.exists()? {
+      store.convert_flat_layout()?;
+    }
+
+    let head = match mode {
+      LockMode::Exclusive => read_head(&store.root)?,
+      LockMode::Shared => store.register_reader()?.0,
+    };
+    store.current = head.current;
+
+    for dir in &BUCKETS {
+      if !store.store_path(head.current)?.join(dir)?.is_dir()? {
         return Err(FileStoreError::MalformedDB);
       }
     }
 
-    Ok(FsKvStore {
-      base_path: root,
+    // as long as we are the only writer, nobody else can be working on
+    // the transaction
+    if head.writer.is_some() && mode == LockMode::Exclusive {
+      store.recover()?;
+    }
+
+    Ok(store)
+  }
+
+  /// throw away an unfinished transaction
+  fn recover(&mut self) -> Result<(), FileStoreError> {
+    self.sync_store(self.current, self.current.other())?;
+    self.write_head(&Head {
+      current: self.current,
+      writer: None,
+      pending: BTreeSet::new(),
     })
   }
 
+  /// move the buckets of a database without pointer file into the first
+  /// copy
+  fn convert_flat_layout(&mut self) -> Result<(), FileStoreError> {
+    let path = self.physical_root.clone().ok_or(FileStoreError::MalformedDB)?;
+
+    // readers need to become a writer for a moment
+    let timeout = self.locks.as_ref().and_then(|locks| locks.timeout);
+    let _writer = match self.locks.as_ref().and_then(|locks| locks.writer.as_ref()) {
+      Some(_) => None,
+      None => Some(lock_file(&path.join(WRITER_LOCK), LockMode::Exclusive, timeout)?),
+    };
+    if self.root.join(HEAD)?.exists()? {
+      // somebody else was faster
+      return Ok(());
+    }
+
+    let first = path.join(Store::A.dir_name());
+    for dir in &BUCKETS {
+      if !self.root.join(dir)?.is_dir()? && !first.join(dir).is_dir() {
+        return Err(FileStoreError::MalformedDB);
+      }
+    }
+
+    std::fs::create_dir_all(&first)?;
+    for dir in &BUCKETS {
+      if self.root.join(dir)?.is_dir()? {
+        std::fs::rename(path.join(dir), first.join(dir))?;
+      }
+    }
+
+    self.current = Store::A;
+    self.recover()
+  }
+
   pub fn init(path: &Path) -> Result<Self, FileStoreError> {
     let root = VfsPath::new(PhysicalFS::new(path.to_path_buf()));
     if !root.is_dir()? {
       if root.exists()? {
         return Err(FileStoreError::MalformedDB);
       } else {
-        root.create_dir_all()?;
+        std::fs::create_dir_all(path)?;
       }
     }
 
-    let check_dirs = ["nodes", "edges", "props", "indexes"];
-    for dir in &check_dirs {
-      root.join(dir)?.create_dir_all()?;
+    if root.join(HEAD)?.exists()? || root.join("nodes")?.exists()? {
+      return Self::open(path);
     }
 
-    Ok(FsKvStore {
-      base_path: root,
-    })
+    let writer = lock_file(&path.join(WRITER_LOCK), LockMode::Exclusive, None)?;
+    let mut store = Self::create(root, Some(path.to_path_buf()))?;
+    store.locks = Some(Locks {
+      mode: LockMode::Exclusive,
+      timeout: None,
+      writer: Some(writer),
+      store: None,
+    });
+    Ok(store)
   }
 
   pub fn from_memory() -> Result<Self, FileStoreError> {
-    let root = VfsPath::new(MemoryFS::new());
+    Self::create(VfsPath::new(MemoryFS::new()), None)
+  }
 
-    let check_dirs = ["nodes", "edges", "props", "indexes"];
-    for dir in &check_dirs {
-      root.join(dir)?.create_dir_all()?;
+  fn create(root: VfsPath, physical_root: Option<PathBuf>) -> Result<Self, FileStoreError> {
+    let store = FsKvStore {
+      root,
+      physical_root,
+      current: Store::A,
+      transaction: None,
+      locks: None,
+    };
+
+    for copy in [Store::A, Store::B] {
+      for dir in &BUCKETS {
+        store.store_path(copy)?.join(dir)?.create_dir_all()?;
+      }
     }
+    store.write_head(&Head {
+      current: Store::A,
+      writer: None,
+      pending: BTreeSet::new(),
+    })?;
 
-    Ok(FsKvStore { base_path: root })
+    Ok(store)
   }
 
+  /// get the directory containing the currently valid data
   pub fn get_root(self) -> VfsPath {
-    self.base_path
+    let (store, _lock) = self.reader().unwrap();
+    self.root.join(store.dir_name()).unwrap()
   }
 }
 
@@ -126,22 +624,67 @@ pub enum FileStoreError {
   Vfs { #[from] source: VfsError },
   #[error("invalid input parameters")]
   InvalidParameters,
+  #[error("serialisation error")]
+  Serialisation { #[from] source: serde_json::Error },
+  #[error("there is already a running transaction")]
+  TransactionRunning,
+  #[error("there is no running transaction")]
+  NoTransaction,
+  #[error("the database is locked by another process")]
+  Locked,
+  #[error("the database was opened read only")]
+  ReadOnly,
 }
 
-fn list_files(dir: &VfsPath) -> VfsResult<Vec<Vec<u8>>> {
+/// the path of a key relative to the copy
+fn encode_key(key: &[u8]) -> String {
+  key.split(|b| *b == b'/')
+    .map(|component| match std::str::from_utf8(component) {
+      Ok(name) if is_plain_name(name) => name.to_string(),
+      _ => std::iter::once("%".to_string())
+        .chain(component.iter().map(|b| format!("{b:02X}")))
+        .collect(),
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// the key of a path relative to the copy
+fn decode_key(path: &str) -> Vec<u8> {
+  let components: Vec<Vec<u8>> = path.split('/')
+    .map(|component| match component.strip_prefix('%') {
+      Some(hex) => (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok())
+        .collect(),
+      None => component.as_bytes().to_vec(),
+    })
+    .collect();
+  components.join(&b'/')
+}
+
+/// whether a part of a key can be used as file name without encoding
+fn is_plain_name(name: &str) -> bool {
+  !name.is_empty()
+    && name != "."
+    && name != ".."
+    && !name.starts_with('%')
+    && !name.chars().any(|c| c.is_control() || c == '\\')
+}
+
+fn list_files(dir: &VfsPath, prefix: &str) -> VfsResult<Vec<Vec<u8>>> {
   let mut result = vec![];
 
   if dir.is_dir()? {
     for path in dir.read_dir()? {
       if path.is_dir()? {
-        result.append(&mut list_files(&path)?);
+        result.append(&mut list_files(&path, prefix)?);
       } else {
         let path = path.as_str();
-        let path = match path.strip_prefix("/") {
+        let path = match path.strip_prefix(prefix) {
           Some(path) => path,
           None => path,
         };
-        result.push(path.as_bytes().to_vec());
+        result.push(decode_key(path));
       }
     }
   }
@@ -161,6 +704,8 @@ fn longest_shared_path(path1: &VfsPath, path2: &VfsPath) -> Option<VfsPath> {
       break;
     }
   }
+  // paths can not end with a separator
+  let shared = shared.trim_end_matches('/');
 
   if !shared.is_empty() {
     let shared = path1.root().join(shared).ok()?;
@@ -173,3 +718,43 @@ fn longest_shared_path(path1: &VfsPath, path2: &VfsPath) -> Option<VfsPath> {
     None
   }
 }
+
+fn read_head(root: &VfsPath) -> Result<Head, FileStoreError> {
+  let content = root.join(HEAD)?.read_to_string()?;
+  serde_json::from_str(&content).map_err(|_| FileStoreError::MalformedDB)
+}
+
+fn physical_path(root: &Path, path: &VfsPath) -> PathBuf {
+  root.join(path.as_str().trim_start_matches('/'))
+}
+
+/// lock a file, waiting up to `timeout` (or until it is released) if it
+/// is locked by another process
+fn lock_file(path: &Path, mode: LockMode, timeout: Option<Duration>) -> Result<File, FileStoreError> {
+  let file = File::options().create(true).truncate(false).write(true).open(path)?;
+  let Some(timeout) = timeout else {
+    match mode {
+      LockMode::Shared => file.lock_shared()?,
+      LockMode::Exclusive => file.lock()?,
+    }
+    return Ok(file);
+  };
+  let start = Instant::now();
+
+  loop {
+    let result = match mode {
+      LockMode::Shared => file.try_lock_shared(),
+      LockMode::Exclusive => file.try_lock(),
+    };
+    match result {
+      Ok(()) => return Ok(file),
+      Err(TryLockError::WouldBlock) => {
+        if start.elapsed() >= timeout {
+          return Err(FileStoreError::Locked);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+      }
+      Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+  }
+}
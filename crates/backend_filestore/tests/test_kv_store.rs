@@ -1,9 +1,11 @@
 use gravitydb::*;
 use gravitydb::kv_graph_store::Uuid;
-use gravitydb_filestore::{FsKvStore, FileStoreError};
+use gravitydb_filestore::{FsKvStore, FileStoreError, LockMode};
 use pretty_assertions::assert_eq;
 use uuid::uuid;
 use vfs::VfsPath;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[test]
 fn create_a_node_in_empty_store() -> Result<(), Error> {
@@ -165,6 +167,346 @@ fn create_two_nodes_with_connection() -> Result<(), Error> {
   Ok(assert_eq!(number_of_files(&store), 0))
 }
 
+#[test]
+fn failed_operations_leave_the_store_unchanged() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  // the second node does not exist, so the edge can not be created
+  match graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec()) {
+    Err(Error::KV(_)) => (),
+    _ => panic!("should fail because the second node does not exist"),
+  };
+
+  let store = get_kv_store(graph);
+  let node_path = format!("nodes/{}", NODE1_UUID);
+  assert_eq!(
+    &remove_file(&store.join(&node_path).unwrap()),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID
+    )
+  );
+  assert_eq!(
+    &remove_file(&store.join(format!("props/{}", PROPERTY_EMPTY_ID)).unwrap()),
+    ""
+  );
+  assert_eq!(
+    &remove_file(&store.join(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID)).unwrap()),
+    &node_path
+  );
+
+  assert_eq!(number_of_files(&store), 0);
+  Ok(())
+}
+
+#[test]
+fn nodes_with_edges_are_only_deleted_on_request() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  match graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject) {
+    Err(Error::NodeHasEdges(msg)) => assert_eq!(msg, NODE2_UUID),
+    _ => panic!("should fail because the node has an edge"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Cascade)?;
+
+  let store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  assert_eq!(
+    &remove_file(&store.join(&node1_path).unwrap()),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID,
+    )
+  );
+  assert_eq!(
+    &remove_file(&store.join(format!("props/{}", PROPERTY_EMPTY_ID)).unwrap()),
+    ""
+  );
+  assert_eq!(
+    &remove_file(&store.join(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID)).unwrap()),
+    &node1_path
+  );
+
+  assert_eq!(number_of_files(&store), 0);
+  Ok(())
+}
+
+#[test]
+fn properties_can_be_found_by_indexed_json_fields() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_JOHN_DOE.to_vec())?;
+  // properties which exist before the index is declared are added
+  graph.create_json_index("/Person/surname")?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_JANE_DOE.to_vec())?;
+
+  let q = ql::PropertyQuery::from_json_field("/Person/surname", "Doe")
+    .referencing_vertices();
+  let result = graph.query(q)?;
+  assert_eq!(result.vertices.len(), 2);
+
+  match graph.query(ql::PropertyQuery::from_json_field("/Person/name", "John")) {
+    Err(Error::MissingIndex(path)) => assert_eq!(path, "/Person/name"),
+    _ => panic!("should fail because the field has no index"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject)?;
+  let result = graph.query(ql::PropertyQuery::from_json_field("/Person/surname", "Doe"))?;
+  assert_eq!(result.properties, std::collections::HashSet::from([PROPERTY_JOHN_DOE_ID.to_string()]));
+
+  let store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  let props_path = format!("props/{}", PROPERTY_JOHN_DOE_ID);
+  assert_eq!(
+    &remove_file(&store.join(format!("indexes/json/{}/{}_{}", SURNAME_PATH_ID, DOE_VALUE_ID, PROPERTY_JOHN_DOE_ID)).unwrap()),
+    &props_path
+  );
+  assert_eq!(
+    &remove_file(&store.join("indexes/json/paths").unwrap()),
+    r#"["/Person/surname"]"#
+  );
+  assert_eq!(
+    &remove_file(&store.join(&node1_path).unwrap()),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_JOHN_DOE_ID,
+    )
+  );
+  assert_eq!(
+    remove_file(&store.join(&props_path).unwrap()),
+    std::str::from_utf8(PROPERTY_JOHN_DOE).unwrap()
+  );
+  assert_eq!(
+    &remove_file(&store.join(format!("indexes/{}/nodes_{}", PROPERTY_JOHN_DOE_ID, NODE1_UUID)).unwrap()),
+    &node1_path
+  );
+
+  assert_eq!(number_of_files(&store), 0);
+  Ok(())
+}
+
+#[test]
+fn records_can_be_scanned() -> Result<(), FileStoreError> {
+  gravitydb_test_utils::scan_records(FsKvStore::from_memory()?)
+}
+
+#[test]
+fn prefixes_end_before_their_successor() -> Result<(), FileStoreError> {
+  let mut store = FsKvStore::from_memory()?;
+  for key in ["nodes/b", "nodes/a", "nodes0"] {
+    store.store_record(key.as_bytes(), b"")?;
+  }
+  let sorted = |mut keys: Vec<Vec<u8>>| {
+    keys.sort();
+    keys
+  };
+
+  assert_eq!(sorted(store.list_records(b"nodes/", b"")?), vec![b"nodes/a".to_vec(), b"nodes/b".to_vec()]);
+  // an empty prefix matches everything
+  assert_eq!(sorted(store.list_records(b"", b"")?), vec![b"nodes/a".to_vec(), b"nodes/b".to_vec(), b"nodes0".to_vec()]);
+  let scanned = store.scan(ScanRange::Prefix(b""), ScanDirection::Reverse)?
+    .map(|record| Ok(record?.0))
+    .collect::<Result<Vec<_>, FileStoreError>>()?;
+  assert_eq!(scanned, vec![b"nodes0".to_vec(), b"nodes/b".to_vec(), b"nodes/a".to_vec()]);
+  // the end of a prefix ending in 0xFF is found by carrying over
+  assert_eq!(store.list_records(b"nodes/\xFF", b"")?, Vec::<Vec<u8>>::new());
+  assert_eq!(store.list_records(b"\xFF", b"")?, Vec::<Vec<u8>>::new());
+  Ok(())
+}
+
+#[test]
+fn keys_can_be_arbitrary_bytes() -> Result<(), FileStoreError> {
+  let path = empty_db_path("binary-keys");
+  // no utf-8, names which already look encoded and names which are no
+  // valid file names
+  let mut keys: Vec<&[u8]> = vec![b"nodes/\xFF", b"nodes/\xFE", b"nodes/%41", b"nodes/A", b"\xC3\x28/x", b"nodes/..", b"nodes/a\\b", b"nodes/\n"];
+  keys.sort();
+
+  let mut store = FsKvStore::init(&path)?;
+  store.begin_transaction()?;
+  store.create_bucket(b"\xC3\x28")?;
+  for key in keys.iter() {
+    store.store_record(key, key)?;
+  }
+  store.commit_transaction()?;
+  // the first record is changed in its own transaction
+  store.store_record(keys[0], b"changed")?;
+  drop(store);
+
+  let store = FsKvStore::open(&path)?;
+  assert_eq!(store.fetch_record(keys[0])?, b"changed");
+  for key in keys[1..].iter() {
+    assert_eq!(store.fetch_record(key)?, *key);
+  }
+  let mut listed = store.list_records(b"", b"")?;
+  listed.sort();
+  assert_eq!(listed, keys);
+  let scanned = store.scan(ScanRange::Prefix(b"nodes/"), ScanDirection::Forward)?
+    .map(|record| Ok(record?.0))
+    .collect::<Result<Vec<_>, FileStoreError>>()?;
+  assert_eq!(scanned, keys.iter().filter(|key| key.starts_with(b"nodes/")).map(|key| key.to_vec()).collect::<Vec<_>>());
+  drop(store);
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn committed_changes_survive_reopening() -> Result<(), FileStoreError> {
+  let path = empty_db_path("reopen");
+
+  let mut store = FsKvStore::init(&path)?;
+  store.begin_transaction()?;
+  store.store_record(b"nodes/x", b"content")?;
+  store.commit_transaction()?;
+  drop(store);
+
+  let store = FsKvStore::open(&path)?;
+  assert_eq!(store.fetch_record(b"nodes/x")?, b"content");
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn interrupted_transactions_are_thrown_away_on_open() -> Result<(), FileStoreError> {
+  let path = empty_db_path("interrupted");
+
+  let mut store = FsKvStore::init(&path)?;
+  store.store_record(b"nodes/x", b"old")?;
+  store.store_record(b"nodes/x", b"older")?;
+  store.begin_transaction()?;
+  store.store_record(b"nodes/x", b"new")?;
+  store.store_record(b"nodes/y", b"new")?;
+  // simulate a crash by never finishing the transaction
+  drop(store);
+
+  let mut store = FsKvStore::open(&path)?;
+  assert_eq!(store.fetch_record(b"nodes/x")?, b"older");
+  assert!(!store.exists(b"nodes/y")?);
+
+  // the database is still usable after the recovery
+  store.store_record(b"nodes/y", b"new")?;
+  drop(store);
+  let store = FsKvStore::open(&path)?;
+  assert_eq!(store.fetch_record(b"nodes/x")?, b"older");
+  assert_eq!(store.fetch_record(b"nodes/y")?, b"new");
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn databases_without_pointer_file_are_converted() -> Result<(), FileStoreError> {
+  let path = empty_db_path("flat");
+
+  for dir in ["nodes", "edges", "props", "indexes"] {
+    std::fs::create_dir_all(path.join(dir))?;
+  }
+  std::fs::write(path.join("nodes/x"), "content")?;
+
+  let store = FsKvStore::open(&path)?;
+  assert_eq!(store.fetch_record(b"nodes/x")?, b"content");
+  assert!(!path.join("nodes").exists());
+  assert!(path.join("HEAD").exists());
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn only_one_process_can_write() -> Result<(), FileStoreError> {
+  let path = empty_db_path("one_writer");
+
+  let writer = FsKvStore::init(&path)?;
+  match FsKvStore::open_locked(&path, LockMode::Exclusive, Some(Duration::from_millis(50))) {
+    Err(FileStoreError::Locked) => (),
+    _ => panic!("should fail because there is already a writer"),
+  };
+
+  // readers can still access the database
+  let reader1 = FsKvStore::open_locked(&path, LockMode::Shared, None)?;
+  let mut reader2 = FsKvStore::open_locked(&path, LockMode::Shared, None)?;
+  match reader2.store_record(b"nodes/x", b"content") {
+    Err(FileStoreError::ReadOnly) => (),
+    _ => panic!("should fail because readers can not write"),
+  };
+
+  drop(writer);
+  drop(reader1);
+  drop(reader2);
+  FsKvStore::open(&path)?;
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn writers_wait_for_readers_of_the_old_copy() -> Result<(), FileStoreError> {
+  let path = empty_db_path("wait_for_readers");
+
+  let mut writer = FsKvStore::init(&path)?;
+  writer.store_record(b"nodes/x", b"old")?;
+  drop(writer);
+  let mut writer = FsKvStore::open_locked(&path, LockMode::Exclusive, Some(Duration::from_millis(50)))?;
+
+  let reader = FsKvStore::open_locked(&path, LockMode::Shared, None)?;
+  let mut scan = reader.scan(ScanRange::Prefix(b"nodes/"), ScanDirection::Forward)?;
+  // the first change goes to the copy nobody reads
+  writer.store_record(b"nodes/x", b"new")?;
+  // the next change would overwrite the data the scan still uses
+  match writer.store_record(b"nodes/x", b"newer") {
+    Err(FileStoreError::Locked) => (),
+    _ => panic!("should fail because the copy is still read"),
+  };
+  assert_eq!(scan.next().transpose()?, Some((b"nodes/x".to_vec(), b"old".to_vec())));
+  assert_eq!(writer.fetch_record(b"nodes/x")?, b"new");
+
+  drop(scan);
+  writer.store_record(b"nodes/x", b"newer")?;
+  assert_eq!(writer.fetch_record(b"nodes/x")?, b"newer");
+
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+#[test]
+fn long_lived_readers_follow_the_commits() -> Result<(), FileStoreError> {
+  let path = empty_db_path("long_lived_reader");
+
+  let mut writer = FsKvStore::init(&path)?;
+  writer.store_record(b"nodes/x", b"old")?;
+
+  let mut reader = FsKvStore::open_locked(&path, LockMode::Shared, None)?;
+  assert_eq!(reader.fetch_record(b"nodes/x")?, b"old");
+  // an idle reader does not keep the writer from committing twice
+  writer.store_record(b"nodes/x", b"new")?;
+  writer.store_record(b"nodes/x", b"newer")?;
+  assert_eq!(reader.fetch_record(b"nodes/x")?, b"newer");
+  writer.store_record(b"nodes/y", b"new")?;
+  assert!(reader.exists(b"nodes/y")?);
+
+  match reader.begin_transaction() {
+    Err(FileStoreError::ReadOnly) => (),
+    _ => panic!("should fail because readers can not write"),
+  };
+
+  drop(reader);
+  Ok(std::fs::remove_dir_all(&path)?)
+}
+
+fn empty_db_path(name: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("gravitydb-test-{}-{}", name, std::process::id()));
+  if path.exists() {
+    std::fs::remove_dir_all(&path).unwrap();
+  }
+  path
+}
+
 fn remove_file(path: &VfsPath) -> String {
   let content = path.read_to_string().expect("could not read out file");
   path.remove_file().unwrap();
@@ -184,6 +526,11 @@ const PROPERTY_EMPTY : &[u8] = "".as_bytes();
 const PROPERTY_EMPTY_ID: &str = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
 const PROPERTY_SIMPLE : &[u8] = "simple text property".as_bytes();
 const PROPERTY_SIMPLE_ID: &str = "4637D294486C315FC8D6C2F11742CBA4958CCB3F083656808C2B257D954DE631";
+const PROPERTY_JOHN_DOE : &[u8] = r#"{"Person":{"name":"John","surname":"Doe"}}"#.as_bytes();
+const PROPERTY_JOHN_DOE_ID: &str = "63AF565972F347A69CD5D3F4011CA40F70B2C474D3B66DE610675173DA76E7DC";
+const PROPERTY_JANE_DOE : &[u8] = r#"{"Person":{"name":"Jane","surname":"Doe"}}"#.as_bytes();
+const SURNAME_PATH_ID: &str = "98A0E50F672AF186AD5069AB18D1A78A5C47F71B1AFD30DC4B2463163CB59D66";
+const DOE_VALUE_ID: &str = "C827A65F67BE22B37B84EC305E59B3BB529A7F3F0F038FCE5F7AC69572ED2078";
 const EDGE1_ID : &str = "0B49457674D1B570400E6EC9E4B78F9C2C9B0721BA7C315BD0811E3059C3BBBA";
 const EDGE_N1_TO_SELF_ID : &str = "7622305FED0A357AF8AAE5ACC4110B8CAD7BDF2D67CAEA195BCDA0889A20FB8A";
 
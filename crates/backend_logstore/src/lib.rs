@@ -0,0 +1,535 @@
+use gravitydb::{KVStore, KeyIterator, RecordIterator, ScanDirection, ScanRange, TransactionalKVStore};
+use sha2::Digest;
+use std::collections::btree_map::Range;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+
+/// A key value store in a single append only file
+///
+/// Every change is appended to the log as a new entry, nothing is ever
+/// changed in place. A sorted index in memory knows where the current
+/// value of every key lies in the log. It is built by replaying the log
+/// when the store is opened.
+///
+/// The log starts with `MAGIC` followed by the entries. An entry
+/// consists of
+///
+/// * its kind (one byte, see `Kind`),
+/// * the length of the key and the length of the value (four bytes
+///   little endian each),
+/// * the key and the value and
+/// * the first four bytes of the sha256 hash of everything before.
+///
+/// The changes of a transaction are followed by a commit entry. Entries
+/// without a commit (e.g. after a crash in the middle of a transaction)
+/// are cut off when the log is replayed, so only complete transactions
+/// survive.
+///
+/// Overwritten and deleted values still take up space in the log until
+/// it is rewritten with `compact`. Only one process at a time can use
+/// the log.
+pub struct LogKvStore {
+  log: Mutex<Box<dyn Log>>,
+  /// the location of the log on disk (`None` if the store lives in
+  /// memory)
+  path: Option<PathBuf>,
+  /// where the current value of every record lies in the log
+  index: BTreeMap<Vec<u8>, Location>,
+  /// the length of the log
+  end: u64,
+  transaction: Option<Transaction>,
+}
+
+/// The kind of an entry in the log
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Kind {
+  /// a record is stored (or overwritten)
+  Put = 1,
+  /// a record is deleted
+  Delete = 2,
+  /// all changes before belong to a complete transaction
+  Commit = 3,
+}
+
+impl Kind {
+  fn from_byte(byte: u8) -> Option<Self> {
+    match byte {
+      1 => Some(Kind::Put),
+      2 => Some(Kind::Delete),
+      3 => Some(Kind::Commit),
+      _ => None,
+    }
+  }
+}
+
+/// Where a value lies in the log
+#[derive(Clone, Copy, Debug)]
+struct Location {
+  offset: u64,
+  len: u32,
+}
+
+struct Entry {
+  kind: Kind,
+  key: Vec<u8>,
+  value: Location,
+  /// the offset of the next entry
+  end: u64,
+}
+
+/// The records changed in a running transaction
+struct Transaction {
+  /// the length of the log before the transaction started
+  start: u64,
+  /// the previous locations of all changed records (`None` if the
+  /// record did not exist before)
+  journal: BTreeMap<Vec<u8>, Option<Location>>,
+}
+
+/// The storage of the log (a file or a buffer in memory)
+trait Log: Read + Write + Seek + Send {
+  /// cut off the log at `len`
+  fn truncate(&mut self, len: u64) -> std::io::Result<()>;
+  /// make sure everything written is on disk
+  fn sync(&mut self) -> std::io::Result<()>;
+}
+
+impl Log for File {
+  fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+    self.set_len(len)
+  }
+
+  fn sync(&mut self) -> std::io::Result<()> {
+    self.sync_data()
+  }
+}
+
+impl Log for Cursor<Vec<u8>> {
+  fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+    self.get_mut().truncate(len as usize);
+    Ok(())
+  }
+
+  fn sync(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+const MAGIC: &[u8] = b"gravitydb-log-1\n";
+const HEADER_LEN: u64 = 9;
+const CHECKSUM_LEN: u64 = 4;
+
+impl KVStore<LogStoreError> for LogKvStore
+{
+  /// There are no buckets in the log, keys are just sorted
+  fn create_bucket(&mut self, _key: &[u8]) -> Result<(), LogStoreError> {
+    Ok(())
+  }
+
+  fn delete_record(&mut self, key: &[u8]) -> Result<(), LogStoreError> {
+    if !self.index.contains_key(key) {
+      return Ok(());
+    }
+    self.autocommit(|store| store.change(key, None))
+  }
+
+  fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), LogStoreError> {
+    self.autocommit(|store| store.change(key, Some(value)))
+  }
+
+  fn fetch_record(&self, key: &[u8]) -> Result<Vec<u8>, LogStoreError> {
+    match self.index.get(key) {
+      Some(location) => self.read_value(*location),
+      None => Err(LogStoreError::Missing(String::from_utf8_lossy(key).to_string())),
+    }
+  }
+
+  fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, LogStoreError> {
+    Ok(self.range(ScanRange::Between(from, to))
+      .map(|(key, _)| key.clone())
+      .collect())
+  }
+
+  /// The keys come from the index, the log is not read at all
+  fn scan_keys<'a>(&'a self, from: &[u8], to: &[u8]) -> Result<KeyIterator<'a, LogStoreError>, LogStoreError>
+  where
+    LogStoreError: 'a,
+  {
+    Ok(Box::new(self.range(ScanRange::Between(from, to)).map(|(key, _)| Ok(key.clone()))))
+  }
+
+  fn scan<'a>(&'a self, range: ScanRange<'_>, direction: ScanDirection) -> Result<RecordIterator<'a, LogStoreError>, LogStoreError>
+  where
+    LogStoreError: 'a,
+  {
+    let records = self.range(range);
+    let records: Box<dyn Iterator<Item = (&Vec<u8>, &Location)>> = match direction {
+      ScanDirection::Forward => Box::new(records),
+      ScanDirection::Reverse => Box::new(records.rev()),
+    };
+    Ok(Box::new(records.map(|(key, location)| Ok((key.clone(), self.read_value(*location)?)))))
+  }
+
+  fn exists(&self, key: &[u8]) -> Result<bool, LogStoreError> {
+    Ok(self.index.contains_key(key))
+  }
+}
+
+impl TransactionalKVStore<LogStoreError> for LogKvStore
+{
+  fn begin_transaction(&mut self) -> Result<(), LogStoreError> {
+    if self.transaction.is_some() {
+      return Err(LogStoreError::TransactionRunning);
+    }
+    self.transaction = Some(Transaction {
+      start: self.end,
+      journal: BTreeMap::new(),
+    });
+    Ok(())
+  }
+
+  fn commit_transaction(&mut self) -> Result<(), LogStoreError> {
+    let transaction = self.transaction.take().ok_or(LogStoreError::NoTransaction)?;
+    if self.end > transaction.start {
+      self.append(Kind::Commit, b"", b"")?;
+      self.log_mut().sync()?;
+    }
+    Ok(())
+  }
+
+  fn rollback_transaction(&mut self) -> Result<(), LogStoreError> {
+    let transaction = self.transaction.take().ok_or(LogStoreError::NoTransaction)?;
+    for (key, location) in transaction.journal.into_iter() {
+      match location {
+        Some(location) => self.index.insert(key, location),
+        None => self.index.remove(&key),
+      };
+    }
+    self.log_mut().truncate(transaction.start)?;
+    self.end = transaction.start;
+    Ok(())
+  }
+}
+
+impl LogKvStore {
+  /// Create a new database file
+  pub fn init(path: &Path) -> Result<Self, LogStoreError> {
+    let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+    lock(&file)?;
+    file.write_all(MAGIC)?;
+    file.sync_all()?;
+    Self::replay(Box::new(file), Some(path.to_path_buf()))
+  }
+
+  /// Open an existing database file
+  ///
+  /// Changes which were never committed (e.g. because the process
+  /// crashed) are thrown away.
+  pub fn open(path: &Path) -> Result<Self, LogStoreError> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    lock(&file)?;
+    Self::replay(Box::new(file), Some(path.to_path_buf()))
+  }
+
+  /// Create a database which lives in memory only
+  ///
+  /// Use this for testing purposes or if your data does not need to be
+  /// persisted permanently.
+  pub fn from_memory() -> Result<Self, LogStoreError> {
+    Self::replay(Box::new(Cursor::new(MAGIC.to_vec())), None)
+  }
+
+  /// Get the current value of every record
+  pub fn records(&self) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, LogStoreError> {
+    self.index.iter()
+      .map(|(key, location)| Ok((key.clone(), self.read_value(*location)?)))
+      .collect()
+  }
+
+  /// The length of the log in bytes
+  pub fn log_size(&self) -> u64 {
+    self.end
+  }
+
+  /// Rewrite the log with the current values only
+  ///
+  /// The new log is written next to the old one and replaces it as a
+  /// whole, so a crash during the compaction leaves the old log intact.
+  pub fn compact(&mut self) -> Result<(), LogStoreError> {
+    if self.transaction.is_some() {
+      return Err(LogStoreError::TransactionRunning);
+    }
+
+    let mut log: Box<dyn Log> = match self.path.as_ref() {
+      Some(path) => {
+        let file = OpenOptions::new()
+          .read(true)
+          .write(true)
+          .create(true)
+          .truncate(true)
+          .open(compaction_path(path))?;
+        lock(&file)?;
+        Box::new(file)
+      }
+      None => Box::new(Cursor::new(Vec::new())),
+    };
+    log.write_all(MAGIC)?;
+
+    let mut index = BTreeMap::new();
+    let mut end = MAGIC.len() as u64;
+    for (key, location) in self.index.iter() {
+      let value = self.read_value(*location)?;
+      let entry = encode_entry(Kind::Put, key, &value)?;
+      log.write_all(&entry)?;
+      index.insert(key.clone(), value_location(end, key, &value));
+      end += entry.len() as u64;
+    }
+    let entry = encode_entry(Kind::Commit, b"", b"")?;
+    log.write_all(&entry)?;
+    end += entry.len() as u64;
+    log.sync()?;
+
+    if let Some(path) = self.path.as_ref() {
+      std::fs::rename(compaction_path(path), path)?;
+      // directories can only be synced on unix like systems
+      if cfg!(unix) {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+          File::open(dir)?.sync_all()?;
+        }
+      }
+    }
+
+    self.log = Mutex::new(log);
+    self.index = index;
+    self.end = end;
+    Ok(())
+  }
+
+  /// Build the index by reading all entries of the log
+  fn replay(mut log: Box<dyn Log>, path: Option<PathBuf>) -> Result<Self, LogStoreError> {
+    let len = log.seek(SeekFrom::End(0))?;
+    log.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(&mut log);
+
+    if read_up_to(&mut reader, MAGIC.len() as u64)? != MAGIC {
+      return Err(LogStoreError::MalformedDB);
+    }
+
+    let mut index = BTreeMap::new();
+    let mut uncommitted = Vec::new();
+    let mut end = MAGIC.len() as u64;
+    let mut position = end;
+    while let Some(entry) = read_entry(&mut reader, position)? {
+      position = entry.end;
+      match entry.kind {
+        Kind::Put => uncommitted.push((entry.key, Some(entry.value))),
+        Kind::Delete => uncommitted.push((entry.key, None)),
+        Kind::Commit => {
+          for (key, location) in uncommitted.drain(..) {
+            match location {
+              Some(location) => index.insert(key, location),
+              None => index.remove(&key),
+            };
+          }
+          end = position;
+        }
+      }
+    }
+    drop(reader);
+
+    // cut off everything after the last commit
+    if end < len {
+      log.truncate(end)?;
+      log.sync()?;
+    }
+
+    Ok(LogKvStore {
+      log: Mutex::new(log),
+      path,
+      index,
+      end,
+      transaction: None,
+    })
+  }
+
+  /// run a single change in its own transaction if none is running
+  fn autocommit<F>(&mut self, f: F) -> Result<(), LogStoreError>
+  where
+    F: FnOnce(&mut Self) -> Result<(), LogStoreError>,
+  {
+    if self.transaction.is_some() {
+      return f(self);
+    }
+
+    self.begin_transaction()?;
+    match f(self) {
+      Ok(()) => self.commit_transaction(),
+      Err(e) => {
+        self.rollback_transaction()?;
+        Err(e)
+      }
+    }
+  }
+
+  /// Write a change to the log and remember the previous location of
+  /// the record in the running transaction
+  fn change(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(), LogStoreError> {
+    let previous = match value {
+      Some(value) => {
+        let location = self.append(Kind::Put, key, value)?;
+        self.index.insert(key.to_vec(), location)
+      }
+      None => {
+        self.append(Kind::Delete, key, b"")?;
+        self.index.remove(key)
+      }
+    };
+    if let Some(transaction) = self.transaction.as_mut() {
+      transaction.journal.entry(key.to_vec()).or_insert(previous);
+    }
+    Ok(())
+  }
+
+  /// Append an entry to the log and get the location of its value
+  fn append(&mut self, kind: Kind, key: &[u8], value: &[u8]) -> Result<Location, LogStoreError> {
+    let entry = encode_entry(kind, key, value)?;
+    let end = self.end;
+    let log = self.log_mut();
+    log.seek(SeekFrom::Start(end))?;
+    log.write_all(&entry)?;
+    self.end += entry.len() as u64;
+    Ok(value_location(end, key, value))
+  }
+
+  fn read_value(&self, location: Location) -> Result<Vec<u8>, LogStoreError> {
+    let mut log = self.log();
+    log.seek(SeekFrom::Start(location.offset))?;
+    let mut value = vec![0; location.len as usize];
+    log.read_exact(&mut value)?;
+    Ok(value)
+  }
+
+  /// All records in a range of keys
+  ///
+  /// An empty end of the range reaches till the first change of the
+  /// start (see `list_records`).
+  fn range(&self, range: ScanRange<'_>) -> Range<'_, Vec<u8>, Location> {
+    self.index.range(range.bounds())
+  }
+
+  fn log(&self) -> MutexGuard<'_, Box<dyn Log>> {
+    self.log.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  fn log_mut(&mut self) -> &mut Box<dyn Log> {
+    self.log.get_mut().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// Make sure only one process at a time uses the log
+fn lock(file: &File) -> Result<(), LogStoreError> {
+  match file.try_lock() {
+    Ok(()) => Ok(()),
+    Err(TryLockError::WouldBlock) => Err(LogStoreError::Locked),
+    Err(TryLockError::Error(e)) => Err(e.into()),
+  }
+}
+
+/// Where a log is written to during the compaction
+fn compaction_path(path: &Path) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(".compact");
+  PathBuf::from(name)
+}
+
+fn encode_entry(kind: Kind, key: &[u8], value: &[u8]) -> Result<Vec<u8>, LogStoreError> {
+  let key_len = u32::try_from(key.len()).map_err(|_| LogStoreError::TooLarge)?;
+  let value_len = u32::try_from(value.len()).map_err(|_| LogStoreError::TooLarge)?;
+
+  let mut entry = vec![kind as u8];
+  entry.extend(key_len.to_le_bytes());
+  entry.extend(value_len.to_le_bytes());
+  entry.extend(key);
+  entry.extend(value);
+  let checksum = checksum(&entry);
+  entry.extend(checksum);
+  Ok(entry)
+}
+
+/// The location of the value of an entry starting at `offset`
+fn value_location(offset: u64, key: &[u8], value: &[u8]) -> Location {
+  Location {
+    offset: offset + HEADER_LEN + key.len() as u64,
+    len: value.len() as u32,
+  }
+}
+
+/// Read the entry starting at `offset`
+///
+/// Returns `None` at the end of the log or if the entry is incomplete or
+/// damaged (e.g. because the process crashed while writing it).
+fn read_entry(reader: &mut impl Read, offset: u64) -> Result<Option<Entry>, LogStoreError> {
+  let header = read_up_to(reader, HEADER_LEN)?;
+  if header.len() as u64 != HEADER_LEN {
+    return Ok(None);
+  }
+  let Some(kind) = Kind::from_byte(header[0]) else {
+    return Ok(None);
+  };
+  let key_len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as u64;
+  let value_len = u32::from_le_bytes([header[5], header[6], header[7], header[8]]);
+
+  let body_len = key_len + value_len as u64 + CHECKSUM_LEN;
+  let body = read_up_to(reader, body_len)?;
+  if body.len() as u64 != body_len {
+    return Ok(None);
+  }
+  let (content, expected) = body.split_at(body.len() - CHECKSUM_LEN as usize);
+  if checksum(&[header.as_slice(), content].concat()) != expected {
+    return Ok(None);
+  }
+
+  Ok(Some(Entry {
+    kind,
+    key: content[..key_len as usize].to_vec(),
+    value: Location {
+      offset: offset + HEADER_LEN + key_len,
+      len: value_len,
+    },
+    end: offset + HEADER_LEN + body_len,
+  }))
+}
+
+/// Read `len` bytes or less if the log ends before
+fn read_up_to(reader: &mut impl Read, len: u64) -> Result<Vec<u8>, LogStoreError> {
+  let mut data = Vec::new();
+  reader.take(len).read_to_end(&mut data)?;
+  Ok(data)
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN as usize] {
+  let hash = sha2::Sha256::digest(data);
+  [hash[0], hash[1], hash[2], hash[3]]
+}
+
+#[derive(Error, Debug)]
+pub enum LogStoreError {
+  #[error("the file is no gravitydb log")]
+  MalformedDB,
+  #[error("io error")]
+  Io { #[from] source: std::io::Error },
+  #[error("the record {0} could not be found")]
+  Missing(String),
+  #[error("keys and values can not be longer than 4 GiB")]
+  TooLarge,
+  #[error("there is already a running transaction")]
+  TransactionRunning,
+  #[error("there is no running transaction")]
+  NoTransaction,
+  #[error("the database is locked by another process")]
+  Locked,
+}
@@ -0,0 +1,112 @@
+use gravitydb_logstore::{LogKvStore, LogStoreError};
+
+#[test]
+fn trivial_queries() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::trivial_queries(kv)
+}
+
+#[test]
+fn alexander_ingredients() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::alexander_ingredients(kv)
+}
+
+#[test]
+fn which_cocktails_include_gin() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::which_cocktails_include_gin(kv)
+}
+
+#[test]
+fn cocktail_statistic() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::cocktail_statistic(kv)
+}
+
+#[test]
+fn cocktail_statistic_in_one_query() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::cocktail_statistic_in_one_query(kv)
+}
+
+#[test]
+fn query_properties() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::query_properties(kv)
+}
+
+#[test]
+fn cocktails_in_alphabetical_range() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::cocktails_in_alphabetical_range(kv)
+}
+
+#[test]
+fn typed_query_results() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::typed_query_results(kv)
+}
+
+#[test]
+fn remember_cocktails_on_the_way() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::remember_cocktails_on_the_way(kv)
+}
+
+#[test]
+fn cocktails_related_by_ingredients() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::cocktails_related_by_ingredients(kv)
+}
+
+#[test]
+fn shortest_way_between_cocktails() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::shortest_way_between_cocktails(kv)
+}
+
+#[test]
+fn graph_algorithms_on_cocktails() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::graph_algorithms_on_cocktails(kv)
+}
+
+#[test]
+fn analyse_cocktail_graph() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::analyse_cocktail_graph(kv)
+}
+
+#[test]
+fn hulls_of_cocktail_results() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::hulls_of_cocktail_results(kv)
+}
+
+#[test]
+fn page_through_cocktails() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::page_through_cocktails(kv)
+}
+
+#[test]
+fn explain_cocktail_queries() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::explain_cocktail_queries(kv)
+}
+
+#[test]
+fn stream_cocktails() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::stream_cocktails(kv)
+}
+
+#[test]
+fn filter_cocktails_by_script() -> Result<(), Error> {
+  let kv = LogKvStore::from_memory().expect("Could not create kv store");
+  gravitydb_test_utils::filter_cocktails_by_script(kv)
+}
+
+type Error = gravitydb::kv_graph_store::Error<LogStoreError>;
+
@@ -0,0 +1,313 @@
+use gravitydb::{KVStore, KeyIterator, RecordIterator, ScanDirection, ScanRange, TransactionalKVStore};
+use rusqlite::{Connection, OptionalExtension, Row};
+use std::collections::BTreeMap;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::path::Path;
+use thiserror::Error;
+
+/// A key value store in a SQLite database
+///
+/// All records live in a single table `records` with the key as primary
+/// key. SQLite compares blobs byte by byte, so the order of the table is
+/// the same as the order of the keys in every other backend and ranges
+/// of keys can be read from the index of the primary key.
+///
+/// Every change outside of a transaction is committed on its own.
+/// Transactions of the graph are mapped onto SQLite transactions.
+pub struct SqliteKvStore {
+  connection: Connection,
+}
+
+/// How many records are read at once while scanning
+const BATCH_SIZE: usize = 256;
+
+impl KVStore<SqliteStoreError> for SqliteKvStore
+{
+  /// There are no buckets in the table, keys are just sorted
+  fn create_bucket(&mut self, _key: &[u8]) -> Result<(), SqliteStoreError> {
+    Ok(())
+  }
+
+  fn delete_record(&mut self, key: &[u8]) -> Result<(), SqliteStoreError> {
+    self.connection.execute("DELETE FROM records WHERE key = ?1", [key])?;
+    Ok(())
+  }
+
+  fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), SqliteStoreError> {
+    self.connection.execute(
+      "INSERT OR REPLACE INTO records (key, value) VALUES (?1, ?2)",
+      [key, value],
+    )?;
+    Ok(())
+  }
+
+  fn fetch_record(&self, key: &[u8]) -> Result<Vec<u8>, SqliteStoreError> {
+    self.connection
+      .query_row("SELECT value FROM records WHERE key = ?1", [key], |row| row.get(0))
+      .optional()?
+      .ok_or_else(|| SqliteStoreError::Missing(String::from_utf8_lossy(key).to_string()))
+  }
+
+  fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, SqliteStoreError> {
+    let (from, to) = ScanRange::Between(from, to).bounds();
+    select(&self.connection, "key", &from, &to, ScanDirection::Forward, None, |row| row.get(0))
+  }
+
+  /// Only the keys are selected, batch by batch like in `scan`
+  fn scan_keys<'a>(&'a self, from: &[u8], to: &[u8]) -> Result<KeyIterator<'a, SqliteStoreError>, SqliteStoreError>
+  where
+    SqliteStoreError: 'a,
+  {
+    let (from, to) = ScanRange::Between(from, to).bounds();
+    Ok(Box::new(Scan::new(
+      &self.connection,
+      "key",
+      |row| row.get(0),
+      |key: &Vec<u8>| key.clone(),
+      (from, to),
+      ScanDirection::Forward,
+    )))
+  }
+
+  fn scan<'a>(&'a self, range: ScanRange<'_>, direction: ScanDirection) -> Result<RecordIterator<'a, SqliteStoreError>, SqliteStoreError>
+  where
+    SqliteStoreError: 'a,
+  {
+    Ok(Box::new(Scan::new(
+      &self.connection,
+      "key, value",
+      |row| Ok((row.get(0)?, row.get(1)?)),
+      |(key, _): &(Vec<u8>, Vec<u8>)| key.clone(),
+      range.bounds(),
+      direction,
+    )))
+  }
+
+  fn exists(&self, key: &[u8]) -> Result<bool, SqliteStoreError> {
+    Ok(self.connection
+      .query_row("SELECT 1 FROM records WHERE key = ?1", [key], |_| Ok(()))
+      .optional()?
+      .is_some())
+  }
+}
+
+impl TransactionalKVStore<SqliteStoreError> for SqliteKvStore
+{
+  fn begin_transaction(&mut self) -> Result<(), SqliteStoreError> {
+    if !self.connection.is_autocommit() {
+      return Err(SqliteStoreError::TransactionRunning);
+    }
+    self.connection.execute_batch("BEGIN IMMEDIATE")?;
+    Ok(())
+  }
+
+  fn commit_transaction(&mut self) -> Result<(), SqliteStoreError> {
+    if self.connection.is_autocommit() {
+      return Err(SqliteStoreError::NoTransaction);
+    }
+    self.connection.execute_batch("COMMIT")?;
+    Ok(())
+  }
+
+  fn rollback_transaction(&mut self) -> Result<(), SqliteStoreError> {
+    if self.connection.is_autocommit() {
+      return Err(SqliteStoreError::NoTransaction);
+    }
+    self.connection.execute_batch("ROLLBACK")?;
+    Ok(())
+  }
+}
+
+impl SqliteKvStore {
+  /// Open a database file (it is created if it does not exist yet)
+  pub fn open(path: &Path) -> Result<Self, SqliteStoreError> {
+    let connection = Connection::open(path)?;
+    // readers do not have to wait for a running transaction
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    Self::from_connection(connection)
+  }
+
+  /// Create a database which lives in memory only
+  ///
+  /// Use this for testing purposes or if your data does not need to be
+  /// persisted permanently.
+  pub fn from_memory() -> Result<Self, SqliteStoreError> {
+    Self::from_connection(Connection::open_in_memory()?)
+  }
+
+  /// Use an already opened connection (e.g. with special flags)
+  ///
+  /// The table for the records is created if it does not exist yet.
+  pub fn from_connection(connection: Connection) -> Result<Self, SqliteStoreError> {
+    connection.execute_batch(
+      "CREATE TABLE IF NOT EXISTS records (
+         key BLOB PRIMARY KEY NOT NULL,
+         value BLOB NOT NULL
+       ) WITHOUT ROWID",
+    )?;
+    Ok(SqliteKvStore { connection })
+  }
+
+  /// Get the current value of every record
+  pub fn records(&self) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, SqliteStoreError> {
+    let records = select(
+      &self.connection,
+      "key, value",
+      &Unbounded,
+      &Unbounded,
+      ScanDirection::Forward,
+      None,
+      |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(records.into_iter().collect())
+  }
+
+  /// The underlying connection (e.g. for backups or inspection)
+  pub fn connection(&self) -> &Connection {
+    &self.connection
+  }
+}
+
+/// Reads the rows of a range batch by batch
+///
+/// A statement borrows the connection and its rows borrow the statement,
+/// so the rows can not be handed out directly. Instead every batch
+/// continues after the last key of the one before.
+struct Scan<'a, T> {
+  connection: &'a Connection,
+  /// the selected columns, `row` turns them into an item
+  columns: &'static str,
+  row: fn(&Row<'_>) -> rusqlite::Result<T>,
+  /// the key of an item
+  key: fn(&T) -> Vec<u8>,
+  from: Bound<Vec<u8>>,
+  to: Bound<Vec<u8>>,
+  direction: ScanDirection,
+  batch: std::vec::IntoIter<T>,
+  done: bool,
+}
+
+impl<'a, T> Scan<'a, T> {
+  fn new(
+    connection: &'a Connection,
+    columns: &'static str,
+    row: fn(&Row<'_>) -> rusqlite::Result<T>,
+    key: fn(&T) -> Vec<u8>,
+    (from, to): (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    direction: ScanDirection,
+  ) -> Self {
+    Scan {
+      connection,
+      columns,
+      row,
+      key,
+      from,
+      to,
+      direction,
+      batch: Vec::new().into_iter(),
+      done: false,
+    }
+  }
+
+  fn fetch(&mut self) -> Result<(), SqliteStoreError> {
+    let rows: Vec<T> = select(
+      self.connection,
+      self.columns,
+      &self.from,
+      &self.to,
+      self.direction,
+      Some(BATCH_SIZE),
+      self.row,
+    )?;
+    if rows.len() < BATCH_SIZE {
+      self.done = true;
+    }
+    if let Some(last) = rows.last() {
+      match self.direction {
+        ScanDirection::Forward => self.from = Excluded((self.key)(last)),
+        ScanDirection::Reverse => self.to = Excluded((self.key)(last)),
+      }
+    }
+    self.batch = rows.into_iter();
+    Ok(())
+  }
+}
+
+impl<T> Iterator for Scan<'_, T> {
+  type Item = Result<T, SqliteStoreError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if let Some(record) = self.batch.next() {
+      return Some(Ok(record));
+    }
+    if self.done {
+      return None;
+    }
+    match self.fetch() {
+      Ok(()) => self.batch.next().map(Ok),
+      Err(e) => {
+        self.done = true;
+        Some(Err(e))
+      }
+    }
+  }
+}
+
+/// Select `columns` of the records between `from` and `to`
+fn select<T, F>(
+  connection: &Connection,
+  columns: &str,
+  from: &Bound<Vec<u8>>,
+  to: &Bound<Vec<u8>>,
+  direction: ScanDirection,
+  limit: Option<usize>,
+  row: F,
+) -> Result<Vec<T>, SqliteStoreError>
+where
+  F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
+{
+  let mut conditions = Vec::new();
+  let mut params = Vec::new();
+  for (bound, included, excluded) in [(from, "key >= ?", "key > ?"), (to, "key <= ?", "key < ?")] {
+    match bound {
+      Included(key) => {
+        conditions.push(included);
+        params.push(key);
+      }
+      Excluded(key) => {
+        conditions.push(excluded);
+        params.push(key);
+      }
+      Unbounded => {}
+    }
+  }
+
+  let mut sql = format!("SELECT {} FROM records", columns);
+  if !conditions.is_empty() {
+    sql += " WHERE ";
+    sql += &conditions.join(" AND ");
+  }
+  sql += match direction {
+    ScanDirection::Forward => " ORDER BY key ASC",
+    ScanDirection::Reverse => " ORDER BY key DESC",
+  };
+  if let Some(limit) = limit {
+    sql += &format!(" LIMIT {}", limit);
+  }
+
+  let mut statement = connection.prepare_cached(&sql)?;
+  let rows = statement.query_map(rusqlite::params_from_iter(params), row)?;
+  Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[derive(Error, Debug)]
+pub enum SqliteStoreError {
+  #[error("sqlite error")]
+  Sqlite { #[from] source: rusqlite::Error },
+  #[error("the record {0} could not be found")]
+  Missing(String),
+  #[error("there is already a running transaction")]
+  TransactionRunning,
+  #[error("there is no running transaction")]
+  NoTransaction,
+}
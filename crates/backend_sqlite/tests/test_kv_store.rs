@@ -0,0 +1,406 @@
+use gravitydb::*;
+use gravitydb::kv_graph_store::Uuid;
+use gravitydb_sqlite::{SqliteKvStore, SqliteStoreError};
+use pretty_assertions::assert_eq;
+use uuid::uuid;
+use std::path::PathBuf;
+
+#[test]
+fn create_a_node_in_empty_store() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  let mut store = get_kv_store(graph);
+  let node_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID
+    )
+  );
+  check_string(
+    store.remove(&format!("props/{}", PROPERTY_EMPTY_ID)),
+    ""
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID)),
+    &node_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn cannot_create_a_node_twice() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  // can not create an identical node
+  match graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec()) {
+    Err(Error::NodeExists(msg)) => assert_eq!(msg, "nodes/a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+    _ => panic!("should fail because node exists"),
+  };
+
+  // can not create a node with the same id but changed content
+  match graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_SIMPLE.to_vec()) {
+    Err(Error::NodeExists(msg)) => assert_eq!(msg, "nodes/a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+    _ => panic!("should fail because node exists"),
+  };
+
+  Ok(())
+}
+
+#[test]
+fn nodes_can_be_connected_with_themselfes() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  let mut store = get_kv_store(graph);
+  let node_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[\"{}\"],\"outgoing\":[\"{}\"]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID,
+        EDGE_N1_TO_SELF_ID,
+        EDGE_N1_TO_SELF_ID,
+    )
+  );
+  check_string(
+    store.remove(&format!("props/{}", PROPERTY_EMPTY_ID)),
+    ""
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID)),
+    &node_path
+  );
+
+  let edge1_path = format!("edges/{}", EDGE_N1_TO_SELF_ID);
+  check_string(
+    store.remove(&edge1_path),
+    &format!(
+      "{{\"properties\":\"{}\",\"n1\":\"{}\",\"n2\":\"{}\"}}",
+        PROPERTY_EMPTY_ID,
+        NODE1_UUID,
+        NODE1_UUID,
+    )
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/edges_{}", PROPERTY_EMPTY_ID, EDGE_N1_TO_SELF_ID)),
+    &edge1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn create_two_nodes_with_connection() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  let mut store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node1_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[\"{}\"]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID,
+        EDGE1_ID,
+    )
+  );
+  check_string(
+    store.remove(&format!("props/{}", PROPERTY_EMPTY_ID)),
+    ""
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID)),
+    &node1_path
+  );
+
+  let node2_path = format!("nodes/{}", NODE2_UUID);
+  check_string(
+    store.remove(&node2_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[\"{}\"],\"outgoing\":[]}}",
+        NODE2_UUID,
+        PROPERTY_SIMPLE_ID,
+        EDGE1_ID,
+    )
+  );
+  check_string(
+    store.remove(&format!("props/{}", PROPERTY_SIMPLE_ID)),
+    "simple text property"
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/nodes_{}", PROPERTY_SIMPLE_ID, NODE2_UUID)),
+    &node2_path
+  );
+
+  let edge1_path = format!("edges/{}", EDGE1_ID);
+  check_string(
+    store.remove(&edge1_path),
+    &format!(
+      "{{\"properties\":\"{}\",\"n1\":\"{}\",\"n2\":\"{}\"}}",
+        PROPERTY_EMPTY_ID,
+        NODE1_UUID,
+        NODE2_UUID,
+    )
+  );
+  check_string(
+    store.remove(&format!("indexes/{}/edges_{}", PROPERTY_EMPTY_ID, EDGE1_ID)),
+    &edge1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn failed_operations_leave_the_store_unchanged() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  // the second node does not exist, so the edge can not be created
+  match graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec()) {
+    Err(Error::KV(_)) => (),
+    _ => panic!("should fail because the second node does not exist"),
+  };
+
+  let mut store = get_kv_store(graph);
+  let node_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID
+    )
+  );
+  check_string(
+    store.remove(format!("props/{}", PROPERTY_EMPTY_ID).as_str()),
+    ""
+  );
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID).as_str()),
+    &node_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn nodes_with_edges_are_only_deleted_on_request() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  match graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject) {
+    Err(Error::NodeHasEdges(msg)) => assert_eq!(msg, NODE2_UUID),
+    _ => panic!("should fail because the node has an edge"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Cascade)?;
+
+  let mut store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node1_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID,
+    )
+  );
+  check_string(
+    store.remove(format!("props/{}", PROPERTY_EMPTY_ID).as_str()),
+    ""
+  );
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID).as_str()),
+    &node1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn properties_can_be_found_by_indexed_json_fields() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_JOHN_DOE.to_vec())?;
+  // properties which exist before the index is declared are added
+  graph.create_json_index("/Person/surname")?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_JANE_DOE.to_vec())?;
+
+  let q = ql::PropertyQuery::from_json_field("/Person/surname", "Doe")
+    .referencing_vertices();
+  let result = graph.query(q)?;
+  assert_eq!(result.vertices.len(), 2);
+
+  match graph.query(ql::PropertyQuery::from_json_field("/Person/name", "John")) {
+    Err(Error::MissingIndex(path)) => assert_eq!(path, "/Person/name"),
+    _ => panic!("should fail because the field has no index"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject)?;
+  let result = graph.query(ql::PropertyQuery::from_json_field("/Person/surname", "Doe"))?;
+  assert_eq!(result.properties, std::collections::HashSet::from([PROPERTY_JOHN_DOE_ID.to_string()]));
+
+  let mut store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  let props_path = format!("props/{}", PROPERTY_JOHN_DOE_ID);
+  check_string(
+    store.remove(format!("indexes/json/{}/{}_{}", SURNAME_PATH_ID, DOE_VALUE_ID, PROPERTY_JOHN_DOE_ID).as_str()),
+    &props_path
+  );
+  check_string(store.remove("indexes/json/paths"), r#"["/Person/surname"]"#);
+  check_string(
+    store.remove(&node1_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_JOHN_DOE_ID,
+    )
+  );
+  check_string(store.remove(&props_path), std::str::from_utf8(PROPERTY_JOHN_DOE).unwrap());
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_JOHN_DOE_ID, NODE1_UUID).as_str()),
+    &node1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn records_can_be_scanned() -> Result<(), SqliteStoreError> {
+  gravitydb_test_utils::scan_records(SqliteKvStore::from_memory()?)
+}
+
+#[test]
+fn keys_can_be_arbitrary_bytes() -> Result<(), SqliteStoreError> {
+  gravitydb_test_utils::binary_keys(SqliteKvStore::from_memory()?)
+}
+
+#[test]
+fn scans_continue_over_several_batches() -> Result<(), SqliteStoreError> {
+  let mut store = SqliteKvStore::from_memory()?;
+  store.begin_transaction()?;
+  for i in 0..1000u32 {
+    store.store_record(&i.to_be_bytes(), b"")?;
+  }
+  store.commit_transaction()?;
+
+  let keys = store.scan(ScanRange::Prefix(b""), ScanDirection::Forward)?
+    .map(|record| Ok(record?.0))
+    .collect::<Result<Vec<_>, SqliteStoreError>>()?;
+  assert_eq!(keys, (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>());
+
+  let keys = store.scan(ScanRange::Between(&300u32.to_be_bytes(), &700u32.to_be_bytes()), ScanDirection::Reverse)?
+    .map(|record| Ok(record?.0))
+    .collect::<Result<Vec<_>, SqliteStoreError>>()?;
+  assert_eq!(keys, (300..=700u32).rev().map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>());
+  Ok(())
+}
+
+#[test]
+fn committed_changes_survive_reopening() -> Result<(), SqliteStoreError> {
+  let path = empty_db_path("reopen");
+
+  let mut store = SqliteKvStore::open(&path)?;
+  store.begin_transaction()?;
+  store.store_record(b"nodes/x", b"content")?;
+  store.commit_transaction()?;
+  store.delete_record(b"nodes/x")?;
+  store.store_record(b"nodes/y", b"content")?;
+  drop(store);
+
+  let store = SqliteKvStore::open(&path)?;
+  assert!(!store.exists(b"nodes/x")?);
+  assert_eq!(store.fetch_record(b"nodes/y")?, b"content");
+  drop(store);
+
+  std::fs::remove_file(&path).expect("could not remove the database");
+  Ok(())
+}
+
+#[test]
+fn rolled_back_changes_are_thrown_away() -> Result<(), SqliteStoreError> {
+  let mut store = SqliteKvStore::from_memory()?;
+  store.store_record(b"nodes/x", b"old")?;
+
+  store.begin_transaction()?;
+  store.store_record(b"nodes/x", b"new")?;
+  store.store_record(b"nodes/y", b"new")?;
+  store.delete_record(b"nodes/x")?;
+  assert!(matches!(store.begin_transaction(), Err(SqliteStoreError::TransactionRunning)));
+  store.rollback_transaction()?;
+
+  assert!(matches!(store.commit_transaction(), Err(SqliteStoreError::NoTransaction)));
+  assert_eq!(store.records()?, [(b"nodes/x".to_vec(), b"old".to_vec())].into());
+  Ok(())
+}
+
+fn empty_db_path(name: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("gravitydb-sqlite-test-{}-{}", name, std::process::id()));
+  if path.exists() {
+    std::fs::remove_file(&path).unwrap();
+  }
+  path
+}
+
+fn check_string(left: Option<Vec<u8>>, right: &str) {
+  let left = left.unwrap();
+  let formatted = String::from_utf8(left).expect("should be an utf8 string");
+
+  assert_eq!(formatted, right.to_string())
+}
+
+const NODE1_UUID : &str = "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8";
+const NODE2_UUID : &str = "e1e2e3e4-f1f2-a1a2-b1b2-b3b4b5b6b7b8";
+const PROPERTY_EMPTY : &[u8] = "".as_bytes();
+const PROPERTY_EMPTY_ID: &str = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+const PROPERTY_SIMPLE : &[u8] = "simple text property".as_bytes();
+const PROPERTY_SIMPLE_ID: &str = "4637D294486C315FC8D6C2F11742CBA4958CCB3F083656808C2B257D954DE631";
+const PROPERTY_JOHN_DOE : &[u8] = r#"{"Person":{"name":"John","surname":"Doe"}}"#.as_bytes();
+const PROPERTY_JOHN_DOE_ID: &str = "63AF565972F347A69CD5D3F4011CA40F70B2C474D3B66DE610675173DA76E7DC";
+const PROPERTY_JANE_DOE : &[u8] = r#"{"Person":{"name":"Jane","surname":"Doe"}}"#.as_bytes();
+const SURNAME_PATH_ID: &str = "98A0E50F672AF186AD5069AB18D1A78A5C47F71B1AFD30DC4B2463163CB59D66";
+const DOE_VALUE_ID: &str = "C827A65F67BE22B37B84EC305E59B3BB529A7F3F0F038FCE5F7AC69572ED2078";
+const EDGE1_ID : &str = "0B49457674D1B570400E6EC9E4B78F9C2C9B0721BA7C315BD0811E3059C3BBBA";
+const EDGE_N1_TO_SELF_ID : &str = "7622305FED0A357AF8AAE5ACC4110B8CAD7BDF2D67CAEA195BCDA0889A20FB8A";
+
+fn create_empty_graph() -> GStore {
+  let kv = SqliteKvStore::from_memory().expect("Could not create kv store");
+  kv_graph_store::KvGraphStore::from_kv(kv)
+}
+
+fn get_kv_store(graph: GStore) -> std::collections::BTreeMap<String, Vec<u8>> {
+  graph.into_kv()
+    .records()
+    .expect("could not read the records")
+    .into_iter()
+    .map(|(key, value)| (String::from_utf8(key).expect("keys are utf8"), value))
+    .collect()
+}
+
+type Error = kv_graph_store::Error<SqliteStoreError>;
+type GStore = kv_graph_store::KvGraphStore::<Vec<u8>, SqliteKvStore, SqliteStoreError>;
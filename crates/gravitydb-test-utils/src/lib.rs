@@ -6,7 +6,7 @@ use serde::{Serialize, Deserialize};
 
 pub fn trivial_queries<KV, E>(kv: KV) -> Result<(), Error<E>>
 where
-  KV: gravitydb::KVStore<E>,
+  KV: gravitydb::TransactionalKVStore<E>,
   E: Send,
 {
   use CocktailSchema::*;
@@ -37,7 +37,7 @@ where
 
 pub fn alexander_ingredients<KV, E>(kv: KV) -> Result<(), Error<E>>
 where
-  KV: gravitydb::KVStore<E>,
+  KV: gravitydb::TransactionalKVStore<E>,
   E: Send,
 {
   use CocktailSchema::*;
@@ -102,9 +102,66 @@ where
   Ok(())
 }
 
+pub fn typed_query_results<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+
+  // what is in an alexander (both variants)?
+  let alexander = Cocktail("Alexander".to_string());
+  let q = alexander.start()
+    .referencing_vertices()
+    .outgoing()
+    .intersect(Includes.start().referencing_edges())
+    .outgoing();
+  let result = graph.query_typed(q.clone())?;
+
+  let mut actual: Vec<_> = result.vertices.iter()
+    .map(|v| {
+      assert_eq!(v.property_id, v.property.get_key());
+      v.property.clone()
+    })
+    .collect();
+  actual.sort_by_key(|v| format!("{:?}",v));
+  let mut expected = graph.extract_properties(&graph.query(q)?)?;
+  expected.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, expected);
+
+  // every path knows the way from the cocktail to the ingredient
+  assert_eq!(result.paths.len(), result.vertices.len());
+  for path in result.paths.iter() {
+    assert!(path.start.is_none());
+    assert_eq!(path.hops.len(), 1);
+    let (cocktail, edge) = &path.hops[0];
+    assert_eq!(cocktail.property, alexander);
+    assert_eq!(edge.property, Includes);
+    assert!(path.end.is_some());
+  }
+
+  // properties are resolved as well
+  let result = graph.query_typed(SchemaType("Cocktail".to_string()).start())?;
+  assert_eq!(result.properties, vec![
+    (SchemaType("Cocktail".to_string()).get_key(), SchemaType("Cocktail".to_string())),
+  ]);
+
+  // or read as plain json
+  let result = graph.query(alexander.start().referencing_vertices())?;
+  let result = graph.resolve(&result, |db, id| db.read_property_json(id))?;
+  assert_eq!(result.vertices.len(), 2);
+  for v in result.vertices {
+    assert_eq!(v.property, serde_json::json!({"Cocktail": "Alexander"}));
+  }
+
+  Ok(())
+}
+
 pub fn which_cocktails_include_gin<KV, E>(kv: KV) -> Result<(), Error<E>>
 where
-  KV: gravitydb::KVStore<E>,
+  KV: gravitydb::TransactionalKVStore<E>,
   E: Send,
 {
   use CocktailSchema::*;
@@ -122,7 +179,15 @@ where
     .intersect(includes.start().referencing_edges())
     .ingoing()
     .intersect(cocktail.start().referencing_properties().referencing_vertices());
-  let result = graph.query(q)?;
+  let result = graph.query(q.clone())?;
+
+  // the same query in the text form, the values of the properties are
+  // hashed through the schema
+  let text = r#"P(Ingredient:"gin") -> V <- E[Includes] <- V & P(SchemaType:Cocktail).referencing"#;
+  let parsed: Result<gravitydb::kv_graph_store::BasicQuery, _> = gravitydb::zoe::parse_with(text, |value| {
+    serde_json::from_value::<CocktailSchema>(value.clone()).ok().map(|p| p.get_key())
+  });
+  assert_eq!(parsed, Ok(q.into()));
 
   let expected = vec![
     Cocktail("Alexander".to_string()),
@@ -229,7 +294,7 @@ where
 
 pub fn cocktail_statistic<KV, E>(kv: KV) -> Result<(), Error<E>>
 where
-  KV: gravitydb::KVStore<E>,
+  KV: gravitydb::TransactionalKVStore<E>,
   E: Send,
 {
   use CocktailSchema::*;
@@ -314,11 +379,900 @@ where
   Ok(())
 }
 
+pub fn cocktail_statistic_in_one_query<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use ql::{Aggregation, DegreeCount, Direction};
+
+  let graph = create_cocktail_graph(kv)?;
+  let includes = || Includes.start().referencing_edges();
+
+  // the same statistic as above, but computed by the database
+  let q = SchemaType("Cocktail".to_string()).start()
+    .referencing_properties()
+    .referencing_vertices()
+    .aggregate("cocktails", Aggregation::Count)
+    .degree("ingredients", DegreeCount::new(Direction::Outgoing).along(Includes.start()))
+    .outgoing()
+    .intersect(includes())
+    .outgoing()
+    .aggregate("types", Aggregation::GroupByNested("/SchemaType".to_string()))
+    .degree("usage", DegreeCount::new(Direction::Ingoing).along(Includes.start()));
+  let result = graph.query(q)?;
+
+  // a cocktail has 3.578 ingredients and 0.844 garnishes on average
+  let cocktails = result.variables["cocktails"].as_u64().unwrap();
+  let ingredients = result.variables["ingredients"].as_object().unwrap();
+  assert_eq!(ingredients.len() as u64, cocktails);
+  let sum: u64 = ingredients.values().map(|cnt| cnt.as_u64().unwrap()).sum();
+  assert_eq!(format!("{:.3}", sum as f32 / cocktails as f32), "4.422");
+
+  let mut types = std::collections::HashMap::new();
+  for property in graph.extract_properties(&result)? {
+    let name = match property {
+      Ingredient(_) => "Ingredient",
+      Garnish(_) => "Garnish",
+      _ => "other",
+    };
+    *types.entry(name.to_string()).or_insert(0) += 1;
+  }
+  let actual: std::collections::HashMap<String, u64> = serde_json::from_value(result.variables["types"].clone()).unwrap();
+  assert_eq!(actual, types);
+
+  // the most used ingredients
+  let mut usage = Vec::new();
+  for (id, cnt) in result.variables["usage"].as_object().unwrap() {
+    let node = graph.read_node(Uuid::from_key(id).unwrap())?;
+    if let Ingredient(name) = graph.read_property(&node.properties)? {
+      usage.push((name, cnt.as_u64().unwrap()));
+    }
+  }
+  usage.sort_by(|(name1, cnt1), (name2, cnt2)| cnt2.cmp(cnt1).then(name1.cmp(name2)));
+  assert_eq!(&usage[..5], vec![
+    ("gin".to_string(), 21),
+    ("lemon juice".to_string(), 16),
+    ("sugar syrup".to_string(), 8),
+    ("club soda".to_string(), 7),
+    ("maraschino".to_string(), 7),
+  ]);
+
+  // edges can be counted as well
+  let result = graph.query(includes().aggregate("includes", Aggregation::Count))?;
+  assert_eq!(result.variables["includes"], serde_json::json!(result.edges.len()));
+  let result = graph.query(includes().aggregate("kinds", Aggregation::GroupBy("".to_string())))?;
+  assert_eq!(result.variables["kinds"], serde_json::json!({"Includes": result.edges.len()}));
+
+  // without elements there is nothing to store the value in
+  let result = graph.query(
+    Cocktail("Virgin Mary".to_string()).start()
+      .referencing_vertices()
+      .aggregate("cocktails", Aggregation::Count)
+  )?;
+  assert!(result.variables.is_empty());
+
+  Ok(())
+}
+
+pub fn query_properties<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+
+  // what kind of thing is an alexander?
+  let alexander = Cocktail("Alexander".to_string());
+  let q = alexander.start()
+    .referenced_properties();
+  let result = graph.query(q)?;
+
+  let actual = graph.extract_properties(&result)?;
+  assert_eq!(actual, vec![SchemaType("Cocktail".to_string())]);
+
+  // and which other things of this kind do you know?
+  let q = alexander.start()
+    .referenced_properties()
+    .referencing_properties();
+  let result = graph.query(q)?;
+
+  // every cocktail only once, even if there are several variants
+  let cocktails = SchemaType("Cocktail".to_string()).start()
+    .referencing_properties()
+    .referencing_vertices();
+  let mut expected = graph.extract_properties(&graph.query(cocktails)?)?;
+  expected.sort_by_key(|v| format!("{:?}",v));
+  expected.dedup();
+
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual.len(), result.properties.len());
+  assert_eq!(actual, expected);
+  assert!(actual.contains(&alexander));
+
+  // a schema type is not made of anything else
+  let q = SchemaType("Cocktail".to_string()).start()
+    .referenced_properties();
+  let result = graph.query(q)?;
+  assert_eq!(result.properties.len(), 0);
+
+  Ok(())
+}
+
+pub fn cocktails_in_alphabetical_range<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use schema::OrderedProperty;
+
+  let graph = create_cocktail_graph(kv)?;
+
+  // the bar keeper is only allowed to mix the cocktails from G to M
+  let q = Cocktail("G".to_string()).range_to(&Cocktail("Martini".to_string()));
+  let result = graph.query(q)?;
+
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, vec![
+    Cocktail("Gin fizz".to_string()),
+    Cocktail("Golden fizz".to_string()),
+    Cocktail("Hanky panky".to_string()),
+    Cocktail("John Collins".to_string()),
+    Cocktail("Last Word".to_string()),
+    Cocktail("Manhattan".to_string()),
+    Cocktail("Martinez".to_string()),
+    Cocktail("Martini".to_string()),
+  ]);
+
+  // the same range also works on the nodes
+  let q = Cocktail("G".to_string()).range_to(&Cocktail("Martini".to_string()))
+    .referencing_vertices();
+  let result = graph.query(q)?;
+  assert_eq!(result.vertices.len(), 8);
+
+  // ingredients have no order and therefore no range
+  let q = Ingredient("a".to_string()).range_to(&Ingredient("z".to_string()));
+  let result = graph.query(q)?;
+  assert_eq!(result.properties.len(), 0);
+
+  // very long names only differ after the part used by the index
+  let mut graph = graph;
+  let long_name = "Z".repeat(100);
+  let long_a = Cocktail(long_name.clone() + "a");
+  let long_b = Cocktail(long_name.clone() + "b");
+  graph.create_node(Uuid::new(), &long_a)?;
+  let long_b_id = graph.create_node(Uuid::new(), &long_b)?;
+
+  let q = long_b.range_to(&Cocktail(long_name.clone() + "z"));
+  let result = graph.query(q)?;
+  let actual = graph.extract_properties(&result)?;
+  assert_eq!(actual, vec![long_b.clone()]);
+
+  graph.delete_node(long_b_id, DeletionMode::Reject)?;
+  let q = Cocktail(long_name).range_to(&Cocktail("Zz".to_string()));
+  let result = graph.query(q)?;
+  let actual = graph.extract_properties(&result)?;
+  assert_eq!(actual, vec![long_a]);
+
+  Ok(())
+}
+
+pub fn remember_cocktails_on_the_way<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+
+  // which alexander can I make with the cream in the fridge?
+  let alexander = Cocktail("Alexander".to_string());
+  let cream = Ingredient("cream".to_string());
+  let q = alexander.start()
+    .referencing_vertices()
+    .store()
+    .outgoing()
+    .intersect(Includes.start().referencing_edges())
+    .store()
+    .outgoing()
+    .intersect(cream.start().referencing_vertices());
+  let result = graph.query(q)?;
+
+  // both of them. The query remembered the cocktails on the way to the
+  // ingredient
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, vec![
+    alexander.clone(),
+    alexander.clone(),
+    Includes,
+    Includes,
+    cream,
+  ]);
+
+  // and with gin?
+  let gin = Ingredient("gin".to_string());
+  let q = alexander.start()
+    .referencing_vertices()
+    .store()
+    .outgoing()
+    .intersect(Includes.start().referencing_edges())
+    .outgoing()
+    .intersect(gin.start().referencing_vertices());
+  let result = graph.query(q)?;
+
+  // only the original one
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, vec![alexander, gin]);
+
+  Ok(())
+}
+
+pub fn cocktails_related_by_ingredients<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use ql::{Direction, Traversal};
+
+  let graph = create_cocktail_graph(kv)?;
+  let alexander = || Cocktail("Alexander".to_string()).start().referencing_vertices();
+  let includes = || Includes.start().referencing_edges();
+
+  // a single hop is the same as following the edges once
+  let q = alexander().traverse(Traversal::new(Direction::Outgoing).depth(1, Some(1)));
+  let mut actual = graph.extract_properties(&graph.query(q)?)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  let mut expected = graph.extract_properties(&graph.query(alexander().outgoing().outgoing())?)?;
+  expected.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, expected);
+
+  // which cocktails share an ingredient with the alexander? (the two
+  // alexanders share the cream)
+  let q = alexander().traverse(Traversal::new(Direction::Both).depth(2, Some(2)).along(Includes.start()));
+  let result = graph.query(q)?;
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  let q = alexander()
+    .outgoing()
+    .intersect(includes())
+    .outgoing()
+    .ingoing()
+    .intersect(includes())
+    .ingoing();
+  let mut expected = graph.extract_properties(&graph.query(q)?)?;
+  expected.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, expected);
+
+  // the way over the ingredient is remembered
+  assert_eq!(result.paths.len(), result.vertices.len());
+  for (_start, hops, end) in result.paths.iter() {
+    assert_eq!(hops.len(), 2);
+    assert_ne!(hops[0].0, end.unwrap());
+    assert_eq!(graph.read_property(&graph.read_edge(&hops[1].1)?.properties)?, Includes);
+  }
+
+  // without a limit the traversal ends at cycles and reaches everything
+  // connected to the alexander (including itself)
+  let q = alexander().traverse(Traversal::new(Direction::Both).depth(0, None).along(Includes.start()));
+  let result = graph.query(q)?;
+  let actual = graph.extract_properties(&result)?;
+  for p in [
+    Cocktail("Alexander".to_string()),
+    Ingredient("gin".to_string()),
+    Cocktail("Aviation".to_string()),
+  ] {
+    assert!(actual.contains(&p), "{p:?} not reached");
+  }
+  assert!(!actual.contains(&Glass("Cocktail glass".to_string())));
+  for (_start, hops, end) in result.paths.iter() {
+    let vertices: Vec<_> = hops.iter().map(|(v, _)| *v).chain(*end).collect();
+    let unique: std::collections::HashSet<_> = vertices.iter().collect();
+    assert_eq!(unique.len(), vertices.len());
+  }
+
+  Ok(())
+}
+
+pub fn shortest_way_between_cocktails<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use ql::{Direction, PathSearch};
+
+  let graph = create_cocktail_graph(kv)?;
+  let alexander = Cocktail("Alexander".to_string());
+  let search = || PathSearch::new(Direction::Both).along(Includes.start());
+
+  // how is the alexander related to the aviation?
+  let q = alexander.start()
+    .referencing_vertices()
+    .shortest_path(Cocktail("Aviation".to_string()).start().referencing_vertices(), search());
+  let result = graph.query(q)?;
+
+  // over the gin of the original alexander
+  assert_eq!(result.vertices.len(), 1);
+  let (start, hops, end) = &result.paths[0];
+  assert_eq!(start, &None);
+  let mut actual = Vec::new();
+  for (vertex, edge) in hops.iter() {
+    actual.push(graph.read_property(&graph.read_node(*vertex)?.properties)?);
+    actual.push(graph.read_property(&graph.read_edge(edge)?.properties)?);
+  }
+  actual.push(graph.read_property(&graph.read_node(end.unwrap())?.properties)?);
+  assert_eq!(actual, vec![
+    alexander.clone(),
+    Includes,
+    Ingredient("gin".to_string()),
+    Includes,
+    Cocktail("Aviation".to_string()),
+  ]);
+
+  // the glass is not an ingredient
+  let q = alexander.start()
+    .referencing_vertices()
+    .shortest_path(Glass("Cocktail glass".to_string()).start().referencing_vertices(), search());
+  let result = graph.query(q)?;
+  assert_eq!(result.vertices.len(), 0);
+
+  Ok(())
+}
+
+pub fn graph_algorithms_on_cocktails<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+  let ids = |q| -> Result<Vec<Uuid>, Error<E>> {
+    Ok(graph.query(q)?.vertices.into_iter().map(|(id, _)| id).collect())
+  };
+
+  assert!(!graph.is_empty()?);
+  assert_eq!(graph.order()?, PropertyGraphReader::nodes(&graph, PropertyFilter::All)?.count());
+  assert_eq!(graph.size()?, Graph::edges(&graph)?.count());
+
+  // the gin is only used by cocktails
+  let gin = ids(Ingredient("gin".to_string()).start().referencing_vertices())?[0];
+  let with_gin = ids(
+    Ingredient("gin".to_string()).start()
+      .referencing_vertices()
+      .ingoing()
+      .ingoing()
+  )?;
+  assert_eq!(graph.outgoing(&gin)?.count(), 0);
+  assert_eq!(graph.degree(&gin)?, with_gin.len());
+  let mut neighbors: Vec<_> = graph.neighbors(&gin)?.map(|id| id.to_key()).collect();
+  neighbors.sort();
+  let mut expected: Vec<_> = graph.incoming(&gin)?.map(|id| id.to_key()).collect();
+  expected.sort();
+  assert_eq!(neighbors, expected);
+  assert_eq!(neighbors.len(), with_gin.len());
+  assert!(graph.has_edge(&with_gin[0], &gin)?);
+  assert!(!graph.has_edge(&gin, &with_gin[0])?);
+  assert!(graph.has_node(&gin)?);
+  assert!(!graph.has_node(&Uuid::new())?);
+
+  // generic algorithms can run on the database
+  let start = ids(Cocktail("Alexander".to_string()).start().referencing_vertices())?[0];
+  let mut actual: Vec<_> = reachable(&graph, start)?.into_iter().map(|id| id.to_key()).collect();
+  actual.sort();
+  let mut expected: Vec<_> = ids(ql::VertexQuery::from_ids(vec![start]).outgoing().outgoing())?.into_iter()
+    .map(|id| id.to_key())
+    .chain([start.to_key()])
+    .collect();
+  expected.sort();
+  assert_eq!(actual, expected);
+
+  Ok(())
+}
+
+/// All nodes which can be reached from the start node
+fn reachable<'a, G, N, E>(graph: &'a G, start: N) -> Result<Vec<N>, E>
+where
+  G: DirectedGraph<'a, N, E>,
+  N: Clone + Eq + std::hash::Hash + 'a,
+{
+  let mut visited = std::collections::HashSet::from([start.clone()]);
+  let mut todo = vec![start];
+  while let Some(node) = todo.pop() {
+    for next in graph.outgoing(&node)? {
+      if visited.insert(next.clone()) {
+        todo.push(next);
+      }
+    }
+  }
+  Ok(visited.into_iter().collect())
+}
+
+pub fn analyse_cocktail_graph<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use algorithms::*;
+
+  let graph = create_cocktail_graph(kv)?;
+  let ids = |q| -> Result<Vec<Uuid>, Error<E>> {
+    Ok(graph.query(q)?.vertices.into_iter().map(|(id, _)| id).collect())
+  };
+  let gin = ids(Ingredient("gin".to_string()).start().referencing_vertices())?[0];
+  let alexander = ids(Cocktail("Alexander".to_string()).start().referencing_vertices())?[0];
+  let aviation = ids(Cocktail("Aviation".to_string()).start().referencing_vertices())?[0];
+
+  // the recipes have no cycles
+  let order = TopologicalSort.filter(&graph)?.expect("the cocktails have no cycles");
+  assert_eq!(order.len(), graph.order()?);
+  let position: std::collections::HashMap<_, _> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+  for (source, target) in Graph::edges(&graph)? {
+    assert!(position[&source] < position[&target]);
+  }
+  let components = StronglyConnectedComponents.filter(&graph)?;
+  assert_eq!(components.len(), graph.order()?);
+
+  // everything is connected over the ingredients and glasses except
+  // unused ingredients
+  let components = ConnectedComponents.filter(&graph)?;
+  assert_eq!(components.iter().map(|c| c.len()).sum::<usize>(), graph.order()?);
+  let with_gin = components.iter().find(|c| c.contains(&gin)).unwrap();
+  assert!(with_gin.contains(&alexander));
+  assert!(with_gin.contains(&aviation));
+
+  // the gin is used more often than the alexander uses ingredients
+  let centrality = DegreeCentrality.filter(&graph)?;
+  assert_eq!(centrality[&gin], graph.degree(&gin)? as f64 / (graph.order()? - 1) as f64);
+  assert!(centrality[&gin] > centrality[&alexander]);
+  let rank = PageRank::default().filter(&graph)?;
+  assert!((rank.values().sum::<f64>() - 1.0).abs() < 1e-9);
+  assert!(rank[&gin] > rank[&alexander]);
+
+  // cocktails are only connected over ingredients and glasses
+  assert!(TriangleCount.filter(&graph)?.values().all(|t| *t == 0));
+
+  // the algorithms work on query results as well
+  let q = Cocktail("Alexander".to_string()).start()
+    .referencing_vertices()
+    .shortest_path(
+      Cocktail("Aviation".to_string()).start().referencing_vertices(),
+      ql::PathSearch::new(ql::Direction::Both).along(Includes.start()),
+    );
+  let subgraph = graph.subgraph(&graph.query(q)?)?;
+  assert_eq!(subgraph.order(), Ok(3));
+  assert_eq!(subgraph.size(), Ok(2));
+  assert_eq!(ConnectedComponents.filter(&subgraph).map(|c| c.len()), Ok(1));
+  let Ok(order) = TopologicalSort.filter(&subgraph);
+  assert_eq!(order.and_then(|o| o.last().copied()), Some(gin));
+
+  Ok(())
+}
+
+pub fn hulls_of_cocktail_results<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+  let keys = |mut ids: Vec<String>| {
+    ids.sort();
+    ids
+  };
+  let vertices = |result: &kv_graph_store::QueryResult| keys(result.vertices.iter().map(|(id, _)| id.to_key()).collect());
+  let edges = |result: &kv_graph_store::QueryResult| keys(result.edges.iter().map(|(id, _)| id.clone()).collect());
+
+  // the gin and the cocktails with gin are connected by the ingredient
+  // edges only
+  let gin = || Ingredient("gin".to_string()).start().referencing_vertices();
+  let q = gin().union(gin().ingoing().ingoing());
+  let result = graph.query(q)?;
+  let hull = graph.inner_hull(&result)?;
+  assert_eq!(vertices(&hull), vertices(&result));
+  assert_eq!(edges(&hull), edges(&graph.query(gin().ingoing())?));
+
+  // edges lead to their vertices
+  let result = graph.query(gin().ingoing())?;
+  let hull = graph.inner_hull(&result)?;
+  assert_eq!(edges(&hull), edges(&result));
+  assert_eq!(vertices(&hull), vertices(&graph.query(gin().union(gin().ingoing().ingoing()))?));
+
+  // the outer hull of a cocktail contains its ingredients and glass
+  let aviation = || Cocktail("Aviation".to_string()).start().referencing_vertices();
+  let result = graph.query(aviation())?;
+  let hull = graph.outer_hull(&result)?;
+  assert_eq!(vertices(&hull), vertices(&graph.query(aviation().union(aviation().outgoing().outgoing()))?));
+  assert_eq!(edges(&hull), edges(&graph.query(aviation().outgoing())?));
+  let data = graph.resolve(&hull, |db, id| db.read_property(id))?;
+  assert!(data.vertices.iter().any(|v| v.property == Ingredient("gin".to_string())));
+
+  // the paths are kept
+  let result = graph.query(aviation().outgoing().store().outgoing())?;
+  let hull = graph.inner_hull(&result)?;
+  assert!(!result.paths.is_empty());
+  assert_eq!(hull.paths, result.paths);
+
+  Ok(())
+}
+
+pub fn page_through_cocktails<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+  use ql::SortOrder;
+
+  let graph = create_cocktail_graph(kv)?;
+  let cocktails = || SchemaType("Cocktail".to_string()).start()
+    .referencing_properties()
+    .referencing_vertices();
+  let names = |result: &kv_graph_store::QueryResult| -> Result<Vec<String>, Error<E>> {
+    Ok(graph.extract_properties(result)?.into_iter()
+      .map(|property| match property {
+        Cocktail(name) => name,
+        other => panic!("{other:?} is no cocktail"),
+      })
+      .collect())
+  };
+
+  let mut all = names(&graph.query(cocktails())?)?;
+  all.sort();
+
+  // the first page of a listing sorted by name
+  let q = cocktails().order_by(SortOrder::by_field("/Cocktail")).limit(10);
+  let result = graph.query(q.clone())?;
+  assert_eq!(names(&result)?, all[..10]);
+  assert_eq!(result.paths.len(), 10);
+
+  // the cursor leads through all other pages
+  let mut listed = Vec::new();
+  let mut cursor = None;
+  let mut pages = 0;
+  loop {
+    let result = graph.query_page(q.clone(), cursor.as_deref())?;
+    listed.extend(names(&result)?);
+    pages += 1;
+    cursor = result.cursor;
+    if cursor.is_none() {
+      break;
+    }
+  }
+  assert_eq!(listed, all);
+  assert_eq!(pages, all.len().div_ceil(10));
+
+  // a page in the middle, starting with the last cocktail
+  let q = cocktails()
+    .order_by(SortOrder::by_field("/Cocktail").descending())
+    .skip(3)
+    .limit(2);
+  let result = graph.query(q)?;
+  let mut reversed = all.clone();
+  reversed.reverse();
+  assert_eq!(names(&result)?, reversed[3..5]);
+
+  // without an order the vertices are taken in the order of their ids
+  let mut ids: Vec<_> = graph.query(cocktails())?.vertices.into_iter()
+    .map(|(id, _)| id.to_key())
+    .collect();
+  ids.sort();
+  let result = graph.query(cocktails().limit(5))?;
+  let actual: Vec<_> = result.vertices.iter().map(|(id, _)| id.to_key()).collect();
+  assert_eq!(actual, ids[..5]);
+
+  // a cursor only fits the query it was created for
+  let q = cocktails().order_by(SortOrder::by_id()).limit(5);
+  let cursor = graph.query(q.clone())?.cursor.expect("there is a second page");
+  assert!(graph.query_page(q.clone(), Some(&cursor)).is_ok());
+  assert!(matches!(
+    graph.query_page(cocktails().limit(6), Some(&cursor)),
+    Err(Error::InvalidCursor(_))
+  ));
+  assert!(matches!(
+    graph.query_page(cocktails(), Some(&cursor)),
+    Err(Error::InvalidCursor(_))
+  ));
+
+  // limits and cursors at the end of the numbers do not overflow
+  let result = graph.query(cocktails().limit(usize::MAX))?;
+  assert_eq!(names(&result)?.len(), all.len());
+  assert!(result.cursor.is_none());
+  let (_, fingerprint) = cursor.split_once('-').expect("cursors contain a fingerprint");
+  let result = graph.query_page(q, Some(&format!("{}-{fingerprint}", usize::MAX)))?;
+  assert!(result.vertices.is_empty());
+  assert!(result.cursor.is_none());
+
+  Ok(())
+}
+
+pub fn explain_cocktail_queries<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+  let cocktails = || SchemaType("Cocktail".to_string()).start()
+    .referencing_properties()
+    .referencing_vertices();
+
+  // the cocktails with gin (see which_cocktails_include_gin)
+  let q = cocktails()
+    .intersect(Ingredient("gin".to_string()).start()
+      .referencing_vertices()
+      .ingoing()
+      .intersect(Includes.start().referencing_edges())
+      .ingoing()
+    );
+  let plan = graph.explain(q.clone())?;
+  assert_eq!(plan.step, "Intersect");
+  assert_eq!(plan.actual, graph.query(q)?.vertices.len());
+  assert_eq!(plan.steps.iter().map(|step| step.step.as_str()).collect::<Vec<_>>(), vec!["Property", "In"]);
+  assert!(plan.total_reads() > plan.steps[0].reads);
+  assert_estimates_are_upper_bounds(&plan);
+
+  // starting with the most selective index
+  let martini = Cocktail("Martini".to_string()).start().referencing_vertices();
+  let q = cocktails().intersect(martini.clone());
+  let optimized = graph.optimize(q.clone())?;
+  assert_eq!(optimized, martini.clone().intersect(cocktails()).into());
+  assert_eq!(graph.query(optimized.clone())?.vertices, graph.query(q)?.vertices);
+  assert_eq!(graph.explain(optimized)?.estimated, Some(1));
+
+  // an empty index makes the rest of the intersection unnecessary
+  let q = cocktails().intersect(Cocktail("Mojito".to_string()).start().referencing_vertices());
+  let before = graph.explain(q.clone())?;
+  let after = graph.explain(graph.optimize(q)?)?;
+  assert_eq!((before.actual, after.actual), (0, 0));
+  assert_eq!(before.steps.len(), 2);
+  assert_eq!(after.steps.len(), 1);
+  assert!(after.total_reads() < before.total_reads());
+
+  // properties are looked up as one step
+  let plan = graph.explain(SchemaType("Cocktail".to_string()).start().referencing_properties())?;
+  assert_eq!(plan.step, "ReferencingProperties");
+  assert!(plan.steps.is_empty());
+  assert!(plan.actual > 20);
+
+  Ok(())
+}
+
+fn assert_estimates_are_upper_bounds(plan: &ql::QueryPlan) {
+  if let Some(estimated) = plan.estimated {
+    assert!(estimated >= plan.actual, "{plan}");
+  }
+  for step in plan.steps.iter() {
+    assert_estimates_are_upper_bounds(step);
+  }
+}
+
+pub fn stream_cocktails<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+  let cocktails = || SchemaType("Cocktail".to_string()).start()
+    .referencing_properties()
+    .referencing_vertices();
+
+  // the stream has the same vertices as the result, ordered by their ids
+  let streamed = graph.stream_nodes(cocktails())?
+    .map(|element| Ok(element?.0))
+    .collect::<Result<Vec<_>, Error<E>>>()?;
+  let result: Vec<_> = graph.query(cocktails())?.vertices.into_iter()
+    .map(|(id, _)| id)
+    .collect();
+  assert_eq!(streamed.len(), result.len());
+  assert!(streamed.iter().all(|id| result.contains(id)));
+  assert!(streamed.is_sorted_by_key(|id| id.to_key()));
+
+  // skip and limit take their elements from the stream
+  let page = graph.query(cocktails().skip(2).limit(3))?;
+  assert_eq!(page.vertices.into_iter().map(|(id, _)| id).collect::<Vec<_>>(), streamed[2..5]);
+
+  let streamed = graph.stream_edges(Includes.start().referencing_edges())?
+    .map(|element| Ok(element?.0))
+    .collect::<Result<Vec<_>, Error<E>>>()?;
+  let page = graph.query(Includes.start().referencing_edges().limit(4))?;
+  assert_eq!(page.edges.into_iter().map(|(id, _)| id).collect::<Vec<_>>(), streamed[..4]);
+
+  // vertices beyond the limit are never read, so a missing vertex at
+  // the end does not matter (a page reads one vertex more to know if
+  // there is a next page)
+  let missing = Uuid::from_key("ffffffff-ffff-ffff-ffff-ffffffffffff").expect("valid uuid");
+  let q = ql::VertexQuery::from_ids(vec![missing, result[0], result[1]])
+    .filter(ql::ShellFilter {
+      interpreter: "lua".to_string(),
+      script: "return true".to_string(),
+    });
+  assert!(graph.query(q.clone()).is_err());
+  assert_eq!(graph.query(q.clone().limit(1))?.vertices.len(), 1);
+  let first = graph.stream_nodes(q)?
+    .take(2)
+    .collect::<Result<Vec<_>, Error<E>>>()?;
+  assert_eq!(first.len(), 2);
+
+  Ok(())
+}
+
+pub fn filter_cocktails_by_script<KV, E>(kv: KV) -> Result<(), Error<E>>
+where
+  KV: gravitydb::TransactionalKVStore<E>,
+  E: Send,
+{
+  use CocktailSchema::*;
+
+  let graph = create_cocktail_graph(kv)?;
+
+  // I only know the first letter of the cocktail, but I know it
+  // includes gin
+  let gin = Ingredient("gin".to_string());
+  let gin_cocktails = gin.start()
+    .referencing_vertices()
+    .ingoing()
+    .intersect(Includes.start().referencing_edges())
+    .ingoing();
+  let expected = vec![
+    Cocktail("Alexander".to_string()),
+    Cocktail("Angel face".to_string()),
+    Cocktail("Aviation".to_string()),
+  ];
+
+  // scripts can be written in lua ...
+  let q = gin_cocktails.clone()
+    .filter(ql::ShellFilter {
+      interpreter: "lua".to_string(),
+      script: "return string.sub(properties.Cocktail, 1, 1) == 'A'".to_string(),
+    });
+  let result = graph.query(q)?;
+
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, expected);
+
+  // ... or use any program on the system
+  let q = gin_cocktails
+    .filter(ql::ShellFilter {
+      interpreter: "sh -c".to_string(),
+      script: "grep -q '\"Cocktail\":\"A'".to_string(),
+    });
+  let result = graph.query(q)?;
+
+  let mut actual = graph.extract_properties(&result)?;
+  actual.sort_by_key(|v| format!("{:?}",v));
+  assert_eq!(actual, expected);
+
+  // the same works for edges
+  let q = ql::VertexQuery::from_ids(result.vertices.iter().map(|(id, _)| *id).collect())
+    .outgoing()
+    .filter(ql::ShellFilter {
+      interpreter: "lua".to_string(),
+      script: "return properties == 'ServedIn'".to_string(),
+    })
+    .outgoing();
+  let result = graph.query(q)?;
+
+  let actual = graph.extract_properties(&result)?;
+  assert_eq!(actual, vec![Glass("Cocktail glass".to_string())]);
+
+  // broken scripts are reported
+  let q = gin.start()
+    .referencing_vertices()
+    .filter(ql::ShellFilter {
+      interpreter: "lua".to_string(),
+      script: "return properties.Ingredient ==".to_string(),
+    });
+  match graph.query(q) {
+    Err(Error::Filter(_)) => (),
+    _ => panic!("should fail because the script can not be parsed"),
+  };
+
+  Ok(())
+}
+
+/// Scan the records of a kv store by range and by prefix in both
+/// directions and scan their keys only
+pub fn scan_records<KV, E>(mut kv: KV) -> Result<(), E>
+where
+  KV: gravitydb::KVStore<E>,
+{
+  use gravitydb::{ScanDirection::*, ScanRange::*};
+
+  for bucket in ["nodes/", "edges/", "indexes/x/", "indexes/y/"] {
+    kv.create_bucket(bucket.as_bytes())?;
+  }
+  for key in ["nodes/b", "nodes/a", "nodes/c", "edges/a", "indexes/x/nodes_b", "indexes/x/nodes_a", "indexes/x/edges_a", "indexes/y/nodes_c"] {
+    kv.store_record(key.as_bytes(), format!("value of {key}").as_bytes())?;
+  }
+
+  let keys = |range, direction| -> Result<Vec<String>, E> {
+    kv.scan(range, direction)?
+      .map(|record| {
+        let (key, value) = record?;
+        let key = String::from_utf8(key).expect("keys are utf8");
+        assert_eq!(value, format!("value of {key}").as_bytes());
+        Ok(key)
+      })
+      .collect()
+  };
+
+  assert_eq!(keys(Prefix(b"nodes/"), Forward)?, vec!["nodes/a", "nodes/b", "nodes/c"]);
+  assert_eq!(keys(Prefix(b"nodes/"), Reverse)?, vec!["nodes/c", "nodes/b", "nodes/a"]);
+  assert_eq!(keys(Prefix(b"indexes/x/nodes_"), Forward)?, vec!["indexes/x/nodes_a", "indexes/x/nodes_b"]);
+  assert_eq!(keys(Prefix(b"nodes/d"), Forward)?, Vec::<String>::new());
+  assert_eq!(keys(Between(b"nodes/b", b"nodes/c"), Forward)?, vec!["nodes/b", "nodes/c"]);
+  assert_eq!(keys(Between(b"indexes/x/", b"indexes/y/"), Reverse)?, vec!["indexes/x/nodes_b", "indexes/x/nodes_a", "indexes/x/edges_a"]);
+
+  let scanned_keys = |from: &[u8], to: &[u8]| -> Result<Vec<String>, E> {
+    kv.scan_keys(from, to)?
+      .map(|key| Ok(String::from_utf8(key?).expect("keys are utf8")))
+      .collect()
+  };
+
+  assert_eq!(scanned_keys(b"nodes/", b"")?, vec!["nodes/a", "nodes/b", "nodes/c"]);
+  assert_eq!(scanned_keys(b"indexes/x/", b"indexes/y/")?, vec!["indexes/x/edges_a", "indexes/x/nodes_a", "indexes/x/nodes_b"]);
+
+  Ok(())
+}
+
+/// Keys are arbitrary bytes and do not need to be valid utf8
+pub fn binary_keys<KV, E>(mut kv: KV) -> Result<(), E>
+where
+  KV: gravitydb::KVStore<E>,
+{
+  use gravitydb::{ScanDirection::*, ScanRange::*};
+
+  let keys: [&[u8]; 6] = [b"\x00", b"\x01\xFF", b"\x01\xFF\xFF", b"\x02", b"\xFF", b"\xFF\x00"];
+  for key in keys {
+    kv.store_record(key, key)?;
+  }
+  for key in keys {
+    assert_eq!(kv.fetch_record(key)?, key);
+  }
+
+  let scanned = |range, direction| -> Result<Vec<Vec<u8>>, E> {
+    kv.scan(range, direction)?.map(|record| Ok(record?.0)).collect()
+  };
+
+  // an empty prefix matches everything
+  assert_eq!(kv.list_records(b"", b"")?, keys);
+  assert_eq!(scanned(Prefix(b""), Forward)?, keys);
+  // the end of a prefix ending in 0xFF is found by carrying over
+  assert_eq!(kv.list_records(b"\x01\xFF", b"")?, vec![b"\x01\xFF".to_vec(), b"\x01\xFF\xFF".to_vec()]);
+  assert_eq!(scanned(Prefix(b"\x01"), Reverse)?, vec![b"\x01\xFF\xFF".to_vec(), b"\x01\xFF".to_vec()]);
+  // a prefix of 0xFF bytes only reaches till the end
+  assert_eq!(kv.list_records(b"\xFF", b"")?, vec![b"\xFF".to_vec(), b"\xFF\x00".to_vec()]);
+  assert_eq!(kv.list_records(b"\x01", b"\x02")?, keys[1..4]);
+
+  kv.delete_record(b"\x01\xFF")?;
+  assert!(!kv.exists(b"\x01\xFF")?);
+  assert!(kv.exists(b"\x01\xFF\xFF")?);
+
+  Ok(())
+}
+
 type GStore<KV, E> = kv_graph_store::KvGraphStore::<CocktailSchema, KV, E>;
 
 fn create_cocktail_graph<KV, E>(kv: KV) -> Result<GStore<KV, E>, Error<E>>
 where
-  KV: gravitydb::KVStore<E>,
+  KV: gravitydb::TransactionalKVStore<E>,
   E: Send,
 {
   let mut g = kv_graph_store::KvGraphStore::from_kv(kv);
@@ -765,6 +1719,22 @@ pub enum CocktailSchema {
 use gravitydb::schema::{JsonSchemaProperty, KeyAdressableElement};
 impl JsonSchemaProperty for CocktailSchema {}
 
+use gravitydb::schema::{OrderedEncoding, OrderedProperty};
+impl OrderedProperty for CocktailSchema {
+  fn ordered_key(&self) -> Option<Vec<u8>> {
+    use CocktailSchema::*;
+
+    match self {
+      Cocktail(name) => {
+        let mut key = vec![];
+        name.encode_ordered(&mut key);
+        Some(key)
+      }
+      _ => None,
+    }
+  }
+}
+
 impl NestableProperty for CocktailSchema {
   fn nested(&self) -> Vec<Self> {
     use CocktailSchema::*;
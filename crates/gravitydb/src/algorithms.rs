@@ -0,0 +1,478 @@
+//! Graph algorithms
+//!
+//! The algorithms are implemented as `GraphFilter`s on top of the
+//! `Graph` and `DirectedGraph` traits. They can be run on a whole
+//! database (`KvGraphStore`) as well as on a `SubGraph`, e.g. of a query
+//! result (see `KvGraphStore::subgraph`).
+//!
+//! Every algorithm reads the neighbours of each node only once, so the
+//! number of reads from a database grows linear with the size of the
+//! graph.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use core::hash::Hash;
+use crate::{DirectedGraph, Graph, GraphFilter};
+
+/// A directed graph held in memory
+///
+/// Parallel edges are kept, so that the graph can represent the edges of
+/// a database one to one.
+#[derive(Debug, Clone)]
+pub struct SubGraph<N> {
+  nodes: Vec<N>,
+  index: HashMap<N, usize>,
+  outgoing: Vec<Vec<usize>>,
+  incoming: Vec<Vec<usize>>,
+  size: usize,
+}
+
+impl<N> Default for SubGraph<N> {
+  fn default() -> Self {
+    SubGraph {
+      nodes: Vec::new(),
+      index: HashMap::new(),
+      outgoing: Vec::new(),
+      incoming: Vec::new(),
+      size: 0,
+    }
+  }
+}
+
+impl<N: Clone + Eq + Hash> SubGraph<N> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a node (if it is not yet part of the graph)
+  pub fn add_node(&mut self, node: N) {
+    self.position(node);
+  }
+
+  /// Add an edge from source to target (the nodes are added as well)
+  pub fn add_edge(&mut self, source: N, target: N) {
+    let source = self.position(source);
+    let target = self.position(target);
+    self.outgoing[source].push(target);
+    self.incoming[target].push(source);
+    self.size += 1;
+  }
+
+  fn position(&mut self, node: N) -> usize {
+    if let Some(i) = self.index.get(&node) {
+      return *i;
+    }
+    let i = self.nodes.len();
+    self.index.insert(node.clone(), i);
+    self.nodes.push(node);
+    self.outgoing.push(Vec::new());
+    self.incoming.push(Vec::new());
+    i
+  }
+
+  fn collect(&self, nodes: impl Iterator<Item = usize>) -> std::vec::IntoIter<N> {
+    nodes.map(|i| self.nodes[i].clone()).collect::<Vec<_>>().into_iter()
+  }
+
+  fn adjacent(&self, node: &N, lists: &[Vec<usize>]) -> std::vec::IntoIter<N> {
+    match self.index.get(node) {
+      Some(i) => self.collect(lists[*i].iter().copied()),
+      None => Vec::new().into_iter(),
+    }
+  }
+}
+
+impl<'a, N> Graph<'a, N, Infallible> for SubGraph<N>
+where
+  N: Clone + Eq + Hash + 'a,
+{
+  type NodeIterator = std::vec::IntoIter<N>;
+  type NeighborIterator = std::vec::IntoIter<N>;
+  type EdgeIterator = std::vec::IntoIter<(N, N)>;
+
+  fn order(&self) -> Result<usize, Infallible> {
+    Ok(self.nodes.len())
+  }
+
+  fn size(&self) -> Result<usize, Infallible> {
+    Ok(self.size)
+  }
+
+  fn nodes(&'a self) -> Result<Self::NodeIterator, Infallible> {
+    Ok(self.nodes.clone().into_iter())
+  }
+
+  fn has_node(&self, node: &N) -> Result<bool, Infallible> {
+    Ok(self.index.contains_key(node))
+  }
+
+  /// The nodes on the other side of the outgoing and the incoming edges
+  fn neighbors(&'a self, node: &N) -> Result<Self::NeighborIterator, Infallible> {
+    let Some(i) = self.index.get(node) else {
+      return Ok(Vec::new().into_iter());
+    };
+    Ok(self.collect(self.outgoing[*i].iter().chain(self.incoming[*i].iter()).copied()))
+  }
+
+  /// The number of edges connected to the node (an edge to itself is
+  /// counted twice)
+  fn degree(&self, node: &N) -> Result<usize, Infallible> {
+    Ok(self.index.get(node).map_or(0, |i| self.outgoing[*i].len() + self.incoming[*i].len()))
+  }
+
+  fn edges(&'a self) -> Result<Self::EdgeIterator, Infallible> {
+    let edges = self.outgoing.iter()
+      .enumerate()
+      .flat_map(|(source, targets)| targets.iter().map(move |target| (source, *target)))
+      .map(|(source, target)| (self.nodes[source].clone(), self.nodes[target].clone()))
+      .collect::<Vec<_>>();
+    Ok(edges.into_iter())
+  }
+
+  /// Returns true if an edge leads from source to target
+  fn has_edge(&self, source: &N, target: &N) -> Result<bool, Infallible> {
+    match (self.index.get(source), self.index.get(target)) {
+      (Some(source), Some(target)) => Ok(self.outgoing[*source].contains(target)),
+      _ => Ok(false),
+    }
+  }
+}
+
+impl<'a, N> DirectedGraph<'a, N, Infallible> for SubGraph<N>
+where
+  N: Clone + Eq + Hash + 'a,
+{
+  type OutIterator = std::vec::IntoIter<N>;
+  type InIterator = std::vec::IntoIter<N>;
+
+  fn outgoing(&'a self, node: &N) -> Result<Self::OutIterator, Infallible> {
+    Ok(self.adjacent(node, &self.outgoing))
+  }
+
+  fn incoming(&'a self, node: &N) -> Result<Self::InIterator, Infallible> {
+    Ok(self.adjacent(node, &self.incoming))
+  }
+}
+
+/// The nodes of a graph by their position together with the positions
+/// of the adjacent nodes
+struct Adjacency<N> {
+  nodes: Vec<N>,
+  adjacent: Vec<Vec<usize>>,
+}
+
+impl<N: Clone + Eq + Hash> Adjacency<N> {
+  fn read<'a, G, E, I, F>(graph: &'a G, neighbours: F) -> Result<Self, E>
+  where
+    G: Graph<'a, N, E>,
+    N: 'a,
+    I: Iterator<Item = N>,
+    F: Fn(&'a G, &N) -> Result<I, E>,
+  {
+    let nodes: Vec<N> = graph.nodes()?.collect();
+    let index: HashMap<&N, usize> = nodes.iter()
+      .enumerate()
+      .map(|(i, node)| (node, i))
+      .collect();
+
+    let adjacent = nodes.iter()
+      .map(|node| {
+        // neighbours outside of the graph are ignored
+        Ok(neighbours(graph, node)?
+          .filter_map(|n| index.get(&n).copied())
+          .collect())
+      })
+      .collect::<Result<Vec<Vec<usize>>, E>>()?;
+
+    Ok(Adjacency { nodes, adjacent })
+  }
+
+  fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  fn node_map<T>(&self, values: impl IntoIterator<Item = T>) -> HashMap<N, T> {
+    self.nodes.iter().cloned().zip(values).collect()
+  }
+
+  fn group(&self, groups: Vec<Vec<usize>>) -> Vec<Vec<N>> {
+    groups.into_iter()
+      .map(|group| group.into_iter().map(|i| self.nodes[i].clone()).collect())
+      .collect()
+  }
+}
+
+/// Splits a graph into its (weakly) connected components
+///
+/// The direction of the edges is ignored. The components are ordered by
+/// the first of their nodes returned by `Graph::nodes`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectedComponents;
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<Vec<Vec<N>>, E>> for ConnectedComponents
+where
+  G: Graph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  fn filter(&mut self, graph: &'a G) -> Result<Vec<Vec<N>>, E> {
+    let graph = Adjacency::read(graph, |g, n| g.neighbors(n))?;
+
+    let mut component = vec![None; graph.len()];
+    let mut components = Vec::new();
+    for start in 0..graph.len() {
+      if component[start].is_some() {
+        continue;
+      }
+      let c = components.len();
+      component[start] = Some(c);
+      let mut members = vec![start];
+      let mut queue = VecDeque::from([start]);
+      while let Some(i) = queue.pop_front() {
+        for n in graph.adjacent[i].iter() {
+          if component[*n].is_none() {
+            component[*n] = Some(c);
+            members.push(*n);
+            queue.push_back(*n);
+          }
+        }
+      }
+      components.push(members);
+    }
+
+    Ok(graph.group(components))
+  }
+}
+
+/// Splits a directed graph into its strongly connected components
+///
+/// Within a strongly connected component every node can be reached from
+/// every other node. The components are returned in reverse topological
+/// order (a component comes before the components it can be reached
+/// from).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StronglyConnectedComponents;
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<Vec<Vec<N>>, E>> for StronglyConnectedComponents
+where
+  G: DirectedGraph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  /// Tarjan's algorithm (without recursion, so that long paths can not
+  /// overflow the stack)
+  fn filter(&mut self, graph: &'a G) -> Result<Vec<Vec<N>>, E> {
+    let graph = Adjacency::read(graph, |g, n| g.outgoing(n))?;
+
+    let mut index = vec![None; graph.len()];
+    let mut lowlink = vec![0; graph.len()];
+    let mut on_stack = vec![false; graph.len()];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next = 0;
+
+    for root in 0..graph.len() {
+      if index[root].is_some() {
+        continue;
+      }
+      // nodes currently visited together with their next edge
+      let mut visiting = vec![(root, 0)];
+      index[root] = Some(next);
+      lowlink[root] = next;
+      next += 1;
+      stack.push(root);
+      on_stack[root] = true;
+
+      while let Some((node, edge)) = visiting.pop() {
+        if let Some(target) = graph.adjacent[node].get(edge).copied() {
+          visiting.push((node, edge + 1));
+          match index[target] {
+            None => {
+              index[target] = Some(next);
+              lowlink[target] = next;
+              next += 1;
+              stack.push(target);
+              on_stack[target] = true;
+              visiting.push((target, 0));
+            }
+            Some(i) if on_stack[target] => lowlink[node] = lowlink[node].min(i),
+            Some(_) => (),
+          }
+          continue;
+        }
+
+        if let Some((parent, _)) = visiting.last() {
+          lowlink[*parent] = lowlink[*parent].min(lowlink[node]);
+        }
+        if Some(lowlink[node]) == index[node] {
+          let mut component = Vec::new();
+          while let Some(member) = stack.pop() {
+            on_stack[member] = false;
+            component.push(member);
+            if member == node {
+              break;
+            }
+          }
+          components.push(component);
+        }
+      }
+    }
+
+    Ok(graph.group(components))
+  }
+}
+
+/// Orders the nodes of a directed graph, so that every edge leads from
+/// an earlier to a later node
+///
+/// Returns `None` if the graph contains a cycle (and can therefore not be
+/// ordered).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TopologicalSort;
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<Option<Vec<N>>, E>> for TopologicalSort
+where
+  G: DirectedGraph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  fn filter(&mut self, graph: &'a G) -> Result<Option<Vec<N>>, E> {
+    let graph = Adjacency::read(graph, |g, n| g.outgoing(n))?;
+
+    let mut ingoing = vec![0; graph.len()];
+    for targets in graph.adjacent.iter() {
+      for target in targets {
+        ingoing[*target] += 1;
+      }
+    }
+
+    let mut queue: VecDeque<usize> = (0..graph.len()).filter(|i| ingoing[*i] == 0).collect();
+    let mut order = Vec::with_capacity(graph.len());
+    while let Some(i) = queue.pop_front() {
+      order.push(i);
+      for target in graph.adjacent[i].iter() {
+        ingoing[*target] -= 1;
+        if ingoing[*target] == 0 {
+          queue.push_back(*target);
+        }
+      }
+    }
+
+    if order.len() < graph.len() {
+      return Ok(None);
+    }
+    Ok(graph.group(vec![order]).pop())
+  }
+}
+
+/// Rates the importance of the nodes of a directed graph by the
+/// importance of the nodes linking to them
+///
+/// The ranks of all nodes add up to 1. Nodes without outgoing edges
+/// distribute their rank evenly over all nodes.
+#[derive(Debug, Clone, Copy)]
+pub struct PageRank {
+  /// The probability to follow an edge instead of jumping to a random
+  /// node
+  pub damping: f64,
+  /// The number of iterations
+  pub iterations: usize,
+}
+
+impl Default for PageRank {
+  fn default() -> Self {
+    PageRank {
+      damping: 0.85,
+      iterations: 20,
+    }
+  }
+}
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<HashMap<N, f64>, E>> for PageRank
+where
+  G: DirectedGraph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  fn filter(&mut self, graph: &'a G) -> Result<HashMap<N, f64>, E> {
+    let graph = Adjacency::read(graph, |g, n| g.outgoing(n))?;
+    if graph.len() == 0 {
+      return Ok(HashMap::new());
+    }
+
+    let n = graph.len() as f64;
+    let mut rank = vec![1.0 / n; graph.len()];
+    for _ in 0..self.iterations {
+      let dangling: f64 = graph.adjacent.iter()
+        .zip(rank.iter())
+        .filter(|(targets, _)| targets.is_empty())
+        .map(|(_, rank)| rank)
+        .sum();
+
+      let mut next = vec![(1.0 - self.damping) / n + self.damping * dangling / n; graph.len()];
+      for (source, targets) in graph.adjacent.iter().enumerate() {
+        let share = self.damping * rank[source] / targets.len() as f64;
+        for target in targets {
+          next[*target] += share;
+        }
+      }
+      rank = next;
+    }
+
+    Ok(graph.node_map(rank))
+  }
+}
+
+/// The degree of every node divided by the number of other nodes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DegreeCentrality;
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<HashMap<N, f64>, E>> for DegreeCentrality
+where
+  G: Graph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  fn filter(&mut self, graph: &'a G) -> Result<HashMap<N, f64>, E> {
+    let nodes: Vec<N> = graph.nodes()?.collect();
+    let others = nodes.len().saturating_sub(1).max(1) as f64;
+
+    nodes.into_iter()
+      .map(|node| {
+        let degree = graph.degree(&node)?;
+        Ok((node, degree as f64 / others))
+      })
+      .collect()
+  }
+}
+
+/// Counts the triangles every node is part of
+///
+/// The direction of the edges is ignored, as well as parallel edges and
+/// edges from a node to itself. The number of triangles in the whole
+/// graph is the sum of all counts divided by three.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TriangleCount;
+
+impl<'a, G, N, E> GraphFilter<&'a G, Result<HashMap<N, usize>, E>> for TriangleCount
+where
+  G: Graph<'a, N, E>,
+  N: Clone + Eq + Hash + 'a,
+{
+  fn filter(&mut self, graph: &'a G) -> Result<HashMap<N, usize>, E> {
+    let graph = Adjacency::read(graph, |g, n| g.neighbors(n))?;
+    let neighbours: Vec<HashSet<usize>> = graph.adjacent.iter()
+      .enumerate()
+      .map(|(i, adjacent)| adjacent.iter().copied().filter(|n| *n != i).collect())
+      .collect();
+
+    // every triangle is found once from its node with the smallest
+    // position
+    let mut triangles = vec![0; graph.len()];
+    for (i, adjacent) in neighbours.iter().enumerate() {
+      for j in adjacent.iter().filter(|j| **j > i) {
+        for k in neighbours[*j].iter().filter(|k| **k > *j && adjacent.contains(k)) {
+          triangles[i] += 1;
+          triangles[*j] += 1;
+          triangles[*k] += 1;
+        }
+      }
+    }
+    Ok(graph.node_map(triangles))
+  }
+}
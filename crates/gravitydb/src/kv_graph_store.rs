@@ -2,15 +2,20 @@ use std::str::FromStr;
 use sha2::Digest;
 use crate::schema::SchemaElement;
 use serde::{Serialize, Deserialize};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
 use crate::{PropertyGraphReader, PropertyFilter};
-use crate::GraphStore;
+use crate::{DeletionMode, GraphStore};
 use crate::GraphBuilder;
 use crate::schema::Property;
 use crate::ql;
+use crate::ql::{MergeContext, OrderedContext};
+use crate::algorithms::SubGraph;
 use core::hash::Hash;
-use crate::KVStore;
+use crate::{KVStore, ScanDirection, ScanRange, TransactionalKVStore};
 use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::ThreadId;
 use thiserror::Error;
 #[cfg(feature="lua")]
 use mlua::FromLua;
@@ -45,6 +50,20 @@ impl Uuid {
   }
 }
 
+impl FromStr for Uuid {
+  type Err = uuid::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::from_key(s)
+  }
+}
+
+impl std::fmt::Display for Uuid {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.to_key())
+  }
+}
+
 pub type HashId = String;
 
 enum BacklinkType {
@@ -54,10 +73,26 @@ enum BacklinkType {
 }
 
 pub type BasicQuery = ql::BasicQuery<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>;
-type QueryResult = ql::QueryResult<VertexId, HashId, HashId>;
+pub type QueryResult = ql::QueryResult<VertexId, HashId, HashId>;
+pub type TypedQueryResult<P> = ql::TypedQueryResult<VertexId, HashId, HashId, P>;
+type TypedElement<Id, P> = ql::TypedElement<Id, HashId, P>;
 
+type VQuery = ql::VertexQuery<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>;
+type EQuery = ql::EdgeQuery<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>;
 type NodeCtx = HashMap<VertexId, ql::VertexQueryContext<VertexId, HashId>>;
 type EdgeCtx = HashMap<HashId, ql::EdgeQueryContext<VertexId, HashId>>;
+/// The ids of the properties of some vertices and the edges between them
+type VerticesAndEdges = (Vec<HashId>, Vec<(HashId, EdgeData)>);
+type IdStream<'a, E> = Box<dyn Iterator<Item = Result<HashId, Error<E>>> + 'a>;
+
+/// The vertices of a query evaluated one after another
+pub type NodeStream<'a, E> = Box<dyn Iterator<Item = Result<(VertexId, ql::VertexQueryContext<VertexId, HashId>), Error<E>>> + 'a>;
+/// The edges of a query evaluated one after another
+pub type EdgeStream<'a, E> = Box<dyn Iterator<Item = Result<(HashId, ql::EdgeQueryContext<VertexId, HashId>), Error<E>>> + 'a>;
+
+/// The traces of the running calls of `explain` (the innermost last)
+/// by thread
+type Traces = HashMap<ThreadId, Vec<Vec<ql::QueryPlan>>>;
 
 pub struct KvGraphStore<T, K, E>
 where
@@ -66,6 +101,15 @@ where
   E: Send,
 {
   kv: K,
+  /// How many (nested) transactions are currently running
+  tx_depth: usize,
+  /// How many calls of `explain` are running (queries are only traced
+  /// while there is one)
+  explaining: AtomicUsize,
+  /// The steps of the queries traced by `explain` which are not
+  /// finished yet (a stack of traces for every thread running an
+  /// `explain`)
+  traces: Mutex<Traces>,
   p_marker: PhantomData<T>,
   kv_err_marker: PhantomData<E>,
 }
@@ -76,9 +120,162 @@ where
   K: KVStore<E>,
   E: Send,
 {
+  /// List the keys of all records starting with a prefix (without the
+  /// prefix)
+  fn list_ids(&self, pfx: &str) -> Result<Vec<HashId>, Error<E>> {
+    self.list_keys(pfx.as_bytes(), b"")?
+      .into_iter()
+      .map(|entry| {
+        let (_, entry) = entry.split_at(pfx.len());
+        Ok(String::from_utf8(entry.to_vec())?)
+      })
+      .collect()
+  }
+
+  /// Iterate over the keys of all records starting with a prefix
+  /// (without the prefix)
+  fn scan_ids(&self, pfx: &str) -> Result<IdStream<'_, E>, Error<E>> {
+    self.count_read();
+    let len = pfx.len();
+    let keys = self.kv.scan_keys(pfx.as_bytes(), b"").map_err(|e| Error::KV(e))?;
+    Ok(Box::new(keys.map(move |entry| {
+      let entry = entry.map_err(|e| Error::KV(e))?;
+      Ok(String::from_utf8(entry[len..].to_vec())?)
+    })))
+  }
+
+  /// The ids of the graph elements connected with the properties of a
+  /// query in the order of the ids
+  ///
+  /// The index of a single property is read lazily, the indexes of
+  /// several properties have to be merged first.
+  fn indexed_ids(&self, q: ql::PropertyQuery<HashId>, prefix: &str) -> Result<IdStream<'_, E>, Error<E>> {
+    let properties: Vec<_> = self.query_properties(q)?.into_iter().collect();
+    if let [prop_id] = properties.as_slice() {
+      return self.scan_ids(&format!("indexes/{prop_id}/{prefix}_"));
+    }
+
+    let mut ids = BTreeSet::new();
+    for prop_id in properties {
+      ids.extend(self.list_ids(&format!("indexes/{prop_id}/{prefix}_"))?);
+    }
+    Ok(Box::new(ids.into_iter().map(Ok)))
+  }
+
+  /// Get all properties with an ordered key in between (and including)
+  /// `from` and `to`
+  fn sorted_properties(&self, from: &ql::OrderedKey, to: &ql::OrderedKey) -> Result<Vec<HashId>, Error<E>> {
+    let start = format!("sorted/{}-", from.truncated(SORTED_KEY_LEN));
+    let end = format!("sorted/{}.", to.truncated(SORTED_KEY_LEN));
+
+    let entries = self.scan_records(ScanRange::Between(start.as_bytes(), end.as_bytes()))?;
+
+    let mut result = Vec::new();
+    for entry in entries {
+      let (path, data) = entry?;
+      let path = String::from_utf8(path)?;
+      let Some((key, hash)) = path["sorted/".len()..].rsplit_once('-') else {
+        return Err(Error::MalformedDB(path));
+      };
+      // Truncated keys are only an approximation of the position
+      if key.len() == 2 * SORTED_KEY_LEN {
+        let key = ql::OrderedKey(String::from_utf8(data)?);
+        if key < *from || key > *to {
+          continue;
+        }
+      }
+      result.push(hash.to_string());
+    }
+
+    Ok(result)
+  }
+
+  /// All JSON pointers with a declared index
+  pub fn json_indexes(&self) -> Result<Vec<String>, Error<E>> {
+    if !self.record_exists(JSON_INDEXES.as_bytes())? {
+      return Ok(Vec::new());
+    }
+    let data = self.read_record(JSON_INDEXES.as_bytes())?;
+    Ok(serde_json::from_slice(&data).map_err(SerialisationError::from)?)
+  }
+
+  /// Add (or remove) a property to (or from) the indexes of the given
+  /// JSON pointers
+  ///
+  /// Properties which are no JSON or don't contain the field are not
+  /// indexed.
+  fn update_json_indexes(&mut self, paths: &[String], hash: &str, data: &[u8], insert: bool) -> Result<(), Error<E>> {
+    let Ok(json) = serde_json::from_slice::<serde_json::Value>(data) else {
+      return Ok(());
+    };
+
+    for path in paths {
+      if let Some(value) = json.pointer(path) {
+        let entry = json_index_path(path, value) + hash;
+        if insert {
+          let (bucket, _) = entry.rsplit_once('/').unwrap();
+          self.kv.create_bucket((bucket.to_string() + "/").as_bytes())
+            .map_err(|e| Error::KV(e))?;
+          let target = "props/".to_string() + hash;
+          self.kv.store_record(entry.as_bytes(), target.as_bytes())
+            .map_err(|e| Error::KV(e))?;
+        } else {
+          self.kv.delete_record(entry.as_bytes()).map_err(|e| Error::KV(e))?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
   pub fn query<Q: Into<BasicQuery>>(&self, q: Q) -> Result<QueryResult, Error<E>> {
-    let q = q.into();
-    let context = match q {
+    self.query_page(q, None)
+  }
+
+  /// Run a query and continue at the cursor of a previous page
+  ///
+  /// If the query ends with a `Limit` step and there are more elements,
+  /// the result contains the cursor of the next page. The cursor replaces
+  /// the `Skip` step before the limit and can only be used with the same
+  /// query.
+  pub fn query_page<Q: Into<BasicQuery>>(&self, q: Q, cursor: Option<&str>) -> Result<QueryResult, Error<E>> {
+    use ql::{EdgeQuery, VertexQuery};
+
+    let context = match q.into() {
+      BasicQuery::V(VertexQuery::Limit(q, limit)) => {
+        let (q, skip) = match *q {
+          VertexQuery::Skip(q, skip) => (*q, skip),
+          q => (q, 0),
+        };
+        let (skip, fingerprint) = page_start(&q, skip, limit, cursor)?;
+        // one more element shows if there is a next page
+        let mut context = self.query_nodes(q.skip(skip).limit(limit.saturating_add(1)))?;
+        let more = split_page(&mut context, limit);
+
+        let mut result: QueryResult = context.into();
+        if more {
+          result.cursor = next_cursor(skip, limit, &fingerprint);
+        }
+        result
+      }
+      BasicQuery::E(EdgeQuery::Limit(q, limit)) => {
+        let (q, skip) = match *q {
+          EdgeQuery::Skip(q, skip) => (*q, skip),
+          q => (q, 0),
+        };
+        let (skip, fingerprint) = page_start(&q, skip, limit, cursor)?;
+        let mut context = self.query_edges(q.skip(skip).limit(limit.saturating_add(1)))?;
+        let more = split_page(&mut context, limit);
+
+        let mut result: QueryResult = context.into();
+        if more {
+          result.cursor = next_cursor(skip, limit, &fingerprint);
+        }
+        result
+      }
+      _ if cursor.is_some() => {
+        return Err(Error::InvalidCursor("the query does not end with a limit".to_string()));
+      }
       BasicQuery::V(q) => {
         self.query_nodes(q)?.into()
       }
@@ -86,7 +283,7 @@ where
         self.query_edges(q)?.into()
       }
       BasicQuery::P(q) => {
-        self.query_property_nodes(q)?.into()
+        self.query_properties(q)?.into()
       }
     };
 
@@ -94,15 +291,22 @@ where
   }
 
   pub fn extract_properties(&self, result: &QueryResult) -> Result<Vec<T>, Error<E>> {
-    let nodes_iter = result.vertices.iter().map(|(n_id, _prop)| {
-      let n = self.read_node(*n_id)?;
-      self.read_property(&n.properties)
+    let nodes_iter = result.vertices.iter().map(|(n_id, prop)| {
+      match prop {
+        Some(p_id) => self.read_property(p_id),
+        None => self.read_property(&self.read_node(*n_id)?.properties),
+      }
+    });
+    let edges_iter = result.edges.iter().map(|(e_id, prop)| {
+      match prop {
+        Some(p_id) => self.read_property(p_id),
+        None => self.read_property(&self.read_edge(e_id)?.properties),
+      }
     });
-    let edges_iter = result.edges.iter().map(|(e_id, _prop)| {
-      let e = self.read_edge(&e_id)?;
-      self.read_property(&e.properties)
+    let properties_iter = result.properties.iter().map(|p_id| {
+      self.read_property(p_id)
     });
-    nodes_iter.chain(edges_iter).collect::<Result<Vec<T>,_>>()
+    nodes_iter.chain(edges_iter).chain(properties_iter).collect::<Result<Vec<T>,_>>()
   }
 
   pub fn extract_path_properties(&self, result: &QueryResult) -> Result<Vec<Vec<T>>, Error<E>> {
@@ -136,10 +340,278 @@ where
       .collect::<Result<Vec<Vec<_>>, _>>()
   }
 
-  fn query_nodes(
+  /// Run a query and read the properties of all elements in the result
+  pub fn query_typed<Q: Into<BasicQuery>>(&self, q: Q) -> Result<TypedQueryResult<T>, Error<E>>
+  where
+    T: Clone,
+  {
+    let result = self.query(q)?;
+    self.resolve(&result, |db, id| db.read_property(id))
+  }
+
+  /// Read the properties of all elements in a query result
+  ///
+  /// `read` deserializes a property by its id (e.g. `read_property` or
+  /// `read_property_json`). Every property is read only once, even if it
+  /// is used by several elements. The ids of the properties are taken
+  /// from the result, only the elements on paths which are not part of
+  /// the result itself have to be read.
+  pub fn resolve<P, F>(&self, result: &QueryResult, read: F) -> Result<TypedQueryResult<P>, Error<E>>
+  where
+    P: Clone,
+    F: Fn(&Self, &HashId) -> Result<P, Error<E>>,
+  {
+    let mut cache = HashMap::new();
+    let mut vertex_properties: HashMap<VertexId, HashId> = result.vertices.iter()
+      .filter_map(|(id, property)| Some((*id, property.clone()?)))
+      .collect();
+    let mut edge_properties: HashMap<HashId, HashId> = result.edges.iter()
+      .filter_map(|(id, property)| Some((id.clone(), property.clone()?)))
+      .collect();
+
+    let vertices = result.vertices.iter()
+      .map(|(id, _)| self.typed_vertex(id, &mut vertex_properties, &mut cache, &read))
+      .collect::<Result<Vec<_>, _>>()?;
+    let edges = result.edges.iter()
+      .map(|(id, _)| self.typed_edge(id, &mut edge_properties, &mut cache, &read))
+      .collect::<Result<Vec<_>, _>>()?;
+    let paths = result.paths.iter()
+      .map(|(start, hops, end)| {
+        Ok(ql::TypedPath {
+          start: start.as_ref()
+            .map(|id| self.typed_edge(id, &mut edge_properties, &mut cache, &read))
+            .transpose()?,
+          hops: hops.iter()
+            .map(|(v_id, e_id)| Ok((
+              self.typed_vertex(v_id, &mut vertex_properties, &mut cache, &read)?,
+              self.typed_edge(e_id, &mut edge_properties, &mut cache, &read)?,
+            )))
+            .collect::<Result<Vec<_>, Error<E>>>()?,
+          end: end.as_ref()
+            .map(|id| self.typed_vertex(id, &mut vertex_properties, &mut cache, &read))
+            .transpose()?,
+        })
+      })
+      .collect::<Result<Vec<_>, Error<E>>>()?;
+    let properties = result.properties.iter()
+      .map(|id| Ok((id.clone(), self.cached_property(id, &mut cache, &read)?)))
+      .collect::<Result<Vec<_>, Error<E>>>()?;
+
+    Ok(ql::TypedQueryResult {
+      vertices,
+      edges,
+      paths,
+      properties,
+      variables: result.variables.clone(),
+    })
+  }
+
+  /// A vertex with its property (the node is only read if the id of its
+  /// property is not known yet)
+  fn typed_vertex<P, F>(
     &self,
-    q: ql::VertexQuery<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>
-  ) -> Result<NodeCtx, Error<E>> {
+    id: &VertexId,
+    properties: &mut HashMap<VertexId, HashId>,
+    cache: &mut HashMap<HashId, P>,
+    read: &F,
+  ) -> Result<TypedElement<VertexId, P>, Error<E>>
+  where
+    P: Clone,
+    F: Fn(&Self, &HashId) -> Result<P, Error<E>>,
+  {
+    let property_id = match properties.get(id) {
+      Some(property_id) => property_id.clone(),
+      None => {
+        let property_id = self.read_node(*id)?.properties;
+        properties.insert(*id, property_id.clone());
+        property_id
+      }
+    };
+    Ok(TypedElement {
+      id: *id,
+      property: self.cached_property(&property_id, cache, read)?,
+      property_id,
+    })
+  }
+
+  /// An edge with its property (the edge is only read if the id of its
+  /// property is not known yet)
+  fn typed_edge<P, F>(
+    &self,
+    id: &HashId,
+    properties: &mut HashMap<HashId, HashId>,
+    cache: &mut HashMap<HashId, P>,
+    read: &F,
+  ) -> Result<TypedElement<HashId, P>, Error<E>>
+  where
+    P: Clone,
+    F: Fn(&Self, &HashId) -> Result<P, Error<E>>,
+  {
+    let property_id = match properties.get(id) {
+      Some(property_id) => property_id.clone(),
+      None => {
+        let property_id = self.read_edge(id)?.properties;
+        properties.insert(id.clone(), property_id.clone());
+        property_id
+      }
+    };
+    Ok(TypedElement {
+      id: id.clone(),
+      property: self.cached_property(&property_id, cache, read)?,
+      property_id,
+    })
+  }
+
+  /// Fill in the ids of the properties of all vertices and edges of a
+  /// query result
+  ///
+  /// `query` only knows the ids of the properties it had to read anyway.
+  /// Every other vertex and edge is read here, so that `resolve` does not
+  /// have to read them again later (e.g. in another process).
+  pub fn with_property_ids(&self, result: QueryResult) -> Result<QueryResult, Error<E>> {
+    let vertices = result.vertices.into_iter()
+      .map(|(id, property)| match property {
+        Some(property) => Ok((id, Some(property))),
+        None => Ok((id, Some(self.read_node(id)?.properties))),
+      })
+      .collect::<Result<_, Error<E>>>()?;
+    let edges = result.edges.into_iter()
+      .map(|(id, property)| match property {
+        Some(property) => Ok((id, Some(property))),
+        None => {
+          let property = self.read_edge(&id)?.properties;
+          Ok((id, Some(property)))
+        }
+      })
+      .collect::<Result<_, Error<E>>>()?;
+    Ok(QueryResult { vertices, edges, ..result })
+  }
+
+  fn cached_property<P, F>(&self, id: &HashId, cache: &mut HashMap<HashId, P>, read: &F) -> Result<P, Error<E>>
+  where
+    P: Clone,
+    F: Fn(&Self, &HashId) -> Result<P, Error<E>>,
+  {
+    if let Some(property) = cache.get(id) {
+      return Ok(property.clone());
+    }
+    let property = read(self, id)?;
+    cache.insert(id.clone(), property.clone());
+    Ok(property)
+  }
+
+  /// The subgraph spanned by the vertices of a query result
+  ///
+  /// The vertices are the ones in the result, on its paths and at the
+  /// ends of its edges. All edges between these vertices are part of the
+  /// subgraph, so the graph algorithms can be run on it.
+  pub fn subgraph(&self, result: &QueryResult) -> Result<SubGraph<VertexId>, Error<E>> {
+    let vertices = self.result_vertices(result)?;
+
+    let mut graph = SubGraph::new();
+    for id in vertices.iter() {
+      graph.add_node(*id);
+    }
+    let (_, edges) = self.edges_between(&vertices)?;
+    for (_, edge) in edges {
+      graph.add_edge(edge.n1, edge.n2);
+    }
+    Ok(graph)
+  }
+
+  /// The vertices of a query result together with all edges between
+  /// them
+  ///
+  /// Like in `subgraph` the vertices on the paths and at the ends of the
+  /// edges belong to the result. Edges which do not lie between two of
+  /// these vertices are removed.
+  pub fn inner_hull(&self, result: &QueryResult) -> Result<QueryResult, Error<E>> {
+    let vertices = self.result_vertices(result)?;
+    self.hull(result, vertices)
+  }
+
+  /// The inner hull of the vertices of a query result and their direct
+  /// neighbours
+  pub fn outer_hull(&self, result: &QueryResult) -> Result<QueryResult, Error<E>> {
+    let mut vertices = self.result_vertices(result)?;
+    for id in vertices.clone() {
+      let neighbours = self.neighbours(id, ql::Direction::Both, &None)?;
+      vertices.extend(neighbours.into_iter().map(|(_, neighbour, _)| neighbour));
+    }
+    let mut contained = HashSet::new();
+    vertices.retain(|id| contained.insert(*id));
+
+    self.hull(result, vertices)
+  }
+
+  /// A copy of the result with the vertices and all edges between them
+  fn hull(&self, result: &QueryResult, vertices: Vec<VertexId>) -> Result<QueryResult, Error<E>> {
+    let (properties, edges) = self.edges_between(&vertices)?;
+
+    let mut hull = QueryResult::new();
+    hull.vertices = vertices.into_iter()
+      .zip(properties)
+      .map(|(id, property)| (id, Some(property)))
+      .collect();
+    hull.edges = edges.into_iter()
+      .map(|(id, edge)| (id, Some(edge.properties)))
+      .collect();
+    hull.paths = result.paths.clone();
+    hull.properties = result.properties.clone();
+    hull.variables = result.variables.clone();
+    Ok(hull)
+  }
+
+  /// The vertices of a query result, on its paths and at the ends of its
+  /// edges (each only once)
+  fn result_vertices(&self, result: &QueryResult) -> Result<Vec<VertexId>, Error<E>> {
+    let mut vertices: Vec<VertexId> = result.vertices.iter().map(|(id, _)| *id).collect();
+    for (id, _) in result.edges.iter() {
+      let edge = self.read_edge(id)?;
+      vertices.push(edge.n1);
+      vertices.push(edge.n2);
+    }
+    for (start, hops, end) in result.paths.iter() {
+      if let Some(id) = start {
+        vertices.push(self.read_edge(id)?.n1);
+      }
+      vertices.extend(hops.iter().map(|(id, _)| *id));
+      vertices.extend(end.iter());
+    }
+
+    let mut contained = HashSet::new();
+    vertices.retain(|id| contained.insert(*id));
+    Ok(vertices)
+  }
+
+  /// All edges leading from one of the vertices to another (together
+  /// with the ids of the properties of the vertices in the same order)
+  fn edges_between(&self, vertices: &[VertexId]) -> Result<VerticesAndEdges, Error<E>> {
+    let contained: HashSet<&VertexId> = vertices.iter().collect();
+    let mut properties = Vec::new();
+    let mut edges = Vec::new();
+    for id in vertices {
+      let node = self.read_node(*id)?;
+      properties.push(node.properties);
+      for edge_id in node.outgoing.into_iter() {
+        let edge = self.read_edge(&edge_id)?;
+        if contained.contains(&edge.n2) {
+          edges.push((edge_id, edge));
+        }
+      }
+    }
+    Ok((properties, edges))
+  }
+
+  fn query_nodes(&self, q: VQuery) -> Result<NodeCtx, Error<E>> {
+    if !self.is_traced() {
+      return self.eval_nodes(q);
+    }
+    let estimated = self.untraced(|| self.estimate_vertices(&q))?;
+    self.traced(ql::QueryPlan::new(q.step_name(), estimated), || self.eval_nodes(q))
+  }
+
+  fn eval_nodes(&self, q: VQuery) -> Result<NodeCtx, Error<E>> {
     use ql::VertexQuery::*;
 
     let result = match q {
@@ -171,10 +643,13 @@ where
         )
       }
       Intersect(sub1, sub2) => {
-        intersection(
-          self.query_nodes(*sub1)?,
-          self.query_nodes(*sub2)?,
-        )
+        // nothing to intersect with if the first part is empty
+        let first = self.query_nodes(*sub1)?;
+        if first.is_empty() {
+          first
+        } else {
+          intersection(first, self.query_nodes(*sub2)?)
+        }
       }
       Substract(sub1, sub2) => {
         substraction(
@@ -188,33 +663,110 @@ where
           self.query_nodes(*sub2)?
         )
       }
-      Store(_q) => unreachable!(),
-      Out(q) => {
-        self.query_edges(q)?.into_iter()
-          .map(|(edge_id, ctx)| {
-            let edge = self.read_edge(&edge_id)?;
-            Ok((edge.n2, ctx.into_vertex_ctx(edge.n2)))
+      Store(q) => {
+        self.query_nodes(*q)?.into_iter()
+          .map(|(id, mut ctx)| {
+            ctx.v_store = HashSet::from([id]);
+            (id, ctx)
           })
-          .collect::<Result<HashMap<_,_>, Error<E>>>()?
+          .collect()
+      }
+      Out(q) => {
+        let mut result = HashMap::default();
+
+        for (edge_id, ctx) in self.query_edges(q)?.into_iter() {
+          let edge = self.read_edge(&edge_id)?;
+          insert_merged(&mut result, edge.n2, ctx.into_vertex_ctx(edge.n2));
+        }
+
+        result
       }
       In(q) => {
-        self.query_edges(q)?.into_iter()
-          .map(|(edge_id, ctx)| {
-            let edge = self.read_edge(&edge_id)?;
-            Ok((edge.n1, ctx.into_vertex_ctx(edge.n1)))
-          })
-          .collect::<Result<HashMap<_,_>, Error<E>>>()?
+        let mut result = HashMap::default();
+
+        for (edge_id, ctx) in self.query_edges(q)?.into_iter() {
+          let edge = self.read_edge(&edge_id)?;
+          insert_merged(&mut result, edge.n1, ctx.into_vertex_ctx(edge.n1));
+        }
+
+        result
+      }
+      Filter(q, filter) => {
+        let runner = filter.runner()?;
+        let mut result = HashMap::default();
+
+        for (id, ctx) in self.query_nodes(*q)?.into_iter() {
+          let node = self.read_node(id)?;
+          let properties = self.read_property_json(&node.properties)?;
+          if runner.matches(&serde_json::json!(id), &properties)? {
+            result.insert(id, ctx);
+          }
+        }
+
+        result
+      }
+      Traverse(q, traversal) => {
+        self.traverse(self.query_nodes(*q)?, traversal)?
+      }
+      ShortestPath(from, to, search) => {
+        self.shortest_paths(self.query_nodes(*from)?, self.query_nodes(*to)?, search)?
+      }
+      Aggregate(q, name, aggregation) => {
+        let mut result = self.query_nodes(*q)?;
+        let properties = result.keys()
+          .map(|id| Ok(self.read_node(*id)?.properties))
+          .collect::<Result<Vec<_>, Error<E>>>()?;
+
+        let value = self.aggregate(properties, &aggregation)?;
+        for ctx in result.values_mut() {
+          ctx.variables.insert(name.clone(), value.clone());
+        }
+        result
+      }
+      Degree(q, name, count) => {
+        let mut result = self.query_nodes(*q)?;
+        let allowed = self.allowed_edges(count.edges)?;
+
+        let mut degrees = serde_json::Map::new();
+        for id in result.keys() {
+          let degree = self.neighbours(*id, count.direction, &allowed)?.len();
+          degrees.insert(id.to_key(), degree.into());
+        }
+        let value = serde_json::Value::Object(degrees);
+        for ctx in result.values_mut() {
+          ctx.variables.insert(name.clone(), value.clone());
+        }
+        result
+      }
+      Order(q, order) => {
+        let result = self.query_nodes(*q)?;
+        let properties = result.keys()
+          .map(|id| Ok((*id, self.read_node(*id)?.properties)))
+          .collect::<Result<Vec<_>, Error<E>>>()?;
+
+        let values = self.field_values(properties, &order.field)?;
+        sort_by_values(result, &values, |id| id.to_key(), order.descending)
+      }
+      Skip(q, n) => {
+        self.stream_nodes(Skip(q, n))?.collect::<Result<_, Error<E>>>()?
+      }
+      Limit(q, n) => {
+        self.stream_nodes(Limit(q, n))?.collect::<Result<_, Error<E>>>()?
       }
-      Filter(_q, _filter) => unreachable!(),
     };
 
     Ok(result)
   }
 
-  fn query_edges(
-    &self,
-    q: ql::EdgeQuery<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>,
-  ) -> Result<EdgeCtx, Error<E>> {
+  fn query_edges(&self, q: EQuery) -> Result<EdgeCtx, Error<E>> {
+    if !self.is_traced() {
+      return self.eval_edges(q);
+    }
+    let estimated = self.untraced(|| self.estimate_edges(&q))?;
+    self.traced(ql::QueryPlan::new(q.step_name(), estimated), || self.eval_edges(q))
+  }
+
+  fn eval_edges(&self, q: EQuery) -> Result<EdgeCtx, Error<E>> {
     use ql::EdgeQuery::*;
 
     let result = match q {
@@ -250,10 +802,13 @@ where
         )
       }
       Intersect(sub1, sub2) => {
-        intersection(
-          self.query_edges(*sub1)?,
-          self.query_edges(*sub2)?,
-        )
+        // nothing to intersect with if the first part is empty
+        let first = self.query_edges(*sub1)?;
+        if first.is_empty() {
+          first
+        } else {
+          intersection(first, self.query_edges(*sub2)?)
+        }
       }
       Substract(sub1, sub2) => {
         substraction(
@@ -267,7 +822,14 @@ where
           self.query_edges(*sub2)?
         )
       }
-      Store(_q) => unreachable!(),
+      Store(q) => {
+        self.query_edges(*q)?.into_iter()
+          .map(|(id, mut ctx)| {
+            ctx.e_store = HashSet::from([id.clone()]);
+            (id, ctx)
+          })
+          .collect()
+      }
       Out(q) => {
         let context = self.query_nodes(*q)?;
 
@@ -277,7 +839,7 @@ where
           let node = self.read_node(node_id)?;
           for edge_id in node.outgoing.into_iter() {
             let key = edge_id.clone();
-            result.insert(edge_id, ctx.clone().into_edge_ctx(key));
+            insert_merged(&mut result, edge_id, ctx.clone().into_edge_ctx(key));
           }
         }
 
@@ -292,71 +854,764 @@ where
           let node = self.read_node(node_id)?;
           for edge_id in node.incoming.into_iter() {
             let key = edge_id.clone();
-            result.insert(edge_id, ctx.clone().into_edge_ctx(key));
+            insert_merged(&mut result, edge_id, ctx.clone().into_edge_ctx(key));
+          }
+        }
+
+        result
+      }
+      Filter(q, filter) => {
+        let runner = filter.runner()?;
+        let mut result = HashMap::default();
+
+        for (id, ctx) in self.query_edges(*q)?.into_iter() {
+          let edge = self.read_edge(&id)?;
+          let properties = self.read_property_json(&edge.properties)?;
+          if runner.matches(&serde_json::json!(id), &properties)? {
+            result.insert(id, ctx);
           }
         }
 
-        result
+        result
+      }
+      Aggregate(q, name, aggregation) => {
+        let mut result = self.query_edges(*q)?;
+        let properties = result.keys()
+          .map(|id| Ok(self.read_edge(id)?.properties))
+          .collect::<Result<Vec<_>, Error<E>>>()?;
+
+        let value = self.aggregate(properties, &aggregation)?;
+        for ctx in result.values_mut() {
+          ctx.variables.insert(name.clone(), value.clone());
+        }
+        result
+      }
+      Order(q, order) => {
+        let result = self.query_edges(*q)?;
+        let properties = result.keys()
+          .map(|id| Ok((id.clone(), self.read_edge(id)?.properties)))
+          .collect::<Result<Vec<_>, Error<E>>>()?;
+
+        let values = self.field_values(properties, &order.field)?;
+        sort_by_values(result, &values, |id| id.clone(), order.descending)
+      }
+      Skip(q, n) => {
+        self.stream_edges(Skip(q, n))?.collect::<Result<_, Error<E>>>()?
+      }
+      Limit(q, n) => {
+        self.stream_edges(Limit(q, n))?.collect::<Result<_, Error<E>>>()?
+      }
+    };
+
+    Ok(result)
+  }
+
+  /// Follow the edges hop by hop (breadth first) from every start
+  /// vertex
+  ///
+  /// A path never visits a vertex twice and a vertex is only expanded
+  /// once per depth below `min` and once beyond it, so cycles end the
+  /// traversal. Every vertex keeps the (shortest) path it was reached
+  /// on first.
+  fn traverse(&self, start: NodeCtx, traversal: ql::Traversal<HashId>) -> Result<NodeCtx, Error<E>> {
+    let ql::Traversal { direction, min, max, edges } = traversal;
+    let allowed = self.allowed_edges(edges)?;
+
+    let mut result = HashMap::default();
+
+    for (start_id, start_ctx) in start.into_iter() {
+      let mut visited = HashSet::new();
+      let mut frontier = HashMap::from([(start_id, start_ctx)]);
+      let mut depth = 0;
+
+      loop {
+        frontier.retain(|id, _| visited.insert((*id, depth.min(min))));
+        if frontier.is_empty() {
+          break;
+        }
+        if depth >= min {
+          for (id, ctx) in frontier.iter() {
+            insert_merged(&mut result, *id, ctx.clone());
+          }
+        }
+        if max == Some(depth) {
+          break;
+        }
+
+        let mut next = HashMap::default();
+        for (node_id, ctx) in frontier.into_iter() {
+          for (edge_id, target, _edge) in self.neighbours(node_id, direction, &allowed)? {
+            // the last hops of the path are the ones of this traversal
+            let hops = &ctx.path[ctx.path.len() - depth..];
+            if target == node_id || hops.iter().any(|(id, _)| *id == target) {
+              continue;
+            }
+            let ctx = ctx.clone().into_edge_ctx(edge_id).into_vertex_ctx(target);
+            insert_merged(&mut next, target, ctx);
+          }
+        }
+
+        frontier = next;
+        depth += 1;
+      }
+    }
+
+    Ok(result)
+  }
+
+  /// Search the shortest paths from the start vertices to the targets
+  ///
+  /// Without a weight the edges are followed breadth first, otherwise
+  /// the paths are searched with Dijkstra's algorithm. Targets which
+  /// can not be reached are not part of the result.
+  fn shortest_paths(&self, start: NodeCtx, targets: NodeCtx, search: ql::PathSearch<HashId>) -> Result<NodeCtx, Error<E>> {
+    let ql::PathSearch { direction, edges, weight } = search;
+    let allowed = self.allowed_edges(edges)?;
+    let mut remaining: HashSet<VertexId> = targets.into_keys().collect();
+    let mut result = HashMap::default();
+
+    match weight {
+      None => {
+        let mut visited = HashSet::new();
+        let mut frontier = start;
+
+        loop {
+          frontier.retain(|id, _| visited.insert(*id));
+          if frontier.is_empty() || remaining.is_empty() {
+            break;
+          }
+
+          let mut next = HashMap::default();
+          for (node_id, ctx) in frontier.into_iter() {
+            if remaining.remove(&node_id) {
+              result.insert(node_id, ctx.clone());
+            }
+            for (edge_id, target, _edge) in self.neighbours(node_id, direction, &allowed)? {
+              if !visited.contains(&target) {
+                let ctx = ctx.clone().into_edge_ctx(edge_id).into_vertex_ctx(target);
+                insert_merged(&mut next, target, ctx);
+              }
+            }
+          }
+          frontier = next;
+        }
+      }
+      Some(pointer) => {
+        let mut distances = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for id in start.keys() {
+          distances.insert(*id, 0.0);
+          queue.push(Candidate { distance: 0.0, id: *id });
+        }
+        let mut contexts = start;
+        let mut done = HashSet::new();
+
+        while let Some(Candidate { distance, id }) = queue.pop() {
+          if remaining.is_empty() {
+            break;
+          }
+          if !done.insert(id) {
+            continue;
+          }
+
+          let ctx = contexts[&id].clone();
+          if remaining.remove(&id) {
+            result.insert(id, ctx.clone());
+          }
+          for (edge_id, target, edge) in self.neighbours(id, direction, &allowed)? {
+            if done.contains(&target) {
+              continue;
+            }
+            let distance = distance + self.edge_weight(&edge_id, &edge, &pointer)?;
+            if distances.get(&target).is_none_or(|known| distance < *known) {
+              distances.insert(target, distance);
+              contexts.insert(target, ctx.clone().into_edge_ctx(edge_id).into_vertex_ctx(target));
+              queue.push(Candidate { distance, id: target });
+            }
+          }
+        }
+      }
+    }
+
+    Ok(result)
+  }
+
+  /// The ids of the edges with one of the properties (`None` allows all
+  /// edges)
+  fn allowed_edges(&self, edges: Option<ql::PropertyQuery<HashId>>) -> Result<Option<HashSet<HashId>>, Error<E>> {
+    Ok(match edges {
+      Some(q) => Some(self.query_edges(ql::EdgeQuery::Property(q))?.into_keys().collect()),
+      None => None,
+    })
+  }
+
+  /// The edges of a vertex in a direction together with the vertex on
+  /// their other side
+  fn neighbours(
+    &self,
+    id: VertexId,
+    direction: ql::Direction,
+    allowed: &Option<HashSet<HashId>>,
+  ) -> Result<Vec<(HashId, VertexId, EdgeData)>, Error<E>> {
+    use ql::Direction::*;
+
+    let node = self.read_node(id)?;
+    let edges = match direction {
+      Outgoing => node.outgoing,
+      Ingoing => node.incoming,
+      Both => node.outgoing.into_iter().chain(node.incoming).collect(),
+    };
+
+    let mut neighbours = Vec::new();
+    for edge_id in edges.into_iter() {
+      if allowed.as_ref().is_some_and(|allowed| !allowed.contains(&edge_id)) {
+        continue;
+      }
+      let edge = self.read_edge(&edge_id)?;
+      let target = if edge.n1 == id { edge.n2 } else { edge.n1 };
+      neighbours.push((edge_id, target, edge));
+    }
+    Ok(neighbours)
+  }
+
+  /// The weight of an edge at the JSON pointer of its properties
+  fn edge_weight(&self, id: &HashId, edge: &EdgeData, pointer: &str) -> Result<f64, Error<E>> {
+    let properties = self.read_property_json(&edge.properties)?;
+    match properties.pointer(pointer).and_then(|w| w.as_f64()) {
+      Some(weight) if weight >= 0.0 => Ok(weight),
+      Some(weight) => Err(Error::InvalidWeight(id.clone(), format!("negative weight {weight}"))),
+      None => Err(Error::InvalidWeight(id.clone(), format!("no number at {pointer}"))),
+    }
+  }
+
+  /// Sum up elements by their properties (see `ql::Aggregation`)
+  fn aggregate(&self, properties: Vec<HashId>, aggregation: &ql::Aggregation) -> Result<serde_json::Value, Error<E>> {
+    use ql::Aggregation::*;
+
+    let (pointer, nested) = match aggregation {
+      Count => return Ok(properties.len().into()),
+      GroupBy(pointer) => (pointer, false),
+      GroupByNested(pointer) => (pointer, true),
+    };
+
+    // every property is only read once
+    let mut counts: HashMap<HashId, usize> = HashMap::new();
+    for id in properties {
+      *counts.entry(id).or_insert(0) += 1;
+    }
+    if nested {
+      let mut nested_counts = HashMap::new();
+      for (id, count) in counts {
+        for nested_id in self.query_properties(ql::PropertyQuery::from_id(id).referenced_properties())? {
+          *nested_counts.entry(nested_id).or_insert(0) += count;
+        }
+      }
+      counts = nested_counts;
+    }
+
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    for (id, count) in counts {
+      let key = match self.read_property_json(&id)?.pointer(pointer) {
+        Some(serde_json::Value::String(key)) => key.clone(),
+        Some(value) => value.to_string(),
+        None => continue,
+      };
+      *groups.entry(key).or_insert(0) += count;
+    }
+    Ok(serde_json::Value::Object(
+      groups.into_iter().map(|(key, count)| (key, count.into())).collect()
+    ))
+  }
+
+  /// The values at the JSON pointer into the properties of the elements
+  /// (none if there is no pointer)
+  fn field_values<Id: Eq + Hash>(
+    &self,
+    properties: Vec<(Id, HashId)>,
+    pointer: &Option<String>,
+  ) -> Result<HashMap<Id, serde_json::Value>, Error<E>> {
+    let Some(pointer) = pointer else {
+      return Ok(HashMap::new());
+    };
+
+    let mut values = HashMap::new();
+    for (id, property) in properties {
+      if let Some(value) = self.read_property_json(&property)?.pointer(pointer) {
+        values.insert(id, value.clone());
+      }
+    }
+    Ok(values)
+  }
+
+  /// Read a property as json to hand it over to filters
+  ///
+  /// Properties which are not stored as json are handed over as string.
+  pub fn read_property_json(&self, id: &HashId) -> Result<serde_json::Value, Error<E>> {
+    let path = "props/".to_string() + id;
+    let data = self.read_record(path.as_bytes())?;
+    Ok(match serde_json::from_slice(&data) {
+      Ok(value) => value,
+      Err(_) => serde_json::Value::String(String::from_utf8_lossy(&data).to_string()),
+    })
+  }
+
+  fn query_properties(
+    &self,
+    q: ql::PropertyQuery<HashId>
+  ) -> Result<HashSet<HashId>, Error<E>> {
+    use ql::PropertyQuery::*;
+
+    let mut result = HashSet::default();
+
+    match q {
+      Specific(id) => {
+        let path = "props/".to_string() + &id;
+        if self.record_exists(path.as_bytes())? {
+          result.insert(id);
+        }
+      }
+      FromTo(from, to) => {
+        result.extend(self.sorted_properties(&from, &to)?);
+      }
+      ReferencingProperties(q) => {
+        for prop_id in self.query_properties(*q)? {
+          for id in self.properties(PropertyFilter::Only(prop_id))? {
+            result.insert(id);
+          }
+        }
+      }
+      ReferencedProperties(q) => {
+        for prop_id in self.query_properties(*q)? {
+          let property: T = self.read_property(&prop_id)?;
+          for nested in property.nested() {
+            result.insert(nested.get_key());
+          }
+        }
+      }
+      JsonField(path, value) => {
+        if !self.json_indexes()?.contains(&path) {
+          return Err(Error::MissingIndex(path));
+        }
+        result.extend(self.list_ids(&json_index_path(&path, &value))?);
+      }
+    };
+
+    Ok(result)
+  }
+
+  /// Run a query and trace the evaluation of every step
+  ///
+  /// The plan shows for every step the estimated and the actual number
+  /// of elements and the records it read from the kv store.
+  pub fn explain<Q: Into<BasicQuery>>(&self, q: Q) -> Result<ql::QueryPlan, Error<E>> {
+    let q = q.into();
+    // collects the reads of steps which are not traced on their own
+    // (e.g. property queries)
+    let root = match &q {
+      BasicQuery::V(q) => ql::QueryPlan::new(q.step_name(), None),
+      BasicQuery::E(q) => ql::QueryPlan::new(q.step_name(), None),
+      BasicQuery::P(q) => ql::QueryPlan::new(q.step_name(), None),
+    };
+    self.traces().entry(std::thread::current().id()).or_default().push(vec![root]);
+    self.explaining.fetch_add(1, Ordering::SeqCst);
+
+    let actual = match q {
+      BasicQuery::V(q) => self.query_nodes(q).map(|result| result.len()),
+      BasicQuery::E(q) => self.query_edges(q).map(|result| result.len()),
+      BasicQuery::P(q) => self.query_properties(q).map(|result| result.len()),
+    };
+
+    let mut plan = self.pop_trace()
+      .and_then(|mut trace| trace.pop())
+      .expect("the trace is never empty while running");
+    self.explaining.fetch_sub(1, Ordering::SeqCst);
+
+    plan.actual = actual?;
+    Ok(match plan.steps.pop() {
+      Some(step) => step,
+      None => plan,
+    })
+  }
+
+  /// The traces of all running calls of `explain`
+  fn traces(&self) -> MutexGuard<'_, Traces> {
+    self.traces.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Remove the trace of the innermost `explain` running in this thread
+  fn pop_trace(&self) -> Option<Vec<ql::QueryPlan>> {
+    let thread = std::thread::current().id();
+    let mut traces = self.traces();
+    let stack = traces.get_mut(&thread)?;
+    let trace = stack.pop();
+    if stack.is_empty() {
+      traces.remove(&thread);
+    }
+    trace
+  }
+
+  /// Change the trace of the innermost `explain` running in this thread
+  /// (if any)
+  fn with_trace<R>(&self, f: impl FnOnce(&mut Vec<ql::QueryPlan>) -> R) -> Option<R> {
+    // ordinary queries do not need to wait for the lock
+    if self.explaining.load(Ordering::SeqCst) == 0 {
+      return None;
+    }
+    self.traces().get_mut(&std::thread::current().id())
+      .and_then(|stack| stack.last_mut())
+      .map(f)
+  }
+
+  fn is_traced(&self) -> bool {
+    self.with_trace(|trace| !trace.is_empty()).unwrap_or(false)
+  }
+
+  /// Run a step and add it to the steps of the step running it
+  fn traced<Id, C>(
+    &self,
+    plan: ql::QueryPlan,
+    run: impl FnOnce() -> Result<HashMap<Id, C>, Error<E>>,
+  ) -> Result<HashMap<Id, C>, Error<E>> {
+    self.with_trace(|trace| trace.push(plan));
+    let result = run();
+
+    self.with_trace(|trace| {
+      let mut plan = trace.pop().expect("the step was pushed before");
+      if let (Ok(result), Some(parent)) = (&result, trace.last_mut()) {
+        plan.actual = result.len();
+        parent.steps.push(plan);
+      }
+    });
+    result
+  }
+
+  /// Run something without tracing it (e.g. the estimates of `explain`)
+  fn untraced<R>(&self, run: impl FnOnce() -> R) -> R {
+    // an empty trace on top of the stack records nothing
+    let thread = std::thread::current().id();
+    let traced = self.with_trace(|_| ()).is_some();
+    if traced {
+      self.traces().entry(thread).or_default().push(Vec::new());
+    }
+    let result = run();
+    if traced {
+      self.pop_trace();
+    }
+    result
+  }
+
+  /// Read a record from the kv store (counted by `explain`)
+  fn read_record(&self, key: &[u8]) -> Result<Vec<u8>, Error<E>> {
+    self.count_read();
+    self.kv.fetch_record(key).map_err(|e| Error::KV(e))
+  }
+
+  /// Check if a record exists in the kv store (counted by `explain`)
+  fn record_exists(&self, key: &[u8]) -> Result<bool, Error<E>> {
+    self.count_read();
+    self.kv.exists(key).map_err(|e| Error::KV(e))
+  }
+
+  /// List records in the kv store (counted by `explain` as one read)
+  fn list_keys(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, Error<E>> {
+    self.count_read();
+    self.kv.list_records(from, to).map_err(|e| Error::KV(e))
+  }
+
+  /// Read the keys and values of records in the kv store (counted by
+  /// `explain` as one read)
+  fn scan_records(&self, range: ScanRange<'_>) -> Result<crate::RecordIterator<'_, Error<E>>, Error<E>> {
+    self.count_read();
+    let records = self.kv.scan(range, ScanDirection::Forward).map_err(|e| Error::KV(e))?;
+    Ok(Box::new(records.map(|record| record.map_err(|e| Error::KV(e)))))
+  }
+
+  fn count_read(&self) {
+    self.with_trace(|trace| {
+      if let Some(step) = trace.last_mut() {
+        step.reads += 1;
+      }
+    });
+  }
+
+  /// Rewrite a query so that it can be evaluated faster
+  ///
+  /// The operands of intersections are reordered to start with the one
+  /// which is expected to have the fewest elements (e.g. the most
+  /// selective property index). If it is empty the other operands are
+  /// not evaluated at all. The paths in the result are the ones of the
+  /// first operand, so they may differ from the original query.
+  pub fn optimize<Q: Into<BasicQuery>>(&self, q: Q) -> Result<BasicQuery, Error<E>> {
+    Ok(match q.into() {
+      BasicQuery::V(q) => self.optimize_vertices(q)?.into(),
+      BasicQuery::E(q) => self.optimize_edges(q)?.into(),
+      BasicQuery::P(q) => q.into(),
+    })
+  }
+
+  fn optimize_vertices(&self, q: VQuery) -> Result<VQuery, Error<E>> {
+    use ql::VertexQuery::*;
+
+    let optimize = |q: Box<VQuery>| -> Result<Box<VQuery>, Error<E>> {
+      Ok(Box::new(self.optimize_vertices(*q)?))
+    };
+    Ok(match q {
+      All | Specific(_) | Property(_) => q,
+      Out(q) => Out(self.optimize_edges(q)?),
+      In(q) => In(self.optimize_edges(q)?),
+      Union(q1, q2) => Union(optimize(q1)?, optimize(q2)?),
+      Intersect(q1, q2) => {
+        let mut operands = Vec::new();
+        for q in flatten_vertex_intersection(Intersect(q1, q2)) {
+          let q = self.optimize_vertices(q)?;
+          operands.push((self.estimate_vertices(&q)?, q));
+        }
+        operands.sort_by_key(|(estimated, _)| (estimated.is_none(), *estimated));
+
+        let mut operands = operands.into_iter().map(|(_, q)| q);
+        let first = operands.next().expect("an intersection has operands");
+        operands.fold(first, |q1, q2| q1.intersect(q2))
+      }
+      Substract(q1, q2) => Substract(optimize(q1)?, optimize(q2)?),
+      DisjunctiveUnion(q1, q2) => DisjunctiveUnion(optimize(q1)?, optimize(q2)?),
+      Filter(q, filter) => Filter(optimize(q)?, filter),
+      Store(q) => Store(optimize(q)?),
+      Traverse(q, traversal) => Traverse(optimize(q)?, traversal),
+      ShortestPath(from, to, search) => ShortestPath(optimize(from)?, optimize(to)?, search),
+      Aggregate(q, name, aggregation) => Aggregate(optimize(q)?, name, aggregation),
+      Degree(q, name, count) => Degree(optimize(q)?, name, count),
+      Order(q, order) => Order(optimize(q)?, order),
+      Skip(q, n) => Skip(optimize(q)?, n),
+      Limit(q, n) => Limit(optimize(q)?, n),
+    })
+  }
+
+  fn optimize_edges(&self, q: EQuery) -> Result<EQuery, Error<E>> {
+    use ql::EdgeQuery::*;
+
+    let optimize = |q: Box<EQuery>| -> Result<Box<EQuery>, Error<E>> {
+      Ok(Box::new(self.optimize_edges(*q)?))
+    };
+    Ok(match q {
+      All | Specific(_) | Property(_) => q,
+      Out(q) => Out(Box::new(self.optimize_vertices(*q)?)),
+      In(q) => In(Box::new(self.optimize_vertices(*q)?)),
+      Union(q1, q2) => Union(optimize(q1)?, optimize(q2)?),
+      Intersect(q1, q2) => {
+        let mut operands = Vec::new();
+        for q in flatten_edge_intersection(Intersect(q1, q2)) {
+          let q = self.optimize_edges(q)?;
+          operands.push((self.estimate_edges(&q)?, q));
+        }
+        operands.sort_by_key(|(estimated, _)| (estimated.is_none(), *estimated));
+
+        let mut operands = operands.into_iter().map(|(_, q)| q);
+        let first = operands.next().expect("an intersection has operands");
+        operands.fold(first, |q1, q2| q1.intersect(q2))
+      }
+      Substract(q1, q2) => Substract(optimize(q1)?, optimize(q2)?),
+      DisjunctiveUnion(q1, q2) => DisjunctiveUnion(optimize(q1)?, optimize(q2)?),
+      Filter(q, filter) => Filter(optimize(q)?, filter),
+      Store(q) => Store(optimize(q)?),
+      Aggregate(q, name, aggregation) => Aggregate(optimize(q)?, name, aggregation),
+      Order(q, order) => Order(optimize(q)?, order),
+      Skip(q, n) => Skip(optimize(q)?, n),
+      Limit(q, n) => Limit(optimize(q)?, n),
+    })
+  }
+
+  /// The number of vertices a query is expected to select (`None` if it
+  /// can not be known without running the query)
+  fn estimate_vertices(&self, q: &VQuery) -> Result<Option<usize>, Error<E>> {
+    use ql::VertexQuery::*;
+
+    Ok(match q {
+      All => Some(self.nodes(PropertyFilter::All)?.count()),
+      Specific(ids) => Some(ids.len()),
+      Property(q) => {
+        let mut count = 0;
+        for prop_id in self.query_properties(q.clone())? {
+          count += self.nodes(PropertyFilter::Only(prop_id))?.count();
+        }
+        Some(count)
+      }
+      // every edge leads to exactly one vertex
+      Out(q) | In(q) => self.estimate_edges(q)?,
+      Union(q1, q2) | DisjunctiveUnion(q1, q2) => {
+        match (self.estimate_vertices(q1)?, self.estimate_vertices(q2)?) {
+          (Some(n1), Some(n2)) => Some(n1 + n2),
+          _ => None,
+        }
+      }
+      Intersect(q1, q2) => {
+        match (self.estimate_vertices(q1)?, self.estimate_vertices(q2)?) {
+          (Some(n1), Some(n2)) => Some(n1.min(n2)),
+          (n1, n2) => n1.or(n2),
+        }
+      }
+      Substract(q, _) | Filter(q, _) | Store(q) | Aggregate(q, ..) | Degree(q, ..) | Order(q, _) => {
+        self.estimate_vertices(q)?
+      }
+      Skip(q, n) => self.estimate_vertices(q)?.map(|count| count.saturating_sub(*n)),
+      Limit(q, n) => Some(self.estimate_vertices(q)?.map_or(*n, |count| count.min(*n))),
+      Traverse(..) => None,
+      ShortestPath(_, to, _) => self.estimate_vertices(to)?,
+    })
+  }
+
+  /// The number of edges a query is expected to select (`None` if it can
+  /// not be known without running the query)
+  fn estimate_edges(&self, q: &EQuery) -> Result<Option<usize>, Error<E>> {
+    use ql::EdgeQuery::*;
+
+    Ok(match q {
+      All => Some(self.edges(PropertyFilter::All)?.count()),
+      Specific(ids) => Some(ids.len()),
+      Property(q) => {
+        let mut count = 0;
+        for prop_id in self.query_properties(q.clone())? {
+          count += self.edges(PropertyFilter::Only(prop_id))?.count();
+        }
+        Some(count)
       }
-      Filter(_q, _filter) => unreachable!(),
-    };
-
-    Ok(result)
+      // the number of edges of the vertices is not known in advance
+      Out(_) | In(_) => None,
+      Union(q1, q2) | DisjunctiveUnion(q1, q2) => {
+        match (self.estimate_edges(q1)?, self.estimate_edges(q2)?) {
+          (Some(n1), Some(n2)) => Some(n1 + n2),
+          _ => None,
+        }
+      }
+      Intersect(q1, q2) => {
+        match (self.estimate_edges(q1)?, self.estimate_edges(q2)?) {
+          (Some(n1), Some(n2)) => Some(n1.min(n2)),
+          (n1, n2) => n1.or(n2),
+        }
+      }
+      Substract(q, _) | Filter(q, _) | Store(q) | Aggregate(q, ..) | Order(q, _) => {
+        self.estimate_edges(q)?
+      }
+      Skip(q, n) => self.estimate_edges(q)?.map(|count| count.saturating_sub(*n)),
+      Limit(q, n) => Some(self.estimate_edges(q)?.map_or(*n, |count| count.min(*n))),
+    })
   }
 
-  fn query_property_nodes(
-    &self,
-    q: ql::PropertyQuery<HashId>
-  ) -> Result<NodeCtx, Error<E>> {
-    let result = HashMap::default();
+  /// Evaluate a vertex query lazily
+  ///
+  /// The vertices come in the order of their positions (and ids) just
+  /// like in a `QueryResult`. The steps `All`, `Specific`, `Property`,
+  /// `Store`, `Filter`, `Skip` and `Limit` hand on one vertex after
+  /// another, so a limit stops reading from the kv store as soon as it
+  /// has enough vertices. All other steps are evaluated completely
+  /// before their first vertex is handed on.
+  pub fn stream_nodes(&self, q: VQuery) -> Result<NodeStream<'_, E>, Error<E>> {
+    use ql::VertexQuery::*;
 
-    let _properties = self.query_properties(q)?;
-    // TODO Wie bei ReferencedProperties properties aber Verweise auf Knoten herausfiltern
+    let stream: NodeStream<'_, E> = match q {
+      All => {
+        Box::new(self.scan_ids("nodes/")?.map(|id| {
+          let id = Uuid::from_key(&id?)?;
+          Ok((id, ql::VertexQueryContext::new(id)))
+        }))
+      }
+      Specific(mut ids) => {
+        ids.sort_by_cached_key(|id| id.to_key());
+        ids.dedup();
+        Box::new(ids.into_iter().map(|id| Ok((id, ql::VertexQueryContext::new(id)))))
+      }
+      Property(q) => {
+        Box::new(self.indexed_ids(q, "nodes")?.map(|id| {
+          let id = Uuid::from_key(&id?)?;
+          Ok((id, ql::VertexQueryContext::new(id)))
+        }))
+      }
+      Store(q) => {
+        Box::new(self.stream_nodes(*q)?.map(|element| {
+          let (id, mut ctx) = element?;
+          ctx.v_store = HashSet::from([id]);
+          Ok((id, ctx))
+        }))
+      }
+      Filter(q, filter) => {
+        let runner = filter.runner()?;
+        Box::new(self.stream_nodes(*q)?
+          .map(move |element| {
+            let (id, ctx) = element?;
+            let node = self.read_node(id)?;
+            let properties = self.read_property_json(&node.properties)?;
+            Ok(runner.matches(&serde_json::json!(id), &properties)?.then_some((id, ctx)))
+          })
+          .filter_map(Result::transpose))
+      }
+      Skip(q, n) => paged(self.stream_nodes(*q)?, n, None),
+      Limit(q, n) => paged(self.stream_nodes(*q)?, 0, Some(n)),
+      q => {
+        Box::new(in_order(self.query_nodes(q)?, |id| id.to_key()).into_iter().map(Ok))
+      }
+    };
 
-    Ok(result)
+    Ok(stream)
   }
 
-  fn query_properties(
-    &self,
-    q: ql::PropertyQuery<HashId>
-  ) -> Result<HashSet<HashId>, Error<E>> {
-    use ql::PropertyQuery::*;
-
-    let mut result = HashSet::default();
+  /// Evaluate an edge query lazily (see `stream_nodes`)
+  pub fn stream_edges(&self, q: EQuery) -> Result<EdgeStream<'_, E>, Error<E>> {
+    use ql::EdgeQuery::*;
 
-    match q {
-      Specific(id) => {
-        let path = "props/".to_string() + &id;
-        if self.kv.exists(path.as_bytes())
-          .map_err(|e| Error::KV(e))?
-        {
-          result.insert(id);
-        }
+    let stream: EdgeStream<'_, E> = match q {
+      All => {
+        Box::new(self.scan_ids("edges/")?.map(|id| {
+          let id = id?;
+          let key = id.clone();
+          Ok((id, ql::EdgeQueryContext::new(key)))
+        }))
       }
-      FromTo(id1, id2) => {
-        for id in self.properties(PropertyFilter::FromTo(id1, id2))? {
-          result.insert(id);
-        }
+      Specific(mut ids) => {
+        ids.sort();
+        ids.dedup();
+        Box::new(ids.into_iter().map(|id| Ok((id.clone(), ql::EdgeQueryContext::new(id)))))
       }
-      ReferencingProperties(q) => {
-        for prop_id in self.query_properties(*q)? {
-          for id in self.properties(PropertyFilter::Only(prop_id))? {
-            result.insert(id);
-          }
-        }
+      Property(q) => {
+        Box::new(self.indexed_ids(q, "edges")?.map(|id| {
+          let id = id?;
+          let key = id.clone();
+          Ok((id, ql::EdgeQueryContext::new(key)))
+        }))
+      }
+      Store(q) => {
+        Box::new(self.stream_edges(*q)?.map(|element| {
+          let (id, mut ctx) = element?;
+          ctx.e_store = HashSet::from([id.clone()]);
+          Ok((id, ctx))
+        }))
+      }
+      Filter(q, filter) => {
+        let runner = filter.runner()?;
+        Box::new(self.stream_edges(*q)?
+          .map(move |element| {
+            let (id, ctx) = element?;
+            let edge = self.read_edge(&id)?;
+            let properties = self.read_property_json(&edge.properties)?;
+            Ok(runner.matches(&serde_json::json!(id), &properties)?.then_some((id, ctx)))
+          })
+          .filter_map(Result::transpose))
       }
-      ReferencedProperties(_q) => {
-        // TODO Hier benötigen wir das Schema
+      Skip(q, n) => paged(self.stream_edges(*q)?, n, None),
+      Limit(q, n) => paged(self.stream_edges(*q)?, 0, Some(n)),
+      q => {
+        Box::new(in_order(self.query_edges(q)?, |id| id.clone()).into_iter().map(Ok))
       }
     };
 
-    Ok(result)
+    Ok(stream)
   }
 
   pub fn from_kv(kv: K) -> Self {
     KvGraphStore {
       p_marker: PhantomData,
       kv_err_marker: PhantomData,
+      tx_depth: 0,
+      explaining: AtomicUsize::new(0),
+      traces: Mutex::default(),
       kv,
     }
   }
@@ -407,46 +1662,25 @@ where
   fn filter_by_property(&self, prefix: &str, filter: PropertyFilter<HashId>) -> Result<impl Iterator<Item=HashId>, Error<E>> {
     use PropertyFilter::*;
 
-    let pfx = match &filter {
+    let ids = match filter {
       Only(prop_id) => {
-        format!("indexes/{prop_id}/{prefix}_").as_bytes().to_vec()
+        self.list_ids(&format!("indexes/{prop_id}/{prefix}_"))?
       },
-      FromTo(_from, _to) => {
-        b"indexes".to_vec()
+      FromTo(from, to) => {
+        let mut ids = Vec::new();
+        for prop_id in self.sorted_properties(&from, &to)? {
+          ids.extend(self.list_ids(&format!("indexes/{prop_id}/{prefix}_"))?);
+        }
+        ids.sort();
+        ids.dedup();
+        ids
       },
       All => {
-        format!("{prefix}/").as_bytes().to_vec()
+        self.list_ids(&format!("{prefix}/"))?
       },
     };
-    let iter = self.kv.list_records(&pfx, b"")
-      .map_err(|e| Error::KV(e))?
-      .into_iter()
-      .map(|entry| {
-        let (_, entry) = entry.split_at(pfx.len());
-        Ok(String::from_utf8(entry.to_vec())?)
-      });
-
-    let iter = match filter {
-      FromTo(from, to) => {
-        iter
-          .filter_map(|entry: Result<_, Error<E>>| {
-            Some(match entry.ok()?.split_once(&format!("/{prefix}_")) {
-              Some((_node_id, prop_id)) => {
-                if *prop_id < *from || *prop_id > *to {
-                  return None;
-                }
-                Ok(prop_id.to_string())
-              },
-              None => { return None; },
-            })
-          })
-          .collect::<Result<Vec<_>, Error<E>>>()?.into_iter()
-      }
-      All | Only(_) => iter
-        .collect::<Result<Vec<_>, Error<E>>>()?.into_iter()
-    };
 
-    Ok(iter)
+    Ok(ids.into_iter())
   }
 }
 
@@ -458,6 +1692,14 @@ pub enum Error<E: Send> {
   NodeExists(String),
   #[error("the element existed before")]
   ExistedBefore,
+  #[error("node {0} still has edges")]
+  NodeHasEdges(String),
+  #[error("there is no index for the field {0}")]
+  MissingIndex(String),
+  #[error("invalid weight on edge {0}: {1}")]
+  InvalidWeight(String, String),
+  #[error("invalid cursor: {0}")]
+  InvalidCursor(String),
   #[error("wrongly formatted input: {0}")]
   MalformedInput(#[from] std::string::FromUtf8Error),
   #[error("uuid parsing error (corrupted db)")]
@@ -466,6 +1708,8 @@ pub enum Error<E: Send> {
   KV(E),
   #[error(transparent)]
   Prop(#[from] SerialisationError),
+  #[error(transparent)]
+  Filter(#[from] ql::FilterError),
 }
 
 #[derive(Error, Debug)]
@@ -474,6 +1718,88 @@ pub enum SerialisationError {
   Json { #[from] source: serde_json::Error },
 }
 
+impl<T, K, E> KvGraphStore<T, K, E>
+where
+  T: Property<HashId, SerialisationError>,
+  K: TransactionalKVStore<E>,
+  E: Send,
+{
+  /// Run several operations as one atomic unit.
+  ///
+  /// If `f` fails, all changes it made are rolled back. Transactions
+  /// can be nested, only the outermost one commits or rolls back the
+  /// changes in the underlying `KVStore`.
+  pub fn transaction<R, F>(&mut self, f: F) -> Result<R, Error<E>>
+  where
+    F: FnOnce(&mut Self) -> Result<R, Error<E>>,
+  {
+    if self.tx_depth == 0 {
+      self.kv.begin_transaction().map_err(|e| Error::KV(e))?;
+    }
+
+    self.tx_depth += 1;
+    let result = f(self);
+    self.tx_depth -= 1;
+
+    if self.tx_depth == 0 {
+      match result {
+        Ok(_) => self.kv.commit_transaction().map_err(|e| Error::KV(e))?,
+        Err(_) => self.kv.rollback_transaction().map_err(|e| Error::KV(e))?,
+      }
+    }
+
+    result
+  }
+
+  /// Declare a secondary index on a field of JSON properties
+  ///
+  /// The field is adressed by a JSON pointer (e.g. `/Person/surname`).
+  /// Afterwards properties can be searched by the value of this field
+  /// (see `ql::PropertyQuery::JsonField`). Already existing properties
+  /// are added to the index.
+  pub fn create_json_index(&mut self, path: &str) -> Result<(), Error<E>> {
+    self.transaction(|db| {
+      let mut paths = db.json_indexes()?;
+      if paths.iter().any(|p| p == path) {
+        return Ok(());
+      }
+      paths.push(path.to_string());
+      db.kv.create_bucket(b"indexes/json/").map_err(|e| Error::KV(e))?;
+      db.kv.store_record(JSON_INDEXES.as_bytes(), &serde_json::to_vec(&paths).map_err(SerialisationError::from)?)
+        .map_err(|e| Error::KV(e))?;
+
+      let ids: Vec<_> = db.properties(PropertyFilter::All)?.collect();
+      for id in ids {
+        let data = db.kv.fetch_record(format!("props/{id}").as_bytes())
+          .map_err(|e| Error::KV(e))?;
+        db.update_json_indexes(&[path.to_string()], &id, &data, true)?;
+      }
+
+      Ok(())
+    })
+  }
+
+  /// Remove a secondary index on a field of JSON properties
+  pub fn delete_json_index(&mut self, path: &str) -> Result<(), Error<E>> {
+    self.transaction(|db| {
+      let mut paths = db.json_indexes()?;
+      if !paths.iter().any(|p| p == path) {
+        return Err(Error::MissingIndex(path.to_string()));
+      }
+      paths.retain(|p| p != path);
+      db.kv.store_record(JSON_INDEXES.as_bytes(), &serde_json::to_vec(&paths).map_err(SerialisationError::from)?)
+        .map_err(|e| Error::KV(e))?;
+
+      let pfx = format!("indexes/json/{:X}/", sha2::Sha256::digest(path));
+      for entry in db.kv.list_records(pfx.as_bytes(), b"").map_err(|e| Error::KV(e))? {
+        db.kv.delete_record(&entry).map_err(|e| Error::KV(e))?;
+      }
+
+      Ok(())
+    })
+  }
+}
+
 impl<P, K, E> PropertyGraphReader<VertexId, NodeData, HashId, EdgeData, HashId, P, Error<E>> for KvGraphStore<P, K, E>
 where
   P: Property<HashId, SerialisationError>,
@@ -500,7 +1826,7 @@ where
   fn read_node(&self, id: VertexId) -> Result<NodeData, Error<E>> {
     let path = "nodes/".to_string() + &id.to_key();
 
-    let data = self.kv.fetch_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
+    let data = self.read_record(path.as_bytes())?;
     let node: NodeData = NodeData::deserialize(&data)?;
     Ok(node)
   }
@@ -508,7 +1834,7 @@ where
   fn read_edge(&self, id: &HashId) -> Result<EdgeData, Error<E>> {
     let path = "edges/".to_string() + id;
 
-    let data = self.kv.fetch_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
+    let data = self.read_record(path.as_bytes())?;
     let edge = EdgeData::deserialize(&data)?;
     Ok(edge)
   }
@@ -516,7 +1842,7 @@ where
   fn read_property(&self, id: &HashId) -> Result<P, Error<E>> {
     let path = "props/".to_string() + id;
 
-    let data = self.kv.fetch_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
+    let data = self.read_record(path.as_bytes())?;
     let property = SchemaElement::deserialize(&data)?;
     Ok(property)
   }
@@ -525,230 +1851,271 @@ where
 impl<P, K, E> GraphStore<VertexId, HashId, HashId, P, Error<E>> for KvGraphStore<P, K, E>
 where
   P: Property<HashId, SerialisationError>,
-  K: KVStore<E>,
+  K: TransactionalKVStore<E>,
   E: Send,
 {
   fn create_node(&mut self, id: VertexId, properties: &P) -> Result<VertexId, Error<E>> {
-    let props_hash = self.create_property(properties)?;
-    let node = NodeData {
-      id,
-      properties: props_hash.clone(),
-      incoming: BTreeSet::new(),
-      outgoing: BTreeSet::new(),
-    };
-    let key = node.get_key();
-    let node = node.serialize()?;
+    self.transaction(|db| {
+      let props_hash = db.create_property(properties)?;
+      let node = NodeData {
+        id,
+        properties: props_hash.clone(),
+        incoming: BTreeSet::new(),
+        outgoing: BTreeSet::new(),
+      };
+      let key = node.get_key();
+      let node = node.serialize()?;
 
-    let path = "nodes/".to_string() + &key;
+      let path = "nodes/".to_string() + &key;
 
-    if self.kv.exists(path.as_bytes()).map_err(|e| Error::KV(e))? {
-      return Err(Error::NodeExists(path));
-    };
+      if db.kv.exists(path.as_bytes()).map_err(|e| Error::KV(e))? {
+        return Err(Error::NodeExists(path));
+      };
 
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
 
-    self.create_idx_backlink(&props_hash, &key, BacklinkType::Node)?;
+      db.create_idx_backlink(&props_hash, &key, BacklinkType::Node)?;
 
-    Ok(id)
+      Ok(id)
+    })
   }
 
   fn update_node(&mut self, id: VertexId, properties: &P) -> Result<VertexId, Error<E>> {
-    let props_hash = self.create_property(properties)?;
-    let path = "nodes/".to_string() + &id.to_key();
-    let NodeData {
-      id,
-      properties: old_properties,
-      incoming,
-      outgoing,
-    } = self.read_node(id)?;
-    let node = NodeData {
-      id,
-      properties: props_hash.clone(),
-      incoming,
-      outgoing,
-    };
-    let key = id.to_key();
-    let node = node.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
-
-    self.create_idx_backlink(&props_hash, &key, BacklinkType::Node)?;
-
-    let last_reference = self.delete_property_backlink(&old_properties, &key, BacklinkType::Node)?;
-    if last_reference {
-      self.delete_property(&old_properties)?;
-    }
+    self.transaction(|db| {
+      let props_hash = db.create_property(properties)?;
+      let path = "nodes/".to_string() + &id.to_key();
+      let NodeData {
+        id,
+        properties: old_properties,
+        incoming,
+        outgoing,
+      } = db.read_node(id)?;
+      let node = NodeData {
+        id,
+        properties: props_hash.clone(),
+        incoming,
+        outgoing,
+      };
+      let key = id.to_key();
+      let node = node.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+
+      db.create_idx_backlink(&props_hash, &key, BacklinkType::Node)?;
+
+      let last_reference = db.delete_property_backlink(&old_properties, &key, BacklinkType::Node)?;
+      if last_reference {
+        db.delete_property(&old_properties)?;
+      }
 
-    Ok(id)
+      Ok(id)
+    })
   }
 
-  fn delete_node(&mut self, id: VertexId) -> Result<VertexId, Error<E>> {
-    let NodeData {
-      id,
-      properties,
-      incoming: _,
-      outgoing: _,
-    } = self.read_node(id)?;
-
-    let key = id.to_key();
-    let path = "nodes/".to_string() + &key;
+  fn delete_node(&mut self, id: VertexId, mode: DeletionMode) -> Result<VertexId, Error<E>> {
+    self.transaction(|db| {
+      let NodeData {
+        id,
+        properties,
+        incoming,
+        outgoing,
+      } = db.read_node(id)?;
+
+      let key = id.to_key();
+      let path = "nodes/".to_string() + &key;
+
+      if !incoming.is_empty() || !outgoing.is_empty() {
+        match mode {
+          DeletionMode::Reject => return Err(Error::NodeHasEdges(key)),
+          DeletionMode::Cascade => {
+            for edge_id in incoming.union(&outgoing) {
+              db.delete_edge(edge_id)?;
+            }
+          }
+        }
+      }
 
-    let last_reference = self.delete_property_backlink(&properties, &key, BacklinkType::Node)?;
-    if last_reference {
-      self.delete_property(&properties)?;
-    }
+      let last_reference = db.delete_property_backlink(&properties, &key, BacklinkType::Node)?;
+      if last_reference {
+        db.delete_property(&properties)?;
+      }
 
-    self.kv.delete_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
-    Ok(id)
+      db.kv.delete_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
+      Ok(id)
+    })
   }
 
   fn create_edge(&mut self, n1: VertexId, n2: VertexId, properties: &P) -> Result<HashId, Error<E>> {
-    let props_hash = self.create_property(properties)?;
-    let edge = EdgeData {
-      n1,
-      n2,
-      properties: props_hash.clone(),
-    };
-
-    let hash = edge.get_key();
-    let path = "edges/".to_string() + &hash;
-
-    let edge = edge.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &edge).map_err(|e| Error::KV(e))?;
-
-    self.create_idx_backlink(&props_hash, &hash, BacklinkType::Edge)?;
-
-    let path = "nodes/".to_string() + &n1.to_key();
-    let NodeData {
-      id,
-      properties,
-      incoming,
-      mut outgoing,
-    } = self.read_node(n1)?;
-    outgoing.insert(hash.clone());
-    let node = NodeData {
-      id,
-      properties,
-      incoming,
-      outgoing,
-    };
-    let node = node.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
-
-    let path = "nodes/".to_string() + &n2.to_key();
-    let NodeData {
-      id,
-      properties,
-      mut incoming,
-      outgoing,
-    } = self.read_node(n2)?;
-    incoming.insert(hash.clone());
-    let node = NodeData {
-      id,
-      properties,
-      incoming,
-      outgoing,
-    };
-    let node = node.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
-
-    Ok(hash)
+    self.transaction(|db| {
+      let props_hash = db.create_property(properties)?;
+      let edge = EdgeData {
+        n1,
+        n2,
+        properties: props_hash.clone(),
+      };
+
+      let hash = edge.get_key();
+      let path = "edges/".to_string() + &hash;
+
+      let edge = edge.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &edge).map_err(|e| Error::KV(e))?;
+
+      db.create_idx_backlink(&props_hash, &hash, BacklinkType::Edge)?;
+
+      let path = "nodes/".to_string() + &n1.to_key();
+      let NodeData {
+        id,
+        properties,
+        incoming,
+        mut outgoing,
+      } = db.read_node(n1)?;
+      outgoing.insert(hash.clone());
+      let node = NodeData {
+        id,
+        properties,
+        incoming,
+        outgoing,
+      };
+      let node = node.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+
+      let path = "nodes/".to_string() + &n2.to_key();
+      let NodeData {
+        id,
+        properties,
+        mut incoming,
+        outgoing,
+      } = db.read_node(n2)?;
+      incoming.insert(hash.clone());
+      let node = NodeData {
+        id,
+        properties,
+        incoming,
+        outgoing,
+      };
+      let node = node.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+
+      Ok(hash)
+    })
   }
 
   fn delete_edge(&mut self, id: &HashId) -> Result<(), Error<E>> {
-    let EdgeData {
-      properties: props_hash,
-      n1,
-      n2,
-    } = self.read_edge(id)?;
-
-    let path = "edges/".to_string() + id;
-
-    self.kv.delete_record(&path.as_bytes()).map_err(|e| Error::KV(e))?;
-
-    let path = "nodes/".to_string() + &n1.to_key();
-    let NodeData {
-      id: _id,
-      properties,
-      incoming,
-      mut outgoing,
-    } = self.read_node(n1)?;
-    outgoing.remove(id);
-    let node = NodeData {
-      id: n1,
-      properties,
-      incoming,
-      outgoing,
-    };
-    let node = node.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
-
-    let path = "nodes/".to_string() + &n2.to_key();
-    let NodeData {
-      id: _id,
-      properties,
-      mut incoming,
-      outgoing,
-    } = self.read_node(n2)?;
-    incoming.remove(id);
-    let node = NodeData {
-      id: n2,
-      properties,
-      incoming,
-      outgoing,
-    };
-    let node = node.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
-
-    let last_reference = self.delete_property_backlink(&props_hash, &id, BacklinkType::Edge)?;
-    if last_reference {
-      self.delete_property(&props_hash)?;
-    }
+    self.transaction(|db| {
+      let EdgeData {
+        properties: props_hash,
+        n1,
+        n2,
+      } = db.read_edge(id)?;
+
+      let path = "edges/".to_string() + id;
+
+      db.kv.delete_record(&path.as_bytes()).map_err(|e| Error::KV(e))?;
+
+      let path = "nodes/".to_string() + &n1.to_key();
+      let NodeData {
+        id: _id,
+        properties,
+        incoming,
+        mut outgoing,
+      } = db.read_node(n1)?;
+      outgoing.remove(id);
+      let node = NodeData {
+        id: n1,
+        properties,
+        incoming,
+        outgoing,
+      };
+      let node = node.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+
+      let path = "nodes/".to_string() + &n2.to_key();
+      let NodeData {
+        id: _id,
+        properties,
+        mut incoming,
+        outgoing,
+      } = db.read_node(n2)?;
+      incoming.remove(id);
+      let node = NodeData {
+        id: n2,
+        properties,
+        incoming,
+        outgoing,
+      };
+      let node = node.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &node).map_err(|e| Error::KV(e))?;
+
+      let last_reference = db.delete_property_backlink(&props_hash, &id, BacklinkType::Edge)?;
+      if last_reference {
+        db.delete_property(&props_hash)?;
+      }
 
-    Ok(())
+      Ok(())
+    })
   }
 
   fn create_property(&mut self, properties: &P) -> Result<HashId, Error<E>> {
-    let hash = properties.get_key();
-    let path = "props/".to_string() + &hash;
-
-    let data = properties.serialize()?;
-    self.kv.store_record(&path.as_bytes(), &data).map_err(|e| Error::KV(e))?;
-
-    properties.nested().iter().try_for_each(|nested| {
-      match self.create_property(nested) {
-        Ok(nested_hash) => {
-          self.create_idx_backlink(&nested_hash, &hash, BacklinkType::Property)?;
-          Ok(())
-        }
-        Err(e) => {
-          use Error::*;
-          match e {
-            ExistedBefore => Ok(()),
-            _ => Err(e),
+    self.transaction(|db| {
+      let hash = properties.get_key();
+      let path = "props/".to_string() + &hash;
+
+      let data = properties.serialize()?;
+      db.kv.store_record(&path.as_bytes(), &data).map_err(|e| Error::KV(e))?;
+      db.update_json_indexes(&db.json_indexes()?, &hash, &data, true)?;
+
+      if let Some(key) = properties.ordered_key() {
+        let key = ql::OrderedKey::from(key);
+        db.kv.create_bucket(b"sorted/").map_err(|e| Error::KV(e))?;
+        db.kv.store_record(sorted_index_path(&key, &hash).as_bytes(), key.0.as_bytes())
+          .map_err(|e| Error::KV(e))?;
+      }
+
+      properties.nested().iter().try_for_each(|nested| {
+        match db.create_property(nested) {
+          Ok(nested_hash) => {
+            db.create_idx_backlink(&nested_hash, &hash, BacklinkType::Property)?;
+            Ok(())
+          }
+          Err(e) => {
+            use Error::*;
+            match e {
+              ExistedBefore => Ok(()),
+              _ => Err(e),
+            }
           }
         }
-      }
-    })?;
+      })?;
 
-    Ok(hash)
+      Ok(hash)
+    })
   }
 
   fn delete_property(&mut self, id: &HashId) -> Result<(), Error<E>> {
-    let path = "props/".to_string() + id;
+    self.transaction(|db| {
+      let path = "props/".to_string() + id;
 
-    let data = self.kv.fetch_record(&path.as_bytes()).map_err(|e| Error::KV(e))?;
-    let properties: P = SchemaElement::deserialize(&data)?;
+      let data = db.kv.fetch_record(&path.as_bytes()).map_err(|e| Error::KV(e))?;
+      let properties: P = SchemaElement::deserialize(&data)?;
 
-    for nested in properties.nested().iter() {
-      let nested_hash = nested.get_key();
-      let last_reference = self.delete_property_backlink(&nested_hash, id, BacklinkType::Property)?;
-      if last_reference {
-        self.delete_property(&nested_hash)?;
+      for nested in properties.nested().iter() {
+        let nested_hash = nested.get_key();
+        let last_reference = db.delete_property_backlink(&nested_hash, id, BacklinkType::Property)?;
+        if last_reference {
+          db.delete_property(&nested_hash)?;
+        }
       }
-    }
 
-    self.kv.delete_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
-    Ok(())
+      if let Some(key) = properties.ordered_key() {
+        let key = ql::OrderedKey::from(key);
+        db.kv.delete_record(sorted_index_path(&key, id).as_bytes())
+          .map_err(|e| Error::KV(e))?;
+      }
+
+      db.update_json_indexes(&db.json_indexes()?, id, &data, false)?;
+
+      db.kv.delete_record(path.as_bytes()).map_err(|e| Error::KV(e))?;
+      Ok(())
+    })
   }
 }
 
@@ -756,7 +2123,7 @@ impl<N, P, K, E> GraphBuilder<N, P, Error<E>> for KvGraphStore<P, K, E>
 where
   N: Node<P>,
   P: Property<HashId, SerialisationError>,
-  K: KVStore<E>,
+  K: TransactionalKVStore<E>,
   E: Send,
 {
   fn add_node(&mut self, node: N) -> Result<(), Error<E>> {
@@ -771,7 +2138,7 @@ where
   }
 
   fn remove_node(&mut self, node: &N) -> Result<(), Error<E>> {
-    self.delete_node(node.id())?;
+    self.delete_node(node.id(), DeletionMode::Cascade)?;
     Ok(())
   }
 
@@ -788,6 +2155,90 @@ where
   }
 }
 
+// The traits are not imported, because their methods `nodes` and
+// `edges` would clash with the ones of `PropertyGraphReader`
+impl<'a, P, K, E> crate::Graph<'a, VertexId, Error<E>> for KvGraphStore<P, K, E>
+where
+  P: Property<HashId, SerialisationError>,
+  K: KVStore<E>,
+  E: Send,
+{
+  type NodeIterator = std::vec::IntoIter<VertexId>;
+  type NeighborIterator = std::vec::IntoIter<VertexId>;
+  type EdgeIterator = std::vec::IntoIter<(VertexId, VertexId)>;
+
+  fn order(&self) -> Result<usize, Error<E>> {
+    Ok(PropertyGraphReader::nodes(self, PropertyFilter::All)?.count())
+  }
+
+  fn size(&self) -> Result<usize, Error<E>> {
+    Ok(PropertyGraphReader::edges(self, PropertyFilter::All)?.count())
+  }
+
+  fn nodes(&'a self) -> Result<Self::NodeIterator, Error<E>> {
+    Ok(PropertyGraphReader::nodes(self, PropertyFilter::All)?.collect::<Vec<_>>().into_iter())
+  }
+
+  fn has_node(&self, node: &VertexId) -> Result<bool, Error<E>> {
+    let path = "nodes/".to_string() + &node.to_key();
+    self.record_exists(path.as_bytes())
+  }
+
+  /// The vertices on the other side of the outgoing and the incoming
+  /// edges
+  fn neighbors(&'a self, node: &VertexId) -> Result<Self::NeighborIterator, Error<E>> {
+    let neighbours = self.neighbours(*node, ql::Direction::Both, &None)?;
+    Ok(neighbours.into_iter().map(|(_, id, _)| id).collect::<Vec<_>>().into_iter())
+  }
+
+  /// The number of edges connected to the node (an edge to itself is
+  /// counted twice)
+  fn degree(&self, node: &VertexId) -> Result<usize, Error<E>> {
+    let node = self.read_node(*node)?;
+    Ok(node.incoming.len() + node.outgoing.len())
+  }
+
+  fn edges(&'a self) -> Result<Self::EdgeIterator, Error<E>> {
+    let edges = PropertyGraphReader::edges(self, PropertyFilter::All)?
+      .map(|id| {
+        let edge = self.read_edge(&id)?;
+        Ok((edge.n1, edge.n2))
+      })
+      .collect::<Result<Vec<_>, Error<E>>>()?;
+    Ok(edges.into_iter())
+  }
+
+  /// Returns true if an edge leads from source to target
+  fn has_edge(&self, source: &VertexId, target: &VertexId) -> Result<bool, Error<E>> {
+    for id in self.read_node(*source)?.outgoing.iter() {
+      if self.read_edge(id)?.n2 == *target {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+}
+
+impl<'a, P, K, E> crate::DirectedGraph<'a, VertexId, Error<E>> for KvGraphStore<P, K, E>
+where
+  P: Property<HashId, SerialisationError>,
+  K: KVStore<E>,
+  E: Send,
+{
+  type OutIterator = std::vec::IntoIter<VertexId>;
+  type InIterator = std::vec::IntoIter<VertexId>;
+
+  fn outgoing(&'a self, node: &VertexId) -> Result<Self::OutIterator, Error<E>> {
+    let neighbours = self.neighbours(*node, ql::Direction::Outgoing, &None)?;
+    Ok(neighbours.into_iter().map(|(_, id, _)| id).collect::<Vec<_>>().into_iter())
+  }
+
+  fn incoming(&'a self, node: &VertexId) -> Result<Self::InIterator, Error<E>> {
+    let neighbours = self.neighbours(*node, ql::Direction::Ingoing, &None)?;
+    Ok(neighbours.into_iter().map(|(_, id, _)| id).collect::<Vec<_>>().into_iter())
+  }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct NodeData {
   /// Unique identifier of the node in the graph.
@@ -864,6 +2315,28 @@ pub struct ChangeSet {
   //pub properties: BTreeSet<Property>,
 }
 
+/// The list of JSON pointers with a declared index
+const JSON_INDEXES: &str = "indexes/json/paths";
+
+/// The prefix of all index entries of properties with `value` at the
+/// JSON pointer `path`
+fn json_index_path(path: &str, value: &serde_json::Value) -> String {
+  let value = serde_json::to_vec(value).unwrap();
+  format!(
+    "indexes/json/{:X}/{:X}_",
+    sha2::Sha256::digest(path),
+    sha2::Sha256::digest(&value),
+  )
+}
+
+/// Maximum number of bytes of an ordered key, which are used in the
+/// path of the sorted index
+const SORTED_KEY_LEN: usize = 64;
+
+fn sorted_index_path(key: &ql::OrderedKey, hash: &str) -> String {
+  format!("sorted/{}-{}", key.truncated(SORTED_KEY_LEN), hash)
+}
+
 pub fn to_query(data: &Vec<u8>) -> Result<BasicQuery, SerialisationError> {
   // TODO Verschiedene Query Sprachen über zweiten Parameter
   // TODO Internes Schema verwenden um Abfragen zu verbessern
@@ -872,6 +2345,205 @@ pub fn to_query(data: &Vec<u8>) -> Result<BasicQuery, SerialisationError> {
   Ok(query)
 }
 
+/// The elements in the order of their positions
+///
+/// Elements which were not ordered follow in the order of their ids.
+fn in_order<K, V: OrderedContext>(context: HashMap<K, V>, key: impl Fn(&K) -> String) -> Vec<(K, V)> {
+  let mut elements: Vec<_> = context.into_iter().collect();
+  elements.sort_by_cached_key(|(k, v)| (v.position().is_none(), v.position(), key(k)));
+  elements
+}
+
+/// Number the elements of a stream after skipping the first `skip`
+/// ones and stop after `limit` elements
+///
+/// Errors are handed on instead of being skipped.
+fn paged<'a, K, V, E>(
+  stream: Box<dyn Iterator<Item = Result<(K, V), Error<E>>> + 'a>,
+  skip: usize,
+  limit: Option<usize>,
+) -> Box<dyn Iterator<Item = Result<(K, V), Error<E>>> + 'a>
+where
+  K: 'a,
+  V: OrderedContext + 'a,
+  E: Send + 'a,
+{
+  let mut skipped = 0;
+  let mut position = 0;
+  Box::new(stream
+    .filter(move |element| {
+      if element.is_ok() && skipped < skip {
+        skipped += 1;
+        return false;
+      }
+      true
+    })
+    .take(limit.unwrap_or(usize::MAX))
+    .map(move |element| {
+      let (k, mut v) = element?;
+      v.set_position(Some(position));
+      position += 1;
+      Ok((k, v))
+    }))
+}
+
+/// Number the elements in their current order
+fn numbered<K, V>(elements: impl Iterator<Item = (K, V)>) -> HashMap<K, V>
+where
+  K: Eq + Hash,
+  V: OrderedContext,
+{
+  elements.enumerate()
+    .map(|(position, (k, mut v))| {
+      v.set_position(Some(position));
+      (k, v)
+    })
+    .collect()
+}
+
+/// The number of elements to skip for a page of a query and the
+/// fingerprint of the query used in its cursors
+///
+/// A cursor is the number of elements before the page followed by the
+/// fingerprint.
+fn page_start<Q: Serialize, E: Send>(
+  q: &Q,
+  skip: usize,
+  limit: usize,
+  cursor: Option<&str>,
+) -> Result<(usize, String), Error<E>> {
+  let data = serde_json::to_vec(&(q, limit)).map_err(SerialisationError::from)?;
+  let fingerprint = format!("{:x}", sha2::Sha256::digest(&data))[..16].to_string();
+
+  let Some(cursor) = cursor else {
+    return Ok((skip, fingerprint));
+  };
+  let Some((skip, query)) = cursor.split_once('-') else {
+    return Err(Error::InvalidCursor(format!("malformed cursor '{cursor}'")));
+  };
+  let Ok(skip) = skip.parse() else {
+    return Err(Error::InvalidCursor(format!("malformed cursor '{cursor}'")));
+  };
+  if query != fingerprint {
+    return Err(Error::InvalidCursor("the cursor belongs to another query".to_string()));
+  }
+  Ok((skip, fingerprint))
+}
+
+/// The cursor of the page after the one starting at `skip`
+///
+/// There is none if the start of the next page can not be counted.
+fn next_cursor(skip: usize, limit: usize, fingerprint: &str) -> Option<String> {
+  skip.checked_add(limit).map(|next| format!("{next}-{fingerprint}"))
+}
+
+/// Remove the element fetched beyond the limit and tell if there was one
+fn split_page<K, V: OrderedContext>(context: &mut HashMap<K, V>, limit: usize) -> bool {
+  let len = context.len();
+  context.retain(|_, ctx| ctx.position() != Some(limit));
+  context.len() < len
+}
+
+/// A vertex waiting to be visited by Dijkstra's algorithm
+///
+/// The candidates are ordered by their distance, the nearest first.
+struct Candidate {
+  distance: f64,
+  id: VertexId,
+}
+
+impl PartialEq for Candidate {
+  fn eq(&self, other: &Self) -> bool {
+    self.distance.total_cmp(&other.distance).is_eq()
+  }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Candidate {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    other.distance.total_cmp(&self.distance)
+  }
+}
+
+/// Sort the elements by their values (see `ql::SortOrder`) and number
+/// them
+fn sort_by_values<K, V>(
+  context: HashMap<K, V>,
+  values: &HashMap<K, serde_json::Value>,
+  key: impl Fn(&K) -> String,
+  descending: bool,
+) -> HashMap<K, V>
+where
+  K: Eq + Hash,
+  V: OrderedContext,
+{
+  use std::cmp::Ordering::*;
+
+  let mut elements: Vec<_> = context.into_iter()
+    .map(|(k, v)| (key(&k), k, v))
+    .collect();
+  elements.sort_by(|(a_key, a, _), (b_key, b, _)| {
+    let order = match (values.get(a), values.get(b)) {
+      (Some(a), Some(b)) if descending => compare_json(b, a),
+      (Some(a), Some(b)) => compare_json(a, b),
+      (Some(_), None) => Less,
+      (None, Some(_)) => Greater,
+      (None, None) => Equal,
+    };
+    order.then_with(|| a_key.cmp(b_key))
+  });
+  numbered(elements.into_iter().map(|(_, k, v)| (k, v)))
+}
+
+/// Compare json values of any type (see `ql::SortOrder`)
+fn compare_json(a: &serde_json::Value, b: &serde_json::Value) -> std::cmp::Ordering {
+  use serde_json::Value::*;
+
+  let rank = |value: &serde_json::Value| match value {
+    Null => 0,
+    Bool(_) => 1,
+    Number(_) => 2,
+    String(_) => 3,
+    Array(_) => 4,
+    Object(_) => 5,
+  };
+  match (a, b) {
+    (Bool(x), Bool(y)) => x.cmp(y),
+    (Number(x), Number(y)) => x.as_f64().unwrap_or(0.0).total_cmp(&y.as_f64().unwrap_or(0.0)),
+    (String(x), String(y)) => x.cmp(y),
+    (Array(x), Array(y)) => x.iter().zip(y)
+      .map(|(x, y)| compare_json(x, y))
+      .find(|order| order.is_ne())
+      .unwrap_or_else(|| x.len().cmp(&y.len())),
+    (Object(_), Object(_)) => a.to_string().cmp(&b.to_string()),
+    _ => rank(a).cmp(&rank(b)),
+  }
+}
+
+fn insert_merged<K, V>(
+  result: &mut HashMap<K, V>,
+  k: K,
+  v: V,
+)
+where
+  K: Eq + Hash,
+  V: MergeContext,
+{
+  match result.get_mut(&k) {
+    Some(existing) => existing.merge(v),
+    None => {
+      result.insert(k, v);
+    }
+  }
+}
+
 fn union<K, V>(
   c1: HashMap<K, V>,
   c2: HashMap<K, V>
@@ -879,10 +2551,13 @@ fn union<K, V>(
   HashMap<K, V>
 where
   K: Eq + Hash,
+  V: MergeContext,
 {
   let mut result = c1;
 
-  result.extend(c2.into_iter());
+  for (k, v) in c2.into_iter() {
+    insert_merged(&mut result, k, v);
+  }
   result
 }
 
@@ -893,12 +2568,16 @@ fn intersection<K, V>(
   HashMap<K, V>
 where
   K: Eq + Hash,
+  V: MergeContext,
 {
   let mut result = c1;
-  let mut c2 = c2;
 
-  c2.retain(|k, _v| result.contains_key(k));
   result.retain(|k, _v| c2.contains_key(k));
+  for (k, v) in c2.into_iter() {
+    if let Some(existing) = result.get_mut(&k) {
+      existing.merge(v);
+    }
+  }
   result
 }
 
@@ -934,3 +2613,27 @@ where
 
   result
 }
+
+/// All operands of nested intersections
+fn flatten_vertex_intersection(q: VQuery) -> Vec<VQuery> {
+  match q {
+    ql::VertexQuery::Intersect(q1, q2) => {
+      let mut operands = flatten_vertex_intersection(*q1);
+      operands.extend(flatten_vertex_intersection(*q2));
+      operands
+    }
+    q => vec![q],
+  }
+}
+
+/// All operands of nested intersections
+fn flatten_edge_intersection(q: EQuery) -> Vec<EQuery> {
+  match q {
+    ql::EdgeQuery::Intersect(q1, q2) => {
+      let mut operands = flatten_edge_intersection(*q1);
+      operands.extend(flatten_edge_intersection(*q2));
+      operands
+    }
+    q => vec![q],
+  }
+}
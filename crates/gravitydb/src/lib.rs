@@ -1,4 +1,7 @@
 pub mod ql;
+pub mod zoe;
+pub mod versioning;
+pub mod algorithms;
 pub mod schema;
 pub mod kv_graph_store;
 pub mod mem_kv_store;
@@ -8,32 +11,34 @@ pub mod lua;
 pub use gravitydb_derive::Schema;
 pub mod import;
 
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
 pub trait GraphFilter<GIN, GOUT>
 {
   fn filter(&mut self, graph: GIN) -> GOUT;
 }
 
 pub trait Graph<'a, N: 'a, E> {
-  type NodeIterator: Iterator<Item=&'a N>;
-  type NeighborIterator: Iterator<Item=&'a N>;
-  type EdgeIterator: Iterator<Item=(&'a N, &'a N)>;
+  type NodeIterator: Iterator<Item=N>;
+  type NeighborIterator: Iterator<Item=N>;
+  type EdgeIterator: Iterator<Item=(N, N)>;
 
   /// Returns true if there are no nodes, or false otherwise.
-  fn is_empty(&self) -> bool {
-    self.order() == 0
+  fn is_empty(&self) -> Result<bool, E> {
+    Ok(self.order()? == 0)
   }
 
   /// Returns the number of nodes in this graph.
-  fn order(&self) -> usize;
+  fn order(&self) -> Result<usize, E>;
 
   /// Returns the number of edges in this graph.
-  fn size(&self) -> usize;
+  fn size(&self) -> Result<usize, E>;
 
   /// Iterates the nodes of this graph
-  fn nodes(&'a self) -> Self::NodeIterator;
+  fn nodes(&'a self) -> Result<Self::NodeIterator, E>;
 
   /// Returns true if node is a member, or false otherwise.
-  fn has_node(&self, node: &N) -> bool;
+  fn has_node(&self, node: &N) -> Result<bool, E>;
 
   /// Iterates the neighbors of node.
   fn neighbors(&'a self, node: &N) -> Result<Self::NeighborIterator, E>;
@@ -42,15 +47,15 @@ pub trait Graph<'a, N: 'a, E> {
   fn degree(&self, node: &N) -> Result<usize, E>;
 
   /// Iterates the edges of this graph.
-  fn edges(&'a self) -> Self::EdgeIterator;
+  fn edges(&'a self) -> Result<Self::EdgeIterator, E>;
 
   /// Returns true if an edge exists between source and target.
   fn has_edge(&self, source: &N, target: &N) -> Result<bool, E>;
 }
 
 pub trait DirectedGraph<'a, N: 'a, E>: Graph<'a, N, E> {
-  type OutIterator: Iterator<Item = &'a N>;
-  type InIterator: Iterator<Item = &'a N>;
+  type OutIterator: Iterator<Item = N>;
+  type InIterator: Iterator<Item = N>;
 
   /// Iterates the outgoing neighbors of node.
   fn outgoing(&'a self, node: &N) -> Result<Self::OutIterator, E>;
@@ -80,9 +85,9 @@ pub trait GraphBuilder<N, P, E> {
 pub enum PropertyFilter<PropKey> {
   /// Get only the elements connected with this property
   Only(PropKey),
-  /// Get all the elements connected with any property in between (and
-  /// including) the start and end properties
-  FromTo(PropKey, PropKey),
+  /// Get all the elements connected with any property whose ordered key
+  /// lies in between (and including) the start and end keys
+  FromTo(ql::OrderedKey, ql::OrderedKey),
   /// Get all elements (don't filter by property)
   All,
 }
@@ -108,18 +113,85 @@ pub trait PropertyGraphReader<NodeKey, Node, EdgeKey, Edge, PropKey, Prop, E> {
   //fn query(&self, q: BasicQuery) -> Result<QueryResult, E>;
 }
 
+/// What to do with the edges of a node that gets deleted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeletionMode {
+  /// Delete all edges connected to the node as well
+  Cascade,
+  /// Refuse to delete a node which still has edges
+  Reject,
+}
+
 /// The Interface for a Graph DB
 pub trait GraphStore<NodeKey, EdgeKey, PropKey, Prop, E> {
   // CRUD functions
   fn create_node(&mut self, id: NodeKey, properties: &Prop) -> Result<NodeKey, E>;
   fn update_node(&mut self, id: NodeKey, properties: &Prop) -> Result<NodeKey, E>;
-  fn delete_node(&mut self, id: NodeKey) -> Result<NodeKey, E>;
+  fn delete_node(&mut self, id: NodeKey, mode: DeletionMode) -> Result<NodeKey, E>;
   fn create_edge(&mut self, n1: NodeKey, n2: NodeKey, properties: &Prop) -> Result<EdgeKey, E>;
   fn delete_edge(&mut self, id: &EdgeKey) -> Result<(), E>;
   fn create_property(&mut self, properties: &Prop) -> Result<PropKey, E>;
   fn delete_property(&mut self, id: &PropKey) -> Result<(), E>;
 }
 
+/// The keys of a `KVStore` read one after another
+pub type KeyIterator<'a, E> = Box<dyn Iterator<Item = Result<Vec<u8>, E>> + 'a>;
+/// The records (keys and values) of a `KVStore` read one after another
+pub type RecordIterator<'a, E> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), E>> + 'a>;
+
+/// The records visited by `KVStore::scan`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanRange<'a> {
+  /// all records in between (and including) two keys (see
+  /// `KVStore::list_records`)
+  Between(&'a [u8], &'a [u8]),
+  /// all records whose keys start with a prefix
+  Prefix(&'a [u8]),
+}
+
+/// The order in which `KVStore::scan` visits the records
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScanDirection {
+  /// ascending keys
+  #[default]
+  Forward,
+  /// descending keys
+  Reverse,
+}
+
+impl ScanRange<'_> {
+  /// The bounds of the keys in this range
+  ///
+  /// An empty end of `Between` reaches till the first change of its
+  /// start (see `KVStore::list_records`). A range ending before it
+  /// starts is empty.
+  pub fn bounds(&self) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    match *self {
+      ScanRange::Between(from, to) if !to.is_empty() && to < from => (Included(from.to_vec()), Excluded(from.to_vec())),
+      ScanRange::Between(from, to) if !to.is_empty() => (Included(from.to_vec()), Included(to.to_vec())),
+      ScanRange::Between(prefix, _) | ScanRange::Prefix(prefix) => {
+        (Included(prefix.to_vec()), prefix_successor(prefix).map_or(Unbounded, Excluded))
+      }
+    }
+  }
+}
+
+/// The first key after all keys starting with `prefix`
+///
+/// Trailing `0xFF` bytes can not be incremented, so they are dropped
+/// and the byte before is incremented instead. There is no such key if
+/// the prefix consists of `0xFF` bytes only.
+pub fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+  let mut successor = prefix.to_vec();
+  while let Some(last) = successor.pop() {
+    if last < 0xFF {
+      successor.push(last + 1);
+      return Some(successor);
+    }
+  }
+  None
+}
+
 /// A Key-Value Store Backend Interface.
 ///
 /// Any Type that implements this interface can be used to run a graph
@@ -133,6 +205,44 @@ pub trait KVStore<E> {
   ///
   /// if `to` is empty it searches everything till the first change of `from`
   fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, E>;
+  /// iterate over the keys of all records in between `from` and `to`
+  /// (just like `list_records`) in ascending order
+  ///
+  /// The default implementation lists all keys at once. Stores which can
+  /// iterate over their keys cheaply should override it.
+  fn scan_keys<'a>(&'a self, from: &[u8], to: &[u8]) -> Result<KeyIterator<'a, E>, E>
+  where
+    E: 'a,
+  {
+    let mut keys = self.list_records(from, to)?;
+    keys.sort();
+    Ok(Box::new(keys.into_iter().map(Ok)))
+  }
+  /// iterate over the keys and values of all records in a range
+  ///
+  /// The default implementation lists the keys and fetches the values
+  /// one after another.
+  fn scan<'a>(&'a self, range: ScanRange<'_>, direction: ScanDirection) -> Result<RecordIterator<'a, E>, E>
+  where
+    E: 'a,
+  {
+    let mut keys = match range {
+      ScanRange::Between(from, to) => self.list_records(from, to)?,
+      ScanRange::Prefix(prefix) => {
+        let mut keys = self.list_records(prefix, b"")?;
+        keys.retain(|key| key.starts_with(prefix));
+        keys
+      }
+    };
+    keys.sort();
+    if direction == ScanDirection::Reverse {
+      keys.reverse();
+    }
+    Ok(Box::new(keys.into_iter().map(move |key| {
+      let value = self.fetch_record(&key)?;
+      Ok((key, value))
+    })))
+  }
   /// store a data record
   fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), E>;
   /// fetch a data record
@@ -140,3 +250,20 @@ pub trait KVStore<E> {
   /// check if an entry exists in the database
   fn exists(&self, key: &[u8]) -> Result<bool, E>;
 }
+
+/// A `KVStore` which can apply several changes as one atomic unit.
+///
+/// Changing a graph touches several records at once, so the graph
+/// store needs this to write. Stores implementing only `KVStore` can
+/// still be queried.
+pub trait TransactionalKVStore<E>: KVStore<E> {
+  /// start a transaction
+  ///
+  /// all changes until the next `commit_transaction` or
+  /// `rollback_transaction` are either applied as a whole or not at all
+  fn begin_transaction(&mut self) -> Result<(), E>;
+  /// make all changes of the running transaction permanent
+  fn commit_transaction(&mut self) -> Result<(), E>;
+  /// undo all changes of the running transaction
+  fn rollback_transaction(&mut self) -> Result<(), E>;
+}
@@ -2,7 +2,7 @@ use core::hash::Hash;
 use mlua::{FromLua, IntoLua, Lua, LuaSerdeExt, UserData, UserDataMethods};
 
 use crate::kv_graph_store::*;
-use crate::{GraphStore, KVStore};
+use crate::{DeletionMode, GraphStore, TransactionalKVStore};
 use crate::ql;
 use crate::ql::{VertexQuery, EdgeQuery, PropertyQuery, QueryResult};
 use crate::schema::Property;
@@ -18,7 +18,7 @@ impl UserData for Uuid {
 impl<P, K, E> UserData for KvGraphStore<P, K, E>
 where
   P: Property<HashId, SerialisationError> + UserData + std::clone::Clone + FromLua,
-  K: KVStore<E>,
+  K: TransactionalKVStore<E>,
   E: Send + Sync + std::fmt::Debug,
 {
   fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
@@ -39,8 +39,13 @@ where
       }
     });
 
-    methods.add_method_mut("delete_node", |_, db, id: VertexId| {
-      match db.delete_node(id) {
+    methods.add_method_mut("delete_node", |_, db, (id, cascade): (VertexId, Option<bool>)| {
+      let mode = if cascade.unwrap_or(false) {
+        DeletionMode::Cascade
+      } else {
+        DeletionMode::Reject
+      };
+      match db.delete_node(id, mode) {
         Ok(id) => Ok(id),
         Err(e) => Err(LuaError::external(e.to_string()))
       }
@@ -61,14 +66,30 @@ where
     });
 
     methods.add_method_mut("query", |lua, db, query: mlua::AnyUserData| {
-      let query: BasicQuery = match query.take::<ql::VertexQuery<_,_,_,_,_>>() {
-        Ok(q) => q.into(),
-        Err(_) => match query.take::<ql::EdgeQuery<_,_,_,_,_>>() {
-          Ok(q) => q.into(),
-          Err(_) => query.take::<ql::PropertyQuery<_>>()?.into(),
-        }
-      };
-      match db.query(query) {
+      match db.query(to_query(query)?) {
+        Ok(result) => Ok(lua.to_value(&result)),
+        Err(e) => Err(LuaError::external(e.to_string()))
+      }
+    });
+
+    methods.add_method_mut("explain", |_, db, query: mlua::AnyUserData| {
+      match db.explain(to_query(query)?) {
+        Ok(plan) => Ok(plan.to_string()),
+        Err(e) => Err(LuaError::external(e.to_string()))
+      }
+    });
+
+    methods.add_method_mut("optimize", |lua, db, query: mlua::AnyUserData| {
+      match db.optimize(to_query(query)?) {
+        Ok(BasicQuery::V(q)) => q.into_lua(lua),
+        Ok(BasicQuery::E(q)) => q.into_lua(lua),
+        Ok(BasicQuery::P(q)) => LuaPropertyQuery::<VertexId, HashId, HashId, ql::ShellFilter, ql::ShellFilter>::from(q).into_lua(lua),
+        Err(e) => Err(LuaError::external(e.to_string()))
+      }
+    });
+
+    methods.add_method_mut("query_page", |lua, db, (query, cursor): (mlua::AnyUserData, Option<String>)| {
+      match db.query_page(to_query(query)?, cursor.as_deref()) {
         Ok(result) => Ok(lua.to_value(&result)),
         Err(e) => Err(LuaError::external(e.to_string()))
       }
@@ -76,6 +97,16 @@ where
   }
 }
 
+fn to_query(query: mlua::AnyUserData) -> mlua::Result<BasicQuery> {
+  Ok(match query.take::<ql::VertexQuery<_,_,_,_,_>>() {
+    Ok(q) => q.into(),
+    Err(_) => match query.take::<ql::EdgeQuery<_,_,_,_,_>>() {
+      Ok(q) => q.into(),
+      Err(_) => query.take::<ql::PropertyQuery<_>>()?.into(),
+    }
+  })
+}
+
 pub fn init_lua<VertexId, EdgeId, PropertyId, VFilter, EFilter>(lua: &Lua) -> mlua::Result<()>
 where
   for<'lua> VertexId:   Clone + 'lua + FromLua,
@@ -187,9 +218,80 @@ where
       let (q1, q2) = queries;
       Ok(q1.substract(q2))
     });
+    methods.add_function("traverse", |_, args: (Self, String, usize, Option<usize>, Option<mlua::AnyUserData>)| {
+      let (q, direction, min, max, edges) = args;
+
+      let mut traversal = ql::Traversal::new(to_direction(&direction)?).depth(min, max);
+      if let Some(edges) = edges {
+        let edges = edges.take::<LuaPropertyQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>()?;
+        traversal = traversal.along(edges.q);
+      }
+      Ok(q.traverse(traversal))
+    });
+    methods.add_function("shortest_path", |_, args: (Self, Self, String, Option<String>)| {
+      let (q, to, direction, weight) = args;
+
+      let mut search = ql::PathSearch::new(to_direction(&direction)?);
+      if let Some(pointer) = weight {
+        search = search.weighted(&pointer);
+      }
+      Ok(q.shortest_path(to, search))
+    });
+    methods.add_function("count", |_, args: (Self, String)| {
+      let (q, name) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::Count))
+    });
+    methods.add_function("group_by", |_, args: (Self, String, String)| {
+      let (q, name, pointer) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::GroupBy(pointer)))
+    });
+    methods.add_function("group_by_nested", |_, args: (Self, String, String)| {
+      let (q, name, pointer) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::GroupByNested(pointer)))
+    });
+    methods.add_function("degree", |_, args: (Self, String, String, Option<mlua::AnyUserData>)| {
+      let (q, name, direction, edges) = args;
+
+      let mut count = ql::DegreeCount::new(to_direction(&direction)?);
+      if let Some(edges) = edges {
+        let edges = edges.take::<LuaPropertyQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>()?;
+        count = count.along(edges.q);
+      }
+      Ok(q.degree(&name, count))
+    });
+    methods.add_function("order_by", |_, args: (Self, Option<String>, Option<bool>)| {
+      let (q, pointer, descending) = args;
+      Ok(q.order_by(to_sort_order(pointer, descending)))
+    });
+    methods.add_function("skip", |_, args: (Self, usize)| {
+      let (q, n) = args;
+      Ok(q.skip(n))
+    });
+    methods.add_function("limit", |_, args: (Self, usize)| {
+      let (q, n) = args;
+      Ok(q.limit(n))
+    });
   }
 }
 
+fn to_direction(name: &str) -> mlua::Result<ql::Direction> {
+  match name {
+    "outgoing" => Ok(ql::Direction::Outgoing),
+    "ingoing" => Ok(ql::Direction::Ingoing),
+    "both" => Ok(ql::Direction::Both),
+    _ => Err(mlua::Error::external(format!("unknown direction: {name}"))),
+  }
+}
+
+/// Order by the ids if there is no JSON pointer
+fn to_sort_order(pointer: Option<String>, descending: Option<bool>) -> ql::SortOrder {
+  let order = match pointer {
+    Some(pointer) => ql::SortOrder::by_field(&pointer),
+    None => ql::SortOrder::by_id(),
+  };
+  if descending.unwrap_or(false) { order.descending() } else { order }
+}
+
 impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> UserData for EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>
 where
   for<'lua> VertexId:   Clone + 'lua + FromLua,
@@ -259,6 +361,30 @@ where
       let (q1, q2) = queries;
       Ok(q1.substract(q2))
     });
+    methods.add_function("count", |_, args: (Self, String)| {
+      let (q, name) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::Count))
+    });
+    methods.add_function("group_by", |_, args: (Self, String, String)| {
+      let (q, name, pointer) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::GroupBy(pointer)))
+    });
+    methods.add_function("group_by_nested", |_, args: (Self, String, String)| {
+      let (q, name, pointer) = args;
+      Ok(q.aggregate(&name, ql::Aggregation::GroupByNested(pointer)))
+    });
+    methods.add_function("order_by", |_, args: (Self, Option<String>, Option<bool>)| {
+      let (q, pointer, descending) = args;
+      Ok(q.order_by(to_sort_order(pointer, descending)))
+    });
+    methods.add_function("skip", |_, args: (Self, usize)| {
+      let (q, n) = args;
+      Ok(q.skip(n))
+    });
+    methods.add_function("limit", |_, args: (Self, usize)| {
+      let (q, n) = args;
+      Ok(q.limit(n))
+    });
   }
 }
 
@@ -368,7 +494,7 @@ impl Completer for LuaCompleter<'_> {
 pub fn lua_repl<T, Kv, E, OutE>(db: KvGraphStore<T, Kv, E>, init_fn: fn(&Lua) -> mlua::Result<()>) -> Result<(), OutE>
 where
   for<'lua> T: Property<HashId, SerialisationError> + 'lua + FromLua + UserData + Clone,
-  Kv: KVStore<E> + 'static,
+  Kv: TransactionalKVStore<E> + 'static,
   E: Send + Sync + std::fmt::Debug + 'static,
   OutE: From<rustyline::error::ReadlineError> + From<mlua::Error>,
 {
@@ -424,7 +550,7 @@ where
 pub fn lua_run<T, Kv, E, S, S2>(db: KvGraphStore<T, Kv, E>, init_fn: fn(&Lua) -> mlua::Result<()>, code: S, code_name: S2) -> Result<(), mlua::Error>
 where
   for<'lua> T: Property<HashId, SerialisationError> + 'lua + FromLua + UserData + Clone,
-  Kv: KVStore<E> + 'static,
+  Kv: TransactionalKVStore<E> + 'static,
   E: Send + Sync + std::fmt::Debug + 'static,
   S: AsRef<str>,
   S2: AsRef<str>,
@@ -437,7 +563,7 @@ where
 fn lua_init<T, Kv, E>(db: KvGraphStore<T, Kv, E>, init_fn: fn(&Lua) -> mlua::Result<()>) -> Result<Lua, mlua::Error>
 where
   for<'lua> T: Property<HashId, SerialisationError> + 'lua + FromLua + UserData + Clone,
-  Kv: KVStore<E> + 'static,
+  Kv: TransactionalKVStore<E> + 'static,
   E: Send + Sync + std::fmt::Debug + 'static,
 {
   let lua = Lua::new();
@@ -1,9 +1,6 @@
-use crate::KVStore;
-use std::{collections::BTreeMap, str::Utf8Error};
+use crate::{KVStore, KeyIterator, RecordIterator, ScanDirection, ScanRange, TransactionalKVStore};
+use std::{collections::BTreeMap, collections::btree_map::Range};
 use thiserror::Error;
-use std::ops::Bound::Included;
-
-type HashId = String;
 
 /// A Backend for the graph database running in memory only.
 ///
@@ -11,13 +8,33 @@ type HashId = String;
 /// persisted permanently.
 #[derive(Debug, Default)]
 pub struct MemoryKvStore {
-  data: BTreeMap<HashId, Vec<u8>>,
+  data: BTreeMap<Vec<u8>, Vec<u8>>,
+  /// The previous values of all records changed during the running
+  /// transaction (`None` if the record did not exist before).
+  journal: Option<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
 }
 
 impl MemoryKvStore {
-  pub fn get_inner(self) -> BTreeMap<HashId, Vec<u8>> {
+  pub fn get_inner(self) -> BTreeMap<Vec<u8>, Vec<u8>> {
     self.data
   }
+
+  /// All records in a range of keys
+  ///
+  /// An empty end of the range reaches till the first change of the
+  /// start (see `list_records`).
+  fn range(&self, range: ScanRange<'_>) -> Range<'_, Vec<u8>, Vec<u8>> {
+    self.data.range(range.bounds())
+  }
+
+  /// remember the old value of a record before it gets changed
+  fn journal(&mut self, key: &[u8]) {
+    if let Some(journal) = self.journal.as_mut() {
+      if !journal.contains_key(key) {
+        journal.insert(key.to_vec(), self.data.get(key).cloned());
+      }
+    }
+  }
 }
 
 impl KVStore<Error> for MemoryKvStore
@@ -27,39 +44,78 @@ impl KVStore<Error> for MemoryKvStore
   }
 
   fn delete_record(&mut self, key: &[u8]) -> Result<(), Error> {
-    self.data.remove(std::str::from_utf8(key)?);
+    self.journal(key);
+    self.data.remove(key);
     Ok(())
   }
 
   fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
-    self.data.insert(key_to_string(key)?, value.to_vec());
+    self.journal(key);
+    self.data.insert(key.to_vec(), value.to_vec());
     Ok(())
   }
 
   fn fetch_record(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
-    let key = key_to_string(key)?;
-    let out = self.data.get(&key).ok_or(Error::Missing(key))?;
+    let out = self.data.get(key)
+      .ok_or_else(|| Error::Missing(String::from_utf8_lossy(key).to_string()))?;
     Ok(out.to_vec())
   }
 
   fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
-    let to = if to.len() != 0 {
-      key_to_string(to)?
-    } else {
-      let mut to: Vec<u8> = from.to_vec();
-      *to.last_mut().unwrap() += 1;
-      key_to_string(&to)?
-    };
-    let from = key_to_string(from)?;
-    let iter: Vec<Vec<u8>>  = self.data
-      .range((Included(from), Included(to)))
-      .map(|(k, _v)| k.as_bytes().to_vec())
+    let iter: Vec<Vec<u8>>  = self.range(ScanRange::Between(from, to))
+      .map(|(k, _v)| k.clone())
       .collect();
     Ok(iter)
   }
 
+  fn scan_keys<'a>(&'a self, from: &[u8], to: &[u8]) -> Result<KeyIterator<'a, Error>, Error>
+  where
+    Error: 'a,
+  {
+    Ok(Box::new(self.range(ScanRange::Between(from, to)).map(|(k, _v)| Ok(k.clone()))))
+  }
+
+  fn scan<'a>(&'a self, range: ScanRange<'_>, direction: ScanDirection) -> Result<RecordIterator<'a, Error>, Error>
+  where
+    Error: 'a,
+  {
+    let records = self.range(range);
+    let records: Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)>> = match direction {
+      ScanDirection::Forward => Box::new(records),
+      ScanDirection::Reverse => Box::new(records.rev()),
+    };
+    Ok(Box::new(records.map(|(k, v)| Ok((k.clone(), v.clone())))))
+  }
+
   fn exists(&self, key: &[u8]) -> Result<bool, Error> {
-    Ok(self.data.contains_key(&key_to_string(key)?))
+    Ok(self.data.contains_key(key))
+  }
+}
+
+impl TransactionalKVStore<Error> for MemoryKvStore
+{
+  fn begin_transaction(&mut self) -> Result<(), Error> {
+    if self.journal.is_some() {
+      return Err(Error::TransactionRunning);
+    }
+    self.journal = Some(BTreeMap::new());
+    Ok(())
+  }
+
+  fn commit_transaction(&mut self) -> Result<(), Error> {
+    self.journal.take().ok_or(Error::NoTransaction)?;
+    Ok(())
+  }
+
+  fn rollback_transaction(&mut self) -> Result<(), Error> {
+    let journal = self.journal.take().ok_or(Error::NoTransaction)?;
+    for (key, old_value) in journal.into_iter() {
+      match old_value {
+        Some(value) => self.data.insert(key, value),
+        None => self.data.remove(&key),
+      };
+    }
+    Ok(())
   }
 }
 
@@ -67,11 +123,8 @@ impl KVStore<Error> for MemoryKvStore
 pub enum Error {
   #[error("the record {0} could not be found")]
   Missing(String),
-  #[error(transparent)]
-  Utf8(#[from] Utf8Error),
+  #[error("there is already a running transaction")]
+  TransactionRunning,
+  #[error("there is no running transaction")]
+  NoTransaction,
 }
-
-fn key_to_string(key: &[u8]) -> Result<String, Error> {
-  Ok(std::str::from_utf8(key)?.to_string())
-}
-
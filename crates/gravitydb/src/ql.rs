@@ -1,11 +1,13 @@
 use core::hash::Hash;
 use std::collections::{HashMap, HashSet};
+use thiserror::Error;
 #[cfg(feature="lua")]
 use mlua::FromLua;
+use indexmap::IndexSet;
 use std::convert::From;
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "lua", derive(FromLua))]
 pub enum VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>
 {
@@ -46,6 +48,30 @@ pub enum VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>
   //  /// Execute some arbitrary function to modify
   //  /// the query context (side effect).
   //  SideEffect(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, Fn(VertexId, QueryContext<VertexId, EdgeId>) -> QueryContext<VertexId, EdgeId>),
+  /// Sum up the selected vertices and store the value in the variable
+  /// with the given name (side effect).
+  Aggregate(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, String, Aggregation),
+  /// Store the degree of every selected vertex in the variable with the
+  /// given name (side effect).
+  Degree(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, String, DegreeCount<PropertyId>),
+  /// Select all vertices reachable over a variable number of
+  /// edges.
+  ///
+  /// The path of every hop is recorded in the query context.
+  Traverse(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, Traversal<PropertyId>),
+  /// Select the vertices of the second query which can be reached
+  /// from the first one. Each vertex is reached on the shortest path
+  /// from any of the start vertices.
+  ShortestPath(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, PathSearch<PropertyId>),
+  /// Sort the selected vertices. The order is kept by the following
+  /// `Skip` and `Limit` steps and in the result.
+  Order(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, SortOrder),
+  /// Leave out the first vertices of the selection. Vertices which were
+  /// not ordered before are taken in the order of their ids.
+  Skip(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, usize),
+  /// Only keep the first vertices of the selection. Vertices which were
+  /// not ordered before are taken in the order of their ids.
+  Limit(Box<VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, usize),
 }
 
 impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
@@ -77,6 +103,22 @@ impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> VertexQuery<VertexId, EdgeI
     VertexQuery::Store(Box::new(self))
   }
 
+  pub fn aggregate(self, name: &str, aggregation: Aggregation) -> Self {
+    VertexQuery::Aggregate(Box::new(self), name.to_string(), aggregation)
+  }
+
+  pub fn order_by(self, order: SortOrder) -> Self {
+    VertexQuery::Order(Box::new(self), order)
+  }
+
+  pub fn skip(self, n: usize) -> Self {
+    VertexQuery::Skip(Box::new(self), n)
+  }
+
+  pub fn limit(self, n: usize) -> Self {
+    VertexQuery::Limit(Box::new(self), n)
+  }
+
   pub fn outgoing(self) -> EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
     EdgeQuery::Out(Box::new(self))
   }
@@ -88,9 +130,47 @@ impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> VertexQuery<VertexId, EdgeI
   pub fn filter(self, filter: VFilter) -> Self {
     VertexQuery::Filter(Box::new(self), filter)
   }
+
+  pub fn degree(self, name: &str, count: DegreeCount<PropertyId>) -> Self {
+    VertexQuery::Degree(Box::new(self), name.to_string(), count)
+  }
+
+  pub fn traverse(self, traversal: Traversal<PropertyId>) -> Self {
+    VertexQuery::Traverse(Box::new(self), traversal)
+  }
+
+  pub fn shortest_path(self, to: VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>, search: PathSearch<PropertyId>) -> Self {
+    VertexQuery::ShortestPath(Box::new(self), Box::new(to), search)
+  }
+
+  /// The name of the outermost step
+  pub fn step_name(&self) -> &'static str {
+    use VertexQuery::*;
+
+    match self {
+      All => "All",
+      Specific(_) => "Specific",
+      Property(_) => "Property",
+      Out(_) => "Out",
+      In(_) => "In",
+      Union(..) => "Union",
+      Intersect(..) => "Intersect",
+      Substract(..) => "Substract",
+      DisjunctiveUnion(..) => "DisjunctiveUnion",
+      Filter(..) => "Filter",
+      Store(_) => "Store",
+      Traverse(..) => "Traverse",
+      ShortestPath(..) => "ShortestPath",
+      Aggregate(..) => "Aggregate",
+      Degree(..) => "Degree",
+      Order(..) => "Order",
+      Skip(..) => "Skip",
+      Limit(..) => "Limit",
+    }
+  }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "lua", derive(FromLua))]
 pub enum EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>
 {
@@ -131,6 +211,18 @@ pub enum EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>
   //  /// Execute some arbitrary function to modify
   //  /// the query context (side effect).
   //  SideEffect(Box<EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, Fn(EdgeId, QueryContext<VertexId, EdgeId>) -> QueryContext<VertexId, EdgeId>),
+  /// Sum up the selected edges and store the value in the variable
+  /// with the given name (side effect).
+  Aggregate(Box<EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, String, Aggregation),
+  /// Sort the selected edges. The order is kept by the following `Skip`
+  /// and `Limit` steps and in the result.
+  Order(Box<EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, SortOrder),
+  /// Leave out the first edges of the selection. Edges which were not
+  /// ordered before are taken in the order of their ids.
+  Skip(Box<EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, usize),
+  /// Only keep the first edges of the selection. Edges which were not
+  /// ordered before are taken in the order of their ids.
+  Limit(Box<EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>>, usize),
 }
 
 impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
@@ -162,6 +254,22 @@ impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> EdgeQuery<VertexId, EdgeId,
     EdgeQuery::Store(Box::new(self))
   }
 
+  pub fn aggregate(self, name: &str, aggregation: Aggregation) -> Self {
+    EdgeQuery::Aggregate(Box::new(self), name.to_string(), aggregation)
+  }
+
+  pub fn order_by(self, order: SortOrder) -> Self {
+    EdgeQuery::Order(Box::new(self), order)
+  }
+
+  pub fn skip(self, n: usize) -> Self {
+    EdgeQuery::Skip(Box::new(self), n)
+  }
+
+  pub fn limit(self, n: usize) -> Self {
+    EdgeQuery::Limit(Box::new(self), n)
+  }
+
   pub fn outgoing(self) -> VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
     VertexQuery::Out(self)
   }
@@ -169,14 +277,45 @@ impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> EdgeQuery<VertexId, EdgeId,
   pub fn ingoing(self) -> VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
     VertexQuery::In(self)
   }
+
+  pub fn filter(self, filter: EFilter) -> Self {
+    EdgeQuery::Filter(Box::new(self), filter)
+  }
+
+  /// The name of the outermost step
+  pub fn step_name(&self) -> &'static str {
+    use EdgeQuery::*;
+
+    match self {
+      All => "All",
+      Specific(_) => "Specific",
+      Property(_) => "Property",
+      Out(_) => "Out",
+      In(_) => "In",
+      Union(..) => "Union",
+      Intersect(..) => "Intersect",
+      Substract(..) => "Substract",
+      DisjunctiveUnion(..) => "DisjunctiveUnion",
+      Filter(..) => "Filter",
+      Store(_) => "Store",
+      Aggregate(..) => "Aggregate",
+      Order(..) => "Order",
+      Skip(..) => "Skip",
+      Limit(..) => "Limit",
+    }
+  }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PropertyQuery<PropertyId> {
   /// Query a specific property
   Specific(PropertyId),
-  /// All properties in between the two property ids
-  FromTo(PropertyId, PropertyId),
+  /// All properties with an ordered key in between (and including) the
+  /// two keys
+  FromTo(OrderedKey, OrderedKey),
+  /// All properties which have the value at the JSON pointer (e.g.
+  /// `/Person/surname`). The pointer needs a declared index.
+  JsonField(String, serde_json::Value),
   /// All properties that use this property
   ReferencingProperties(Box<PropertyQuery<PropertyId>>),
   /// All properties that are used by this property
@@ -188,8 +327,13 @@ impl<PropertyId> PropertyQuery<PropertyId> {
     PropertyQuery::Specific(id)
   }
 
-  pub fn from_to(id1: PropertyId, id2: PropertyId) -> Self {
-    PropertyQuery::FromTo(id1, id2)
+  pub fn from_to(from: OrderedKey, to: OrderedKey) -> Self {
+    PropertyQuery::FromTo(from, to)
+  }
+
+  /// All properties which have the value at the JSON pointer
+  pub fn from_json_field<V: Into<serde_json::Value>>(path: &str, value: V) -> Self {
+    PropertyQuery::JsonField(path.to_string(), value.into())
   }
 
   /// All properties that use this property
@@ -222,9 +366,22 @@ impl<PropertyId> PropertyQuery<PropertyId> {
   ) -> EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
     EdgeQuery::Property(self)
   }
+
+  /// The name of the outermost step
+  pub fn step_name(&self) -> &'static str {
+    use PropertyQuery::*;
+
+    match self {
+      Specific(_) => "Specific",
+      FromTo(..) => "FromTo",
+      ReferencingProperties(_) => "ReferencingProperties",
+      ReferencedProperties(_) => "ReferencedProperties",
+      JsonField(..) => "JsonField",
+    }
+  }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BasicQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
   V(VertexQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>),
   E(EdgeQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>),
@@ -249,6 +406,33 @@ impl<VertexId, EdgeId, PropertyId, VFilter, EFilter> From<PropertyQuery<Property
   }
 }
 
+/// An order preserving property key (see
+/// `schema::OrderedProperty`)
+///
+/// The bytes are hex encoded, so that the textual order of the keys is
+/// the same as the order of the bytes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OrderedKey(pub String);
+
+impl OrderedKey {
+  /// The key shortened to at most `len` bytes
+  pub fn truncated(&self, len: usize) -> &str {
+    &self.0[..self.0.len().min(2 * len)]
+  }
+}
+
+impl From<&[u8]> for OrderedKey {
+  fn from(key: &[u8]) -> Self {
+    OrderedKey(key.iter().map(|b| format!("{b:02X}")).collect())
+  }
+}
+
+impl From<Vec<u8>> for OrderedKey {
+  fn from(key: Vec<u8>) -> Self {
+    key.as_slice().into()
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VertexQueryContext<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> {
   /// The current vertex
@@ -264,6 +448,9 @@ pub struct VertexQueryContext<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> {
   pub v_store: HashSet<VertexId>,
   /// Edges stored with the store action
   pub e_store: HashSet<EdgeId>,
+  /// The position in the selection if it was ordered
+  #[serde(default)]
+  pub position: Option<usize>,
 }
 
 impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> VertexQueryContext<VertexId, EdgeId> {
@@ -275,6 +462,7 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> VertexQueryContext<VertexId
       variables: HashMap::default(),
       v_store: HashSet::default(),
       e_store: HashSet::default(),
+      position: None,
     }
   }
 
@@ -286,6 +474,7 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> VertexQueryContext<VertexId
       variables,
       v_store,
       e_store,
+      position: _,
     } = self;
 
     path.push((vid, id.clone()));
@@ -297,6 +486,7 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> VertexQueryContext<VertexId
       variables,
       v_store,
       e_store,
+      position: None,
     }
   }
 }
@@ -316,6 +506,9 @@ pub struct EdgeQueryContext<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> {
   pub v_store: HashSet<VertexId>,
   /// Edges stored with the store action
   pub e_store: HashSet<EdgeId>,
+  /// The position in the selection if it was ordered
+  #[serde(default)]
+  pub position: Option<usize>,
 }
 
 impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> EdgeQueryContext<VertexId, EdgeId> {
@@ -327,6 +520,7 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> EdgeQueryContext<VertexId,
       variables: HashMap::default(),
       v_store: HashSet::default(),
       e_store: HashSet::default(),
+      position: None,
     }
   }
 
@@ -338,6 +532,7 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> EdgeQueryContext<VertexId,
       variables,
       v_store,
       e_store,
+      position: _,
     } = self;
 
     VertexQueryContext {
@@ -347,16 +542,327 @@ impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> EdgeQueryContext<VertexId,
       variables,
       v_store,
       e_store,
+      position: None,
     }
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A filter running a script for each element
+///
+/// The interpreter `lua` is built in (if the `lua` feature is enabled).
+/// The script gets the globals `id` and `properties` and has to return a
+/// boolean.
+///
+/// Any other interpreter is taken as a command line (e.g. `sh -c` or
+/// `python3 -c`) to which the script is appended as last argument. The
+/// process gets `{"id": .., "properties": ..}` as json on stdin. If it
+/// exits with 0 the element is kept, otherwise it is filtered out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShellFilter {
   pub interpreter: String,
   pub script: String,
 }
 
+impl ShellFilter {
+  /// Prepare the filter to be run on several elements
+  pub fn runner(&self) -> Result<FilterRunner, FilterError> {
+    #[cfg(feature="lua")]
+    if self.interpreter == "lua" {
+      let lua = mlua::Lua::new();
+      let function = lua.load(&self.script).into_function()?;
+      return Ok(FilterRunner::Lua(lua, function));
+    }
+
+    let mut command = self.interpreter.split_whitespace();
+    let program = command.next()
+      .ok_or_else(|| FilterError::UnknownInterpreter(self.interpreter.clone()))?;
+    Ok(FilterRunner::Process {
+      program: program.to_string(),
+      args: command.map(str::to_string).collect(),
+      script: self.script.clone(),
+    })
+  }
+}
+
+pub enum FilterRunner {
+  #[cfg(feature="lua")]
+  Lua(mlua::Lua, mlua::Function),
+  Process {
+    program: String,
+    args: Vec<String>,
+    script: String,
+  },
+}
+
+impl FilterRunner {
+  /// Check if an element should be kept
+  pub fn matches(&self, id: &serde_json::Value, properties: &serde_json::Value) -> Result<bool, FilterError> {
+    match self {
+      #[cfg(feature="lua")]
+      FilterRunner::Lua(lua, function) => {
+        use mlua::LuaSerdeExt;
+
+        lua.globals().set("id", lua.to_value(id)?)?;
+        lua.globals().set("properties", lua.to_value(properties)?)?;
+        Ok(function.call::<bool>(())?)
+      }
+      FilterRunner::Process { program, args, script } => {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new(program)
+          .args(args)
+          .arg(script)
+          .stdin(Stdio::piped())
+          .stdout(Stdio::null())
+          .spawn()?;
+        let input = serde_json::json!({
+          "id": id,
+          "properties": properties,
+        });
+        if let Some(mut stdin) = child.stdin.take() {
+          // the script does not need to read its input
+          match stdin.write_all(input.to_string().as_bytes()) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => (),
+            result => result?,
+          }
+        }
+        let status = child.wait()?;
+        match status.code() {
+          Some(code) => Ok(code == 0),
+          None => Err(FilterError::Aborted(program.to_string())),
+        }
+      }
+    }
+  }
+}
+
+#[derive(Error, Debug)]
+pub enum FilterError {
+  #[error("no interpreter given for the filter: {0:?}")]
+  UnknownInterpreter(String),
+  #[error("could not run the filter")]
+  Io { #[from] source: std::io::Error },
+  #[error("the filter {0} was aborted")]
+  Aborted(String),
+  #[cfg(feature="lua")]
+  #[error("lua filter failed: {0}")]
+  Lua(String),
+}
+
+#[cfg(feature="lua")]
+impl From<mlua::Error> for FilterError {
+  fn from(e: mlua::Error) -> Self {
+    FilterError::Lua(e.to_string())
+  }
+}
+
+/// Combine the contexts if the same element is reached on different ways
+///
+/// The path of the context is kept, but the side effects (stored
+/// elements and variables) of both contexts are joined.
+pub trait MergeContext {
+  fn merge(&mut self, other: Self);
+}
+
+impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> MergeContext for VertexQueryContext<VertexId, EdgeId> {
+  fn merge(&mut self, other: Self) {
+    self.variables.extend(other.variables);
+    self.v_store.extend(other.v_store);
+    self.e_store.extend(other.e_store);
+  }
+}
+
+impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> MergeContext for EdgeQueryContext<VertexId, EdgeId> {
+  fn merge(&mut self, other: Self) {
+    self.variables.extend(other.variables);
+    self.v_store.extend(other.v_store);
+    self.e_store.extend(other.e_store);
+  }
+}
+
+/// How an `Aggregate` step sums up the selected elements
+///
+/// Elements without a value at the JSON pointer are not counted. Values
+/// which are no strings are used in their JSON form as key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Aggregation {
+  /// The number of elements
+  Count,
+  /// The number of elements per value at the JSON pointer into their
+  /// properties (`""` for the whole property)
+  GroupBy(String),
+  /// The number of elements per value at the JSON pointer into the
+  /// properties nested in their properties (e.g. their schema type)
+  GroupByNested(String),
+}
+
+/// Which edges a `Degree` step counts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DegreeCount<PropertyId> {
+  pub direction: Direction,
+  /// Only count edges with one of these properties
+  pub edges: Option<PropertyQuery<PropertyId>>,
+}
+
+impl<PropertyId> DegreeCount<PropertyId> {
+  /// Count the edges in a direction
+  pub fn new(direction: Direction) -> Self {
+    DegreeCount {
+      direction,
+      edges: None,
+    }
+  }
+
+  /// Only count edges with one of these properties
+  pub fn along(self, edges: PropertyQuery<PropertyId>) -> Self {
+    DegreeCount { edges: Some(edges), ..self }
+  }
+}
+
+/// The direction in which edges are followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+  /// Follow the outgoing edges (like `outgoing().outgoing()`)
+  Outgoing,
+  /// Follow the incoming edges (like `ingoing().ingoing()`)
+  Ingoing,
+  /// Follow edges in both directions
+  Both,
+}
+
+/// How a `Traverse` step follows the edges
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Traversal<PropertyId> {
+  pub direction: Direction,
+  /// The minimal number of hops (0 includes the start vertices)
+  pub min: usize,
+  /// The maximal number of hops (unlimited if not set)
+  pub max: Option<usize>,
+  /// Only follow edges with one of these properties
+  pub edges: Option<PropertyQuery<PropertyId>>,
+}
+
+impl<PropertyId> Traversal<PropertyId> {
+  /// Follow edges in a direction for at least one hop
+  pub fn new(direction: Direction) -> Self {
+    Traversal {
+      direction,
+      min: 1,
+      max: None,
+      edges: None,
+    }
+  }
+
+  /// Limit the number of hops
+  pub fn depth(self, min: usize, max: Option<usize>) -> Self {
+    Traversal { min, max, ..self }
+  }
+
+  /// Only follow edges with one of these properties
+  pub fn along(self, edges: PropertyQuery<PropertyId>) -> Self {
+    Traversal { edges: Some(edges), ..self }
+  }
+}
+
+/// How a `ShortestPath` step searches for the paths
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathSearch<PropertyId> {
+  pub direction: Direction,
+  /// Only follow edges with one of these properties
+  pub edges: Option<PropertyQuery<PropertyId>>,
+  /// The JSON pointer to the weight in the properties of the edges
+  /// (e.g. `/Road/length`). Without a weight every edge counts as one.
+  pub weight: Option<String>,
+}
+
+impl<PropertyId> PathSearch<PropertyId> {
+  /// Search the paths with the fewest edges
+  pub fn new(direction: Direction) -> Self {
+    PathSearch {
+      direction,
+      edges: None,
+      weight: None,
+    }
+  }
+
+  /// Only follow edges with one of these properties
+  pub fn along(self, edges: PropertyQuery<PropertyId>) -> Self {
+    PathSearch { edges: Some(edges), ..self }
+  }
+
+  /// Search the paths with the lowest sum of the weights
+  pub fn weighted(self, pointer: &str) -> Self {
+    PathSearch { weight: Some(pointer.to_string()), ..self }
+  }
+}
+
+/// How an `Order` step sorts the selected elements
+///
+/// Elements with the same value are sorted by their id and elements
+/// without a value at the JSON pointer follow at the end. Values of
+/// different types are ordered null, booleans, numbers, strings, arrays
+/// and objects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortOrder {
+  /// The JSON pointer into the properties of the elements (ordered by
+  /// the ids if not set)
+  pub field: Option<String>,
+  pub descending: bool,
+}
+
+impl SortOrder {
+  /// Sort the elements by their ids
+  pub fn by_id() -> Self {
+    SortOrder {
+      field: None,
+      descending: false,
+    }
+  }
+
+  /// Sort the elements by the value at the JSON pointer into their
+  /// properties (`""` for the whole property)
+  pub fn by_field(pointer: &str) -> Self {
+    SortOrder {
+      field: Some(pointer.to_string()),
+      descending: false,
+    }
+  }
+
+  /// Start with the highest value
+  pub fn descending(self) -> Self {
+    SortOrder { descending: true, ..self }
+  }
+}
+
+/// The position of an element in an ordered selection
+///
+/// A hop to other elements starts without a position again.
+pub trait OrderedContext {
+  fn position(&self) -> Option<usize>;
+  fn set_position(&mut self, position: Option<usize>);
+}
+
+impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> OrderedContext for VertexQueryContext<VertexId, EdgeId> {
+  fn position(&self) -> Option<usize> {
+    self.position
+  }
+
+  fn set_position(&mut self, position: Option<usize>) {
+    self.position = position;
+  }
+}
+
+impl<VertexId: Hash + Eq, EdgeId: Hash + Eq + Clone> OrderedContext for EdgeQueryContext<VertexId, EdgeId> {
+  fn position(&self) -> Option<usize> {
+    self.position
+  }
+
+  fn set_position(&mut self, position: Option<usize>) {
+    self.position = position;
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct QueryResult<VertexId, EdgeId, PropertyId>
 where
@@ -364,13 +870,23 @@ where
   EdgeId: Hash + Eq + Clone,
   PropertyId: Hash + Eq,
 {
-  /// All vertices matched by the query
-  pub vertices: HashSet<(VertexId, Option<PropertyId>)>,
-  /// All edges matched by the query
-  pub edges: HashSet<(EdgeId, Option<PropertyId>)>,
+  /// All vertices matched by the query (in the order of the query if it
+  /// was ordered) and the ids of their properties (if the graph store
+  /// filled them in)
+  pub vertices: IndexSet<(VertexId, Option<PropertyId>)>,
+  /// All edges matched by the query (in the order of the query if it
+  /// was ordered) and the ids of their properties (if the graph store
+  /// filled them in)
+  pub edges: IndexSet<(EdgeId, Option<PropertyId>)>,
   /// All Paths matched by the query
   pub paths: Vec<(Option<EdgeId>, Vec<(VertexId, EdgeId)>, Option<VertexId>)>,
+  /// All properties matched by the query
+  pub properties: HashSet<PropertyId>,
   pub variables: HashMap<String, serde_json::Value>,
+  /// The token to fetch the next page if the query ended with a `Limit`
+  /// step and there are more elements
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cursor: Option<String>,
 }
 
 impl<VertexId, EdgeId, PropertyId> QueryResult<VertexId, EdgeId, PropertyId>
@@ -381,31 +897,36 @@ where
 {
   pub fn new() -> Self {
     Self {
-      vertices: HashSet::default(),
-      edges: HashSet::default(),
+      vertices: IndexSet::default(),
+      edges: IndexSet::default(),
       paths: Vec::new(),
+      properties: HashSet::default(),
       variables: HashMap::default(),
+      cursor: None,
     }
   }
 }
 
 impl<VertexId, EdgeId, PropertyId> From<HashMap<VertexId, VertexQueryContext<VertexId, EdgeId>>> for QueryResult<VertexId, EdgeId, PropertyId>
 where
-  VertexId: Hash + Eq,
+  VertexId: Hash + Eq + Clone,
   EdgeId: Hash + Eq + Clone,
   PropertyId: Hash + Eq,
 {
-  fn from(mut item: HashMap<VertexId, VertexQueryContext<VertexId, EdgeId>>) -> Self {
+  fn from(item: HashMap<VertexId, VertexQueryContext<VertexId, EdgeId>>) -> Self {
     let QueryResult {
       mut vertices,
       mut edges,
       mut paths,
+      properties,
       mut variables,
+      cursor,
     } = QueryResult::new();
 
-    for (id,ctx) in item.drain() {
-      vertices.insert((id, None));
+    let item = in_position_order(item);
+    vertices.extend(item.iter().map(|(id, _)| (id.clone(), None)));
 
+    for (_, ctx) in item.into_iter() {
       let VertexQueryContext {
         id,
         path,
@@ -413,6 +934,7 @@ where
         variables: ctx_vars,
         v_store,
         e_store,
+        position: _,
       } = ctx;
 
       vertices.extend(v_store.into_iter().map(|v| {(v, None)}));
@@ -425,7 +947,9 @@ where
       vertices,
       edges,
       paths,
+      properties,
       variables,
+      cursor,
     }
   }
 }
@@ -436,17 +960,20 @@ where
   EdgeId: Hash + Eq + Clone,
   PropertyId: Hash + Eq,
 {
-  fn from(mut item: HashMap<EdgeId, EdgeQueryContext<VertexId, EdgeId>>) -> Self {
+  fn from(item: HashMap<EdgeId, EdgeQueryContext<VertexId, EdgeId>>) -> Self {
     let QueryResult {
       mut vertices,
       mut edges,
       mut paths,
+      properties,
       mut variables,
+      cursor,
     } = QueryResult::new();
 
-    for (id,ctx) in item.drain() {
-      edges.insert((id, None));
+    let item = in_position_order(item);
+    edges.extend(item.iter().map(|(id, _)| (id.clone(), None)));
 
+    for (_, ctx) in item.into_iter() {
       let EdgeQueryContext {
         id: _,
         path,
@@ -454,6 +981,7 @@ where
         variables: ctx_vars,
         v_store,
         e_store,
+        position: _,
       } = ctx;
 
       vertices.extend(v_store.into_iter().map(|v| {(v, None)}));
@@ -466,7 +994,129 @@ where
       vertices,
       edges,
       paths,
+      properties,
       variables,
+      cursor,
+    }
+  }
+}
+
+/// The elements sorted by their positions (elements without a position
+/// follow at the end)
+fn in_position_order<K, V: OrderedContext>(item: HashMap<K, V>) -> Vec<(K, V)> {
+  let mut item: Vec<_> = item.into_iter().collect();
+  item.sort_by_key(|(_, ctx)| ctx.position().unwrap_or(usize::MAX));
+  item
+}
+
+impl<VertexId, EdgeId, PropertyId> From<HashSet<PropertyId>> for QueryResult<VertexId, EdgeId, PropertyId>
+where
+  VertexId: Hash + Eq,
+  EdgeId: Hash + Eq + Clone,
+  PropertyId: Hash + Eq,
+{
+  fn from(properties: HashSet<PropertyId>) -> Self {
+    QueryResult {
+      properties,
+      ..QueryResult::new()
+    }
+  }
+}
+
+/// A graph element together with its property
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedElement<Id, PropertyId, P> {
+  pub id: Id,
+  pub property_id: PropertyId,
+  pub property: P,
+}
+
+/// A vertex on a path and the edge used to leave it
+pub type TypedHop<VertexId, EdgeId, PropertyId, P> = (
+  TypedElement<VertexId, PropertyId, P>,
+  TypedElement<EdgeId, PropertyId, P>,
+);
+
+/// A path of a query with the properties of every hop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedPath<VertexId, EdgeId, PropertyId, P> {
+  /// The edge the path started with (if the query started on edges)
+  pub start: Option<TypedElement<EdgeId, PropertyId, P>>,
+  /// The vertices and the edges used to leave them
+  pub hops: Vec<TypedHop<VertexId, EdgeId, PropertyId, P>>,
+  /// The vertex the path ended with (if the query ended on vertices)
+  pub end: Option<TypedElement<VertexId, PropertyId, P>>,
+}
+
+/// A `QueryResult` with the properties of all elements resolved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypedQueryResult<VertexId, EdgeId, PropertyId, P> {
+  /// All vertices matched by the query
+  pub vertices: Vec<TypedElement<VertexId, PropertyId, P>>,
+  /// All edges matched by the query
+  pub edges: Vec<TypedElement<EdgeId, PropertyId, P>>,
+  /// All Paths matched by the query
+  pub paths: Vec<TypedPath<VertexId, EdgeId, PropertyId, P>>,
+  /// All properties matched by the query
+  pub properties: Vec<(PropertyId, P)>,
+  pub variables: HashMap<String, serde_json::Value>,
+}
+
+/// The evaluation of a step of a query together with the steps it was
+/// evaluated from (the output of an `explain`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryPlan {
+  /// The name of the step
+  pub step: String,
+  /// The number of elements expected before the query was run (if it
+  /// can be known in advance)
+  pub estimated: Option<usize>,
+  /// The number of elements selected by the step
+  pub actual: usize,
+  /// The number of records read from the kv store by the step itself
+  pub reads: usize,
+  pub steps: Vec<QueryPlan>,
+}
+
+impl QueryPlan {
+  pub fn new(step: &str, estimated: Option<usize>) -> Self {
+    QueryPlan {
+      step: step.to_string(),
+      estimated,
+      actual: 0,
+      reads: 0,
+      steps: Vec::new(),
     }
   }
+
+  /// The number of records read by the step and all steps it was
+  /// evaluated from
+  pub fn total_reads(&self) -> usize {
+    self.reads + self.steps.iter().map(|step| step.total_reads()).sum::<usize>()
+  }
+
+  fn write_tree(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+    let estimated = match self.estimated {
+      Some(estimated) => estimated.to_string(),
+      None => "?".to_string(),
+    };
+    writeln!(
+      f,
+      "{}{} (estimated: {estimated}, actual: {}, reads: {})",
+      "  ".repeat(depth),
+      self.step,
+      self.actual,
+      self.reads,
+    )?;
+    for step in self.steps.iter() {
+      step.write_tree(f, depth + 1)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for QueryPlan {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.write_tree(f, 0)
+  }
 }
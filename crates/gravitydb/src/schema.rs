@@ -12,11 +12,6 @@ pub trait KeyAdressableElement<K: Sized> {
   fn start(&self) -> crate::ql::PropertyQuery<K> {
     crate::ql::PropertyQuery::from_id(self.get_key())
   }
-
-  /// A starting point for a range of properties on an ordered scale
-  fn from_to(&self, to: &Self) -> crate::ql::PropertyQuery<K> {
-    crate::ql::PropertyQuery::from_to(self.get_key(), to.get_key())
-  }
 }
 
 pub trait NestableProperty: Sized
@@ -24,8 +19,121 @@ pub trait NestableProperty: Sized
   fn nested(&self) -> Vec<Self>;
 }
 
-pub trait Property<K: Sized, E>: Sized + SchemaElement<E> + KeyAdressableElement<K> + NestableProperty {}
-impl<T: Sized + SchemaElement<E> + NestableProperty + KeyAdressableElement<K>, K: Sized, E> Property<K, E> for T {}
+/// Properties which can be placed on an ordered scale
+///
+/// The hash key of a property says nothing about its value. Properties
+/// returning an ordered key are additionally written into a sorted
+/// index, so that ranges of values (numbers, timestamps, names, ...)
+/// can be queried.
+pub trait OrderedProperty {
+  /// A key which sorts (bytewise) in the same order as the property
+  /// values or `None` if the property has no natural order
+  fn ordered_key(&self) -> Option<Vec<u8>> { None }
+
+  /// A starting point for a range of properties on an ordered scale
+  ///
+  /// Properties without an ordered key result in an empty range.
+  fn range_to<K>(&self, to: &Self) -> crate::ql::PropertyQuery<K> {
+    crate::ql::PropertyQuery::from_to(
+      self.ordered_key().unwrap_or_default().into(),
+      to.ordered_key().unwrap_or_default().into(),
+    )
+  }
+}
+
+/// Encode a value into bytes which sort in the same order as the value
+/// itself
+pub trait OrderedEncoding {
+  fn encode_ordered(&self, key: &mut Vec<u8>);
+}
+
+macro_rules! ordered_unsigned {
+  ($($t:ty),*) => {$(
+    impl OrderedEncoding for $t {
+      fn encode_ordered(&self, key: &mut Vec<u8>) {
+        key.extend_from_slice(&self.to_be_bytes());
+      }
+    }
+  )*}
+}
+
+macro_rules! ordered_signed {
+  ($($t:ty => $u:ty),*) => {$(
+    impl OrderedEncoding for $t {
+      fn encode_ordered(&self, key: &mut Vec<u8>) {
+        // flipping the sign bit puts negative numbers in front
+        let value = (*self as $u) ^ (1 << (<$u>::BITS - 1));
+        key.extend_from_slice(&value.to_be_bytes());
+      }
+    }
+  )*}
+}
+
+macro_rules! ordered_float {
+  ($($t:ty => $u:ty),*) => {$(
+    impl OrderedEncoding for $t {
+      fn encode_ordered(&self, key: &mut Vec<u8>) {
+        let bits = self.to_bits();
+        let sign: $u = 1 << (<$u>::BITS - 1);
+        // negative numbers are sorted in reverse
+        let bits = if bits & sign != 0 { !bits } else { bits | sign };
+        key.extend_from_slice(&bits.to_be_bytes());
+      }
+    }
+  )*}
+}
+
+ordered_unsigned!(u8, u16, u32, u64, u128, usize);
+ordered_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+ordered_float!(f32 => u32, f64 => u64);
+
+impl OrderedEncoding for bool {
+  fn encode_ordered(&self, key: &mut Vec<u8>) {
+    key.push(*self as u8);
+  }
+}
+
+impl OrderedEncoding for char {
+  fn encode_ordered(&self, key: &mut Vec<u8>) {
+    (*self as u32).encode_ordered(key);
+  }
+}
+
+impl OrderedEncoding for str {
+  /// Zero bytes are escaped and the string is terminated, so that a
+  /// prefix always sorts in front of the longer string and further
+  /// fields can follow.
+  fn encode_ordered(&self, key: &mut Vec<u8>) {
+    for b in self.bytes() {
+      key.push(b);
+      if b == 0 {
+        key.push(0xFF);
+      }
+    }
+    key.extend_from_slice(&[0, 0]);
+  }
+}
+
+impl OrderedEncoding for String {
+  fn encode_ordered(&self, key: &mut Vec<u8>) {
+    self.as_str().encode_ordered(key);
+  }
+}
+
+impl<T: OrderedEncoding> OrderedEncoding for Option<T> {
+  fn encode_ordered(&self, key: &mut Vec<u8>) {
+    match self {
+      None => key.push(0),
+      Some(value) => {
+        key.push(1);
+        value.encode_ordered(key);
+      }
+    }
+  }
+}
+
+pub trait Property<K: Sized, E>: Sized + SchemaElement<E> + KeyAdressableElement<K> + NestableProperty + OrderedProperty {}
+impl<T: Sized + SchemaElement<E> + NestableProperty + KeyAdressableElement<K> + OrderedProperty, K: Sized, E> Property<K, E> for T {}
 
 pub enum SchemaConstraint<VertexId, EdgeId, PropertyId, VFilter, EFilter> {
   Requiered(ql::BasicQuery<VertexId, EdgeId, PropertyId, VFilter, EFilter>),
@@ -114,6 +222,8 @@ impl NestableProperty for GenericProperty {
   fn nested(&self) -> Vec<Self> { Vec::new() }
 }
 
+impl OrderedProperty for GenericProperty {}
+
 #[cfg(feature="lua")]
 impl UserData for GenericProperty {}
 
@@ -139,3 +249,5 @@ impl<E> SchemaElement<E> for Vec<u8>
 impl NestableProperty for Vec<u8> {
   fn nested(&self) -> Vec<Self> { Vec::new() }
 }
+
+impl OrderedProperty for Vec<u8> {}
@@ -0,0 +1,344 @@
+//! Versioned serialisation of queries and query results
+//!
+//! Queries and their results are wrapped in an envelope
+//! `{"version": .., "data": ..}` when they are stored or transmitted.
+//! Documents written by an older version are migrated to the current
+//! AST when they are read. Documents without an envelope were written
+//! before the envelope was introduced (version 1 or 2). Results are
+//! read as version 1, the version of queries is told apart by the shape
+//! of their keys (see [`bare_query_version`]).
+//!
+//! Because queries can be put together anywhere, the steps of a query
+//! can also be checked against an older version (e.g. before sending it
+//! to an older database).
+
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The version of the format written by this library
+///
+/// * 1: queries and results without an envelope
+/// * 2: `FromTo` uses ordered keys instead of property ids,
+///   `JsonField` was added and results contain `properties`
+/// * 3: `Traverse` was added
+/// * 4: `ShortestPath` was added
+/// * 5: `Aggregate` and `Degree` were added
+/// * 6: `Order`, `Skip` and `Limit` were added and results may contain
+///   a `cursor`
+pub const FORMAT_VERSION: u32 = 6;
+
+/// A document together with the version of its format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Versioned<T> {
+  pub version: u32,
+  pub data: T,
+}
+
+impl<T> Versioned<T> {
+  /// Wrap the data with the current version
+  pub fn new(data: T) -> Self {
+    Versioned {
+      version: FORMAT_VERSION,
+      data,
+    }
+  }
+}
+
+/// A step of a query, which can not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedStep {
+  /// The JSON pointer to the step in the query
+  pub path: String,
+  /// The name of the step
+  pub step: String,
+}
+
+impl std::fmt::Display for UnsupportedStep {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "'{}' at '{}'", self.step, self.path)
+  }
+}
+
+#[derive(Error, Debug)]
+pub enum VersionError {
+  #[error("format version {0} is not supported (the current version is {FORMAT_VERSION})")]
+  UnsupportedVersion(u32),
+  #[error("steps not supported in version {version}: {}", list(.steps))]
+  UnsupportedSteps {
+    version: u32,
+    steps: Vec<UnsupportedStep>,
+  },
+  #[error("the step {step} of version {version} can not be migrated to the current version")]
+  NotMigratable {
+    version: u32,
+    step: UnsupportedStep,
+  },
+  #[error("invalid document")]
+  Serialisation { #[from] source: serde_json::Error },
+}
+
+fn list(steps: &[UnsupportedStep]) -> String {
+  steps.iter()
+    .map(|s| s.to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Serialise a query with the current version
+pub fn query_to_vec<Q: Serialize>(query: &Q) -> Result<Vec<u8>, VersionError> {
+  Ok(serde_json::to_vec(&Versioned::new(query))?)
+}
+
+/// Read a query of any supported version
+///
+/// Older queries are migrated and unknown steps are reported as
+/// `VersionError::UnsupportedSteps`.
+pub fn query_from_slice<Q: DeserializeOwned>(data: &[u8]) -> Result<Q, VersionError> {
+  let (version, query) = read_envelope(data)?;
+  let version = version.unwrap_or_else(|| bare_query_version(&query));
+  let query = migrate_query(query, version)?;
+  check_steps(&query, FORMAT_VERSION)?;
+
+  Ok(serde_json::from_value(query)?)
+}
+
+/// Serialise a query result with the current version
+pub fn result_to_vec<R: Serialize>(result: &R) -> Result<Vec<u8>, VersionError> {
+  Ok(serde_json::to_vec(&Versioned::new(result))?)
+}
+
+/// Read a query result of any supported version
+pub fn result_from_slice<R: DeserializeOwned>(data: &[u8]) -> Result<R, VersionError> {
+  let (version, result) = read_envelope(data)?;
+  let result = migrate_result(result, version.unwrap_or(1))?;
+
+  Ok(serde_json::from_value(result)?)
+}
+
+/// Check if all steps of the query are available in a version
+pub fn check_query<Q: Serialize>(query: &Q, version: u32) -> Result<(), VersionError> {
+  check_steps(&serde_json::to_value(query)?, version)
+}
+
+/// All steps of a serialised query, which are not available in a
+/// version
+pub fn unsupported_steps(query: &Value, version: u32) -> Vec<UnsupportedStep> {
+  let mut unsupported = Vec::new();
+  walk(query, Part::Query, "", &mut |_part, step, since| {
+    if since.is_none_or(|since| since > version) {
+      unsupported.push(step);
+    }
+  });
+  unsupported
+}
+
+fn check_steps(query: &Value, version: u32) -> Result<(), VersionError> {
+  let steps = unsupported_steps(query, version);
+  if steps.is_empty() {
+    Ok(())
+  } else {
+    Err(VersionError::UnsupportedSteps { version, steps })
+  }
+}
+
+/// Split a document into its version (`None` without envelope) and data
+fn read_envelope(data: &[u8]) -> Result<(Option<u32>, Value), VersionError> {
+  let document: Value = serde_json::from_slice(data)?;
+  let is_envelope = match &document {
+    Value::Object(map) => map.len() == 2 && map.contains_key("version") && map.contains_key("data"),
+    _ => false,
+  };
+  if !is_envelope {
+    return Ok((None, document));
+  }
+
+  let envelope: Versioned<Value> = serde_json::from_value(document)?;
+  if envelope.version == 0 || envelope.version > FORMAT_VERSION {
+    return Err(VersionError::UnsupportedVersion(envelope.version));
+  }
+  Ok((Some(envelope.version), envelope.data))
+}
+
+/// The version of a serialised query without envelope
+///
+/// Before the envelope was introduced, `FromTo` changed from a range of
+/// property ids (version 1) to a range of ordered keys (version 2) and
+/// `JsonField` was added. Property ids are SHA-256 hashes (64 hex
+/// digits), ordered keys are hex strings of any even length. A query is
+/// of version 2, if it uses `JsonField` or a `FromTo` over keys which
+/// are ordered keys but not all property ids. Everything else is read
+/// as version 1, so that a range which could be either is rejected
+/// instead of silently changing its meaning.
+pub fn bare_query_version(query: &Value) -> u32 {
+  let mut version = 1;
+  walk(query, Part::Query, "", &mut |part, step, _since| {
+    if part != Part::Properties {
+      return;
+    }
+    let ordered = match step.step.as_str() {
+      "JsonField" => true,
+      "FromTo" => query.pointer(&step.path)
+        .and_then(|properties| properties.get("FromTo"))
+        .and_then(Value::as_array)
+        .is_some_and(|keys| keys.iter().all(is_ordered_key) && !keys.iter().all(is_property_id)),
+      _ => false,
+    };
+    if ordered {
+      version = 2;
+    }
+  });
+  version
+}
+
+fn is_ordered_key(key: &Value) -> bool {
+  key.as_str().is_some_and(|key| {
+    key.len() % 2 == 0 && key.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c))
+  })
+}
+
+fn is_property_id(key: &Value) -> bool {
+  key.as_str().is_some_and(|key| key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Migrate a serialised query to the current version
+pub fn migrate_query(mut query: Value, from: u32) -> Result<Value, VersionError> {
+  for version in from..FORMAT_VERSION {
+    query = match version {
+      1 => {
+        // `FromTo` was a range of property ids, which has no meaning
+        // for ordered keys
+        let mut removed = None;
+        walk(&query, Part::Query, "", &mut |part, step, _since| {
+          if part == Part::Properties && step.step == "FromTo" && removed.is_none() {
+            removed = Some(step);
+          }
+        });
+        if let Some(step) = removed {
+          return Err(VersionError::NotMigratable { version, step });
+        }
+        query
+      }
+      2..=5 => query,
+      _ => return Err(VersionError::UnsupportedVersion(version)),
+    };
+  }
+  Ok(query)
+}
+
+/// Migrate a serialised query result to the current version
+pub fn migrate_result(mut result: Value, from: u32) -> Result<Value, VersionError> {
+  for version in from..FORMAT_VERSION {
+    result = match version {
+      1 => {
+        if let Value::Object(map) = &mut result {
+          map.entry("properties").or_insert(Value::Array(Vec::new()));
+        }
+        result
+      }
+      2..=5 => result,
+      _ => return Err(VersionError::UnsupportedVersion(version)),
+    };
+  }
+  Ok(result)
+}
+
+/// The kind of (sub)query a serialised value stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+  Query,
+  Vertices,
+  Edges,
+  Properties,
+  /// The parameters of a `Traverse`, `ShortestPath` or `Degree` step
+  /// (with the allowed edges in `edges`)
+  Hops,
+  /// Ids, keys, filters etc.
+  Data,
+}
+
+/// The version a step was introduced in and the parts of its arguments
+fn step(part: Part, name: &str) -> Option<(u32, &'static [Part])> {
+  use Part::*;
+
+  let step: (u32, &'static [Part]) = match (part, name) {
+    (Query, "V") => (1, &[Vertices]),
+    (Query, "E") => (1, &[Edges]),
+    (Query, "P") => (1, &[Properties]),
+    (Vertices | Edges, "All") => (1, &[]),
+    (Vertices | Edges, "Specific") => (1, &[Data]),
+    (Vertices | Edges, "Property") => (1, &[Properties]),
+    (Vertices, "Out" | "In") => (1, &[Edges]),
+    (Edges, "Out" | "In") => (1, &[Vertices]),
+    (Vertices, "Union" | "Intersect" | "Substract" | "DisjunctiveUnion") => (1, &[Vertices, Vertices]),
+    (Edges, "Union" | "Intersect" | "Substract" | "DisjunctiveUnion") => (1, &[Edges, Edges]),
+    (Vertices, "Filter") => (1, &[Vertices, Data]),
+    (Edges, "Filter") => (1, &[Edges, Data]),
+    (Vertices, "Store") => (1, &[Vertices]),
+    (Edges, "Store") => (1, &[Edges]),
+    (Vertices, "Traverse") => (3, &[Vertices, Hops]),
+    (Vertices, "ShortestPath") => (4, &[Vertices, Vertices, Hops]),
+    (Vertices, "Aggregate") => (5, &[Vertices, Data, Data]),
+    (Edges, "Aggregate") => (5, &[Edges, Data, Data]),
+    (Vertices, "Degree") => (5, &[Vertices, Data, Hops]),
+    (Vertices, "Order" | "Skip" | "Limit") => (6, &[Vertices, Data]),
+    (Edges, "Order" | "Skip" | "Limit") => (6, &[Edges, Data]),
+    (Properties, "Specific") => (1, &[Data]),
+    (Properties, "FromTo") => (2, &[Data, Data]),
+    (Properties, "ReferencingProperties" | "ReferencedProperties") => (1, &[Properties]),
+    (Properties, "JsonField") => (2, &[Data, Data]),
+    _ => return None,
+  };
+  Some(step)
+}
+
+/// Visit all steps of a serialised (sub)query together with the version
+/// they were introduced in (or `None` for unknown steps)
+fn walk<F>(value: &Value, part: Part, path: &str, visit: &mut F)
+where
+  F: FnMut(Part, UnsupportedStep, Option<u32>),
+{
+  match part {
+    Part::Data => return,
+    Part::Hops => {
+      if let Some(edges) = value.get("edges").filter(|edges| !edges.is_null()) {
+        walk(edges, Part::Properties, &format!("{path}/edges"), visit);
+      }
+      return;
+    }
+    _ => (),
+  }
+
+  // unit variants are serialised as string, all others as map with a
+  // single entry
+  let (name, args) = match value {
+    Value::String(name) => (name, None),
+    Value::Object(map) if map.len() == 1 => match map.iter().next() {
+      Some((name, args)) => (name, Some(args)),
+      None => return,
+    },
+    // malformed documents are reported when they are deserialised
+    _ => return,
+  };
+
+  let found = UnsupportedStep {
+    path: path.to_string(),
+    step: name.clone(),
+  };
+  let Some((since, parts)) = step(part, name) else {
+    visit(part, found, None);
+    return;
+  };
+  visit(part, found, Some(since));
+
+  let path = format!("{path}/{name}");
+  match (parts, args) {
+    ([part], Some(arg)) => walk(arg, *part, &path, visit),
+    (parts, Some(Value::Array(args))) if parts.len() > 1 => {
+      for (i, (part, arg)) in parts.iter().zip(args).enumerate() {
+        walk(arg, *part, &format!("{path}/{i}"), visit);
+      }
+    }
+    _ => (),
+  }
+}
@@ -0,0 +1,975 @@
+//! Textual syntax for the zoe query language
+//!
+//! Queries are written as a start point followed by any number of
+//! steps. Set operations combine two queries of the same kind.
+//!
+//! ```text
+//! query    := term (('|' | '&' | '-' | '^') term)*
+//! term     := atom step*
+//! step     := ('->' | '<-') target?
+//!           | '.store' | '.filter(' string ',' string ')'
+//!           | '.referencing' | '.referenced'
+//!           | '.traverse(' direction ',' min ',' max (',' property)? ')'
+//!           | '.shortest_path(' direction ',' query
+//!             (',' 'along' property)? (',' 'weight' string)? ')'
+//!           | '.count(' string ')'
+//!           | '.group_by(' string ',' string ')'
+//!           | '.group_by_nested(' string ',' string ')'
+//!           | '.degree(' string ',' direction (',' property)? ')'
+//!           | '.order_by(' ('id' | string) (',' 'desc')? ')'
+//!           | '.skip(' number ')' | '.limit(' number ')'
+//! direction := '->' | '<-' | '<->'
+//! target   := 'V' | 'V(' ids ')' | 'V[' property ']'
+//!           | 'E' | 'E(' ids ')' | 'E[' property ']'
+//! atom     := target
+//!           | 'P(' string ')'                 // a specific property
+//!           | 'P(' value ')'                  // the property with a value
+//!           | 'P(' string '..' string ')'     // ordered keys in between
+//!           | 'P(' string '=' json ')'       // an indexed json field
+//!           | '(' query ')'
+//! property := string | value | query
+//! value    := name (':' (name | json))? | json-object
+//! ids      := (string (',' string)*)?
+//! ```
+//!
+//! * `->` and `<-` follow the edges in the outgoing or ingoing direction
+//!   (vertices -> edges -> vertices). A target right after the arrow
+//!   keeps only the elements it selects as well (`V <- E[...]` is the
+//!   same as `V <- & E[...]`).
+//! * `->` leads from properties to the vertices or edges which have
+//!   them. It needs a target (`P(...) -> V` or `P(...) -> E`).
+//! * A set operation between vertices (or edges) and properties selects
+//!   the vertices (or edges) with the properties (`V & P(...)` is the
+//!   same as `V & V[P(...)]`).
+//! * `|`, `&`, `-` and `^` are union, intersection, difference and
+//!   disjunctive union. They all have the same precedence and are left
+//!   associative, steps bind stronger.
+//! * `.traverse` follows the edges in a direction for `min` to `max`
+//!   hops (`max` may be `null` for no limit), optionally only along
+//!   edges with the given property.
+//! * `.shortest_path` selects the vertices of the query which can be
+//!   reached, each on its shortest path. With a weight (a json pointer
+//!   into the properties of the edges) the sum of the weights is used
+//!   instead of the number of edges.
+//! * `.count`, `.group_by` and `.group_by_nested` sum up the selected
+//!   elements into the variable with the given name. The groups are
+//!   formed by the values at a json pointer into the properties of the
+//!   elements (or into the properties nested in them).
+//! * `.degree` stores the number of edges in a direction (optionally
+//!   only along edges with the given property) of every selected vertex
+//!   in the variable with the given name.
+//! * `.order_by` sorts the selected elements by their ids or by the
+//!   values at a json pointer into their properties (`desc` starts with
+//!   the highest value). `.skip` and `.limit` keep this order (or order
+//!   by the ids if there was none) and select a part of the elements.
+//! * Strings use the json syntax (including escapes). A string always
+//!   stands for the id of a property.
+//! * Values of properties are written as json of the schema. `Includes`
+//!   is short for `"Includes"` and `SchemaType:Cocktail` for
+//!   `{"SchemaType": "Cocktail"}`. Only [`parse_with`] knows the schema
+//!   and turns them into the ids of the properties.
+//!
+//! The cocktails which contain gin could be found like this:
+//!
+//! ```text
+//! P(Ingredient:"gin") -> V <- E[Includes] <- V & P(SchemaType:Cocktail).referencing
+//! ```
+//!
+//! `.referencing` is needed because the cocktails don't have the schema
+//! type itself but a property (`Cocktail:"Martini"`) which refers to
+//! it. Without a schema the ids of the properties are given instead:
+//!
+//! ```text
+//! V["<hash of gin>"] <- E["<hash of includes>"] <- & V["<hash of cocktail>"]
+//! ```
+
+use crate::ql::{Aggregation, BasicQuery, DegreeCount, Direction, EdgeQuery, OrderedKey, PathSearch, PropertyQuery, ShellFilter, SortOrder, Traversal, VertexQuery};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use thiserror::Error;
+
+type VQuery<V, E, P> = VertexQuery<V, E, P, ShellFilter, ShellFilter>;
+type EQuery<V, E, P> = EdgeQuery<V, E, P, ShellFilter, ShellFilter>;
+type Query<V, E, P> = BasicQuery<V, E, P, ShellFilter, ShellFilter>;
+type ShortestPath<V, E, P> = (VQuery<V, E, P>, PathSearch<P>);
+/// A parsed (part of a) query and its position in the query text
+type Parsed<V, E, P> = Result<(Query<V, E, P>, Range<usize>), ParseError>;
+
+/// Turns the value of a property into the id of the property
+type Resolve<'a> = &'a dyn Fn(&serde_json::Value) -> Option<String>;
+
+/// A syntax error together with the position in the query text
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{message} (at {}..{})", span.start, span.end)]
+pub struct ParseError {
+  pub message: String,
+  /// The byte range in the query text
+  pub span: Range<usize>,
+}
+
+impl ParseError {
+  fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+    ParseError { message: message.into(), span }
+  }
+
+  /// Describe the error with the affected line of the query text and
+  /// the position marked below it
+  pub fn report(&self, input: &str) -> String {
+    let line_start = input[..self.span.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = input[self.span.start..].find('\n')
+      .map(|i| self.span.start + i)
+      .unwrap_or(input.len());
+    let line_no = input[..line_start].matches('\n').count() + 1;
+    let column = input[line_start..self.span.start].chars().count();
+    let width = input[self.span.start..self.span.end.min(line_end)].chars().count().max(1);
+
+    format!(
+      "error: {}\n{:>4} | {}\n     | {}{}",
+      self.message,
+      line_no,
+      &input[line_start..line_end],
+      " ".repeat(column),
+      "^".repeat(width),
+    )
+  }
+}
+
+/// Parse a query in zoe syntax
+///
+/// Properties have to be given by their ids, values of properties are
+/// rejected.
+pub fn parse<V, E, P>(input: &str) -> Result<Query<V, E, P>, ParseError>
+where
+  V: FromStr,
+  E: FromStr,
+  P: FromStr,
+{
+  run(Parser { input, pos: 0, resolve: None })
+}
+
+/// Parse a query in zoe syntax with values of properties
+///
+/// `resolve` turns the json of a value into the id of its property (or
+/// `None` if the value does not fit the schema).
+pub fn parse_with<V, E, P, R>(input: &str, resolve: R) -> Result<Query<V, E, P>, ParseError>
+where
+  V: FromStr,
+  E: FromStr,
+  P: FromStr + fmt::Display,
+  R: Fn(&serde_json::Value) -> Option<P>,
+{
+  let resolve = |value: &serde_json::Value| resolve(value).map(|id| id.to_string());
+  run(Parser { input, pos: 0, resolve: Some(&resolve) })
+}
+
+fn run<V, E, P>(mut parser: Parser<'_>) -> Result<Query<V, E, P>, ParseError>
+where
+  V: FromStr,
+  E: FromStr,
+  P: FromStr,
+{
+  let (query, _) = parser.query()?;
+  match parser.next()? {
+    None => Ok(query),
+    Some((_, span)) => Err(ParseError::new("expected the end of the query", span)),
+  }
+}
+
+impl<V, E, P> FromStr for BasicQuery<V, E, P, ShellFilter, ShellFilter>
+where
+  V: FromStr,
+  E: FromStr,
+  P: FromStr,
+{
+  type Err = ParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    parse(s)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(String),
+  Str(String),
+  Number(usize),
+  LParen,
+  RParen,
+  LBracket,
+  RBracket,
+  Comma,
+  Dot,
+  DotDot,
+  Colon,
+  Equals,
+  Out,
+  In,
+  Both,
+  Union,
+  Intersect,
+  Substract,
+  DisjunctiveUnion,
+}
+
+#[derive(Clone, Copy)]
+enum SetOperation {
+  Union,
+  Intersect,
+  Substract,
+  DisjunctiveUnion,
+}
+
+struct Parser<'a> {
+  input: &'a str,
+  pos: usize,
+  /// Turns values of properties into the ids of the properties
+  resolve: Option<Resolve<'a>>,
+}
+
+impl Parser<'_> {
+  fn skip_whitespace(&mut self) {
+    let rest = &self.input[self.pos..];
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+
+  fn next(&mut self) -> Result<Option<(Token, Range<usize>)>, ParseError> {
+    self.skip_whitespace();
+    let start = self.pos;
+    let rest = &self.input[start..];
+    let Some(c) = rest.chars().next() else {
+      return Ok(None);
+    };
+
+    let (token, len) = match c {
+      '"' => {
+        let mut escaped = false;
+        let end = rest.char_indices().skip(1).find(|(_, c)| {
+          let found = !escaped && *c == '"';
+          escaped = !escaped && *c == '\\';
+          found
+        });
+        let Some((end, _)) = end else {
+          return Err(ParseError::new("unterminated string", start..self.input.len()));
+        };
+        let value = serde_json::from_str(&rest[..=end])
+          .map_err(|e| ParseError::new(format!("invalid string: {e}"), start..start + end + 1))?;
+        (Token::Str(value), end + 1)
+      }
+      c if c.is_ascii_digit() => {
+        let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let value = rest[..len].parse()
+          .map_err(|e| ParseError::new(format!("invalid number: {e}"), start..start + len))?;
+        (Token::Number(value), len)
+      }
+      c if c.is_alphabetic() || c == '_' => {
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        (Token::Ident(rest[..len].to_string()), len)
+      }
+      _ if rest.starts_with("->") => (Token::Out, 2),
+      _ if rest.starts_with("<->") => (Token::Both, 3),
+      _ if rest.starts_with("<-") => (Token::In, 2),
+      _ if rest.starts_with("..") => (Token::DotDot, 2),
+      '.' => (Token::Dot, 1),
+      '(' => (Token::LParen, 1),
+      ')' => (Token::RParen, 1),
+      '[' => (Token::LBracket, 1),
+      ']' => (Token::RBracket, 1),
+      ',' => (Token::Comma, 1),
+      ':' => (Token::Colon, 1),
+      '=' => (Token::Equals, 1),
+      '|' => (Token::Union, 1),
+      '&' => (Token::Intersect, 1),
+      '-' => (Token::Substract, 1),
+      '^' => (Token::DisjunctiveUnion, 1),
+      c => {
+        return Err(ParseError::new(format!("unexpected character '{c}'"), start..start + c.len_utf8()));
+      }
+    };
+
+    self.pos += len;
+    Ok(Some((token, start..self.pos)))
+  }
+
+  fn peek(&mut self) -> Result<Option<Token>, ParseError> {
+    let pos = self.pos;
+    let token = self.next()?.map(|(token, _)| token);
+    self.pos = pos;
+    Ok(token)
+  }
+
+  /// The position of the next token (for error messages)
+  fn here(&mut self) -> Range<usize> {
+    self.skip_whitespace();
+    let end = self.input[self.pos..].chars().next().map(|c| self.pos + c.len_utf8()).unwrap_or(self.pos);
+    self.pos..end
+  }
+
+  fn expect(&mut self, expected: Token, description: &str) -> Result<Range<usize>, ParseError> {
+    let here = self.here();
+    match self.next()? {
+      Some((token, span)) if token == expected => Ok(span),
+      _ => Err(ParseError::new(format!("expected {description}"), here)),
+    }
+  }
+
+  fn string(&mut self) -> Result<(String, Range<usize>), ParseError> {
+    let here = self.here();
+    match self.next()? {
+      Some((Token::Str(value), span)) => Ok((value, span)),
+      _ => Err(ParseError::new("expected a string", here)),
+    }
+  }
+
+  fn ident(&mut self) -> Result<(String, Range<usize>), ParseError> {
+    let here = self.here();
+    match self.next()? {
+      Some((Token::Ident(name), span)) => Ok((name, span)),
+      _ => Err(ParseError::new("expected the name of a step", here)),
+    }
+  }
+
+  fn query<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Parsed<V, E, P> {
+    let (mut left, mut span) = self.term()?;
+
+    loop {
+      let op = match self.peek()? {
+        Some(Token::Union) => SetOperation::Union,
+        Some(Token::Intersect) => SetOperation::Intersect,
+        Some(Token::Substract) => SetOperation::Substract,
+        Some(Token::DisjunctiveUnion) => SetOperation::DisjunctiveUnion,
+        _ => break,
+      };
+      self.next()?;
+      let (right, right_span) = self.term()?;
+      let full_span = span.start..right_span.end;
+
+      left = match (left, right) {
+        (BasicQuery::V(l), BasicQuery::V(r)) => BasicQuery::V(combine_vertices(op, l, r)),
+        (BasicQuery::E(l), BasicQuery::E(r)) => BasicQuery::E(combine_edges(op, l, r)),
+        (BasicQuery::V(l), BasicQuery::P(r)) => {
+          BasicQuery::V(combine_vertices(op, l, VertexQuery::from_property(r)))
+        }
+        (BasicQuery::E(l), BasicQuery::P(r)) => {
+          BasicQuery::E(combine_edges(op, l, EdgeQuery::from_property(r)))
+        }
+        (BasicQuery::P(_), _) => {
+          return Err(ParseError::new("set operations are not supported on properties", span));
+        }
+        (l, _) => {
+          return Err(ParseError::new(format!("expected {} on both sides", kind(&l)), full_span));
+        }
+      };
+      span = full_span;
+    }
+
+    Ok((left, span))
+  }
+
+  fn term<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Parsed<V, E, P> {
+    let (mut query, mut span) = self.atom()?;
+
+    loop {
+      let (step, step_span) = match self.peek()? {
+        Some(Token::Out) | Some(Token::In) => {
+          let (token, step_span) = self.next()?.unwrap();
+          (if token == Token::Out { "->" } else { "<-" }.to_string(), step_span)
+        }
+        Some(Token::Dot) => {
+          self.next()?;
+          self.ident()?
+        }
+        _ => break,
+      };
+
+      query = match (step.as_str(), query) {
+        ("->", BasicQuery::V(q)) => self.target(q.outgoing().into())?,
+        ("->", BasicQuery::E(q)) => self.target(q.outgoing().into())?,
+        ("<-", BasicQuery::V(q)) => self.target(q.ingoing().into())?,
+        ("<-", BasicQuery::E(q)) => self.target(q.ingoing().into())?,
+        ("->", BasicQuery::P(q)) => self.having(q)?,
+        ("store", BasicQuery::V(q)) => q.store().into(),
+        ("store", BasicQuery::E(q)) => q.store().into(),
+        ("filter", BasicQuery::V(q)) => q.filter(self.filter()?).into(),
+        ("filter", BasicQuery::E(q)) => q.filter(self.filter()?).into(),
+        ("referencing", BasicQuery::P(q)) => q.referencing_properties().into(),
+        ("referenced", BasicQuery::P(q)) => q.referenced_properties().into(),
+        ("traverse", BasicQuery::V(q)) => q.traverse(self.traversal::<V, E, P>()?).into(),
+        ("shortest_path", BasicQuery::V(q)) => {
+          let (to, search) = self.path_search()?;
+          q.shortest_path(to, search).into()
+        }
+        ("count" | "group_by" | "group_by_nested", BasicQuery::V(q)) => {
+          let (name, aggregation) = self.aggregation(&step)?;
+          q.aggregate(&name, aggregation).into()
+        }
+        ("count" | "group_by" | "group_by_nested", BasicQuery::E(q)) => {
+          let (name, aggregation) = self.aggregation(&step)?;
+          q.aggregate(&name, aggregation).into()
+        }
+        ("degree", BasicQuery::V(q)) => {
+          let (name, count) = self.degree::<V, E, P>()?;
+          q.degree(&name, count).into()
+        }
+        ("order_by", BasicQuery::V(q)) => q.order_by(self.sort_order()?).into(),
+        ("order_by", BasicQuery::E(q)) => q.order_by(self.sort_order()?).into(),
+        ("skip", BasicQuery::V(q)) => q.skip(self.count()?).into(),
+        ("skip", BasicQuery::E(q)) => q.skip(self.count()?).into(),
+        ("limit", BasicQuery::V(q)) => q.limit(self.count()?).into(),
+        ("limit", BasicQuery::E(q)) => q.limit(self.count()?).into(),
+        ("<-" | "store" | "filter" | "referencing" | "referenced" | "traverse" | "shortest_path"
+          | "count" | "group_by" | "group_by_nested" | "degree" | "order_by" | "skip" | "limit", q) => {
+          return Err(ParseError::new(format!("the step '{step}' can not be used on {}", kind(&q)), step_span));
+        }
+        _ => {
+          return Err(ParseError::new(format!("unknown step '{step}'"), step_span));
+        }
+      };
+      span = span.start..self.pos;
+    }
+
+    Ok((query, span))
+  }
+
+  /// Whether a target ('V' or 'E') follows
+  fn at_target(&mut self) -> Result<bool, ParseError> {
+    Ok(matches!(self.peek()?, Some(Token::Ident(name)) if name == "V" || name == "E"))
+  }
+
+  /// Keep only the elements of the query which are selected by the
+  /// target after an arrow as well
+  fn target<V: FromStr, E: FromStr, P: FromStr>(&mut self, query: Query<V, E, P>) -> Result<Query<V, E, P>, ParseError> {
+    if !self.at_target()? {
+      return Ok(query);
+    }
+
+    match (query, self.atom()?) {
+      (query, (BasicQuery::V(VertexQuery::All), _)) if matches!(query, BasicQuery::V(_)) => Ok(query),
+      (query, (BasicQuery::E(EdgeQuery::All), _)) if matches!(query, BasicQuery::E(_)) => Ok(query),
+      (BasicQuery::V(q), (BasicQuery::V(target), _)) => Ok(q.intersect(target).into()),
+      (BasicQuery::E(q), (BasicQuery::E(target), _)) => Ok(q.intersect(target).into()),
+      (query, (_, span)) => Err(ParseError::new(format!("expected {} after the arrow", kind(&query)), span)),
+    }
+  }
+
+  /// The vertices or edges with the properties (`P(...) -> V`)
+  fn having<V: FromStr, E: FromStr, P: FromStr>(&mut self, properties: PropertyQuery<P>) -> Result<Query<V, E, P>, ParseError> {
+    if !self.at_target()? {
+      return Err(ParseError::new("expected 'V' or 'E' after the properties", self.here()));
+    }
+
+    let query = match self.atom()? {
+      (BasicQuery::V(VertexQuery::All), _) => VertexQuery::from_property(properties).into(),
+      (BasicQuery::E(EdgeQuery::All), _) => EdgeQuery::from_property(properties).into(),
+      (BasicQuery::V(target), _) => VertexQuery::from_property(properties).intersect(target).into(),
+      (BasicQuery::E(target), _) => EdgeQuery::from_property(properties).intersect(target).into(),
+      (BasicQuery::P(_), span) => return Err(ParseError::new("expected 'V' or 'E' after the properties", span)),
+    };
+    Ok(query)
+  }
+
+  fn filter(&mut self) -> Result<ShellFilter, ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let (interpreter, _) = self.string()?;
+    self.expect(Token::Comma, "','")?;
+    let (script, _) = self.string()?;
+    self.expect(Token::RParen, "')'")?;
+    Ok(ShellFilter { interpreter, script })
+  }
+
+  fn traversal<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Result<Traversal<P>, ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let direction = self.direction()?;
+    self.expect(Token::Comma, "','")?;
+    let here = self.here();
+    let min = match self.next()? {
+      Some((Token::Number(min), _)) => min,
+      _ => return Err(ParseError::new("expected the minimal number of hops", here)),
+    };
+    self.expect(Token::Comma, "','")?;
+    let here = self.here();
+    let max = match self.next()? {
+      Some((Token::Number(max), _)) => Some(max),
+      Some((Token::Ident(null), _)) if null == "null" => None,
+      _ => return Err(ParseError::new("expected the maximal number of hops or null", here)),
+    };
+    let mut traversal = Traversal::new(direction).depth(min, max);
+    if self.peek()? == Some(Token::Comma) {
+      self.next()?;
+      traversal = traversal.along(self.property::<V, E, P>()?);
+    }
+    self.expect(Token::RParen, "')'")?;
+    Ok(traversal)
+  }
+
+  /// The target and the parameters of a `shortest_path` step
+  fn path_search<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Result<ShortestPath<V, E, P>, ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let mut search = PathSearch::new(self.direction()?);
+    self.expect(Token::Comma, "','")?;
+    let to = match self.query::<V, E, P>()? {
+      (BasicQuery::V(q), _) => q,
+      (q, span) => return Err(ParseError::new(format!("expected vertices but found {}", kind(&q)), span)),
+    };
+    while self.peek()? == Some(Token::Comma) {
+      self.next()?;
+      let (option, span) = self.ident()?;
+      search = match option.as_str() {
+        "along" => search.along(self.property::<V, E, P>()?),
+        "weight" => search.weighted(&self.string()?.0),
+        _ => return Err(ParseError::new(format!("unknown option '{option}'"), span)),
+      };
+    }
+    self.expect(Token::RParen, "')'")?;
+    Ok((to, search))
+  }
+
+  /// The name of the variable and the aggregation of a `count`,
+  /// `group_by` or `group_by_nested` step
+  fn aggregation(&mut self, step: &str) -> Result<(String, Aggregation), ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let (name, _) = self.string()?;
+    let aggregation = match step {
+      "count" => Aggregation::Count,
+      _ => {
+        self.expect(Token::Comma, "','")?;
+        let (pointer, _) = self.string()?;
+        if step == "group_by" {
+          Aggregation::GroupBy(pointer)
+        } else {
+          Aggregation::GroupByNested(pointer)
+        }
+      }
+    };
+    self.expect(Token::RParen, "')'")?;
+    Ok((name, aggregation))
+  }
+
+  /// The name of the variable and the counted edges of a `degree` step
+  fn degree<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Result<(String, DegreeCount<P>), ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let (name, _) = self.string()?;
+    self.expect(Token::Comma, "','")?;
+    let mut count = DegreeCount::new(self.direction()?);
+    if self.peek()? == Some(Token::Comma) {
+      self.next()?;
+      count = count.along(self.property::<V, E, P>()?);
+    }
+    self.expect(Token::RParen, "')'")?;
+    Ok((name, count))
+  }
+
+  /// The order of an `order_by` step
+  fn sort_order(&mut self) -> Result<SortOrder, ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let here = self.here();
+    let mut order = match self.next()? {
+      Some((Token::Ident(id), _)) if id == "id" => SortOrder::by_id(),
+      Some((Token::Str(pointer), _)) => SortOrder::by_field(&pointer),
+      _ => return Err(ParseError::new("expected 'id' or a json pointer", here)),
+    };
+    if self.peek()? == Some(Token::Comma) {
+      self.next()?;
+      let (option, span) = self.ident()?;
+      if option != "desc" {
+        return Err(ParseError::new(format!("unknown option '{option}'"), span));
+      }
+      order = order.descending();
+    }
+    self.expect(Token::RParen, "')'")?;
+    Ok(order)
+  }
+
+  /// The number of elements of a `skip` or `limit` step
+  fn count(&mut self) -> Result<usize, ParseError> {
+    self.expect(Token::LParen, "'('")?;
+    let here = self.here();
+    let n = match self.next()? {
+      Some((Token::Number(n), _)) => n,
+      _ => return Err(ParseError::new("expected the number of elements", here)),
+    };
+    self.expect(Token::RParen, "')'")?;
+    Ok(n)
+  }
+
+  fn direction(&mut self) -> Result<Direction, ParseError> {
+    let here = self.here();
+    match self.next()? {
+      Some((Token::Out, _)) => Ok(Direction::Outgoing),
+      Some((Token::In, _)) => Ok(Direction::Ingoing),
+      Some((Token::Both, _)) => Ok(Direction::Both),
+      _ => Err(ParseError::new("expected a direction ('->', '<-' or '<->')", here)),
+    }
+  }
+
+  fn atom<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Parsed<V, E, P> {
+    let here = self.here();
+    let Some((token, span)) = self.next()? else {
+      return Err(ParseError::new("expected a query", here));
+    };
+
+    let query = match token {
+      Token::Ident(name) if name == "V" || name == "E" => {
+        let is_vertex = name == "V";
+        match self.peek()? {
+          Some(Token::LParen) => {
+            self.next()?;
+            let mut ids = Vec::new();
+            while self.peek()? != Some(Token::RParen) {
+              if !ids.is_empty() {
+                self.expect(Token::Comma, "',' or ')'")?;
+              }
+              ids.push(self.string()?);
+            }
+            self.next()?;
+            if is_vertex {
+              VertexQuery::from_ids(parse_ids(ids, "vertex")?).into()
+            } else {
+              EdgeQuery::from_ids(parse_ids(ids, "edge")?).into()
+            }
+          }
+          Some(Token::LBracket) => {
+            self.next()?;
+            let property = self.property::<V, E, P>()?;
+            self.expect(Token::RBracket, "']'")?;
+            if is_vertex {
+              VertexQuery::from_property(property).into()
+            } else {
+              EdgeQuery::from_property(property).into()
+            }
+          }
+          _ => if is_vertex {
+            VertexQuery::all().into()
+          } else {
+            EdgeQuery::all().into()
+          }
+        }
+      }
+      Token::Ident(name) if name == "P" => {
+        self.expect(Token::LParen, "'('")?;
+        if self.at_value()? {
+          let query = PropertyQuery::from_id(self.value()?);
+          self.expect(Token::RParen, "')'")?;
+          return Ok((query.into(), span.start..self.pos));
+        }
+        let (first, first_span) = self.string()?;
+        let query = match self.peek()? {
+          Some(Token::DotDot) => {
+            self.next()?;
+            let (to, _) = self.string()?;
+            PropertyQuery::from_to(OrderedKey(first), OrderedKey(to))
+          }
+          Some(Token::Equals) => {
+            self.next()?;
+            PropertyQuery::JsonField(first, self.json()?)
+          }
+          _ => PropertyQuery::from_id(parse_id(first, first_span, "property")?),
+        };
+        self.expect(Token::RParen, "')'")?;
+        query.into()
+      }
+      Token::LParen => {
+        let (query, _) = self.query()?;
+        self.expect(Token::RParen, "')'")?;
+        query
+      }
+      _ => return Err(ParseError::new("expected 'V', 'E', 'P' or '('", span)),
+    };
+
+    Ok((query, span.start..self.pos))
+  }
+
+  /// A property query, the id of a single property or its value
+  fn property<V: FromStr, E: FromStr, P: FromStr>(&mut self) -> Result<PropertyQuery<P>, ParseError> {
+    if self.at_value()? {
+      return Ok(PropertyQuery::from_id(self.value()?));
+    }
+    if let Some(Token::Str(_)) = self.peek()? {
+      let (id, span) = self.string()?;
+      return Ok(PropertyQuery::from_id(parse_id(id, span, "property")?));
+    }
+
+    match self.query::<V, E, P>()? {
+      (BasicQuery::P(q), _) => Ok(q),
+      (q, span) => Err(ParseError::new(format!("expected properties but found {}", kind(&q)), span)),
+    }
+  }
+
+  /// Whether the value of a property follows (a name other than 'V',
+  /// 'E' and 'P' or a json object)
+  fn at_value(&mut self) -> Result<bool, ParseError> {
+    self.skip_whitespace();
+    if self.input[self.pos..].starts_with('{') {
+      return Ok(true);
+    }
+    Ok(matches!(self.peek()?, Some(Token::Ident(name)) if !matches!(name.as_str(), "V" | "E" | "P")))
+  }
+
+  /// The value of a property, turned into the id of the property
+  fn value<P: FromStr>(&mut self) -> Result<P, ParseError> {
+    self.skip_whitespace();
+    let start = self.pos;
+    let value = match self.next_name()? {
+      Some(name) if self.peek()? == Some(Token::Colon) => {
+        self.next()?;
+        let inner = match self.next_name()? {
+          Some(inner) => serde_json::Value::String(inner),
+          None => self.json()?,
+        };
+        serde_json::Value::Object([(name, inner)].into_iter().collect())
+      }
+      Some(name) => serde_json::Value::String(name),
+      None => self.json()?,
+    };
+    let span = start..self.pos;
+
+    let Some(resolve) = self.resolve else {
+      return Err(ParseError::new("values of properties need a schema, give the id of the property instead", span));
+    };
+    match resolve(&value) {
+      Some(id) => parse_id(id, span, "property"),
+      None => Err(ParseError::new(format!("{value} is no property of the schema"), span)),
+    }
+  }
+
+  /// A name if one follows
+  fn next_name(&mut self) -> Result<Option<String>, ParseError> {
+    self.skip_whitespace();
+    if self.input[self.pos..].starts_with('{') {
+      return Ok(None);
+    }
+    let pos = self.pos;
+    match self.next()? {
+      Some((Token::Ident(name), _)) => Ok(Some(name)),
+      _ => {
+        self.pos = pos;
+        Ok(None)
+      }
+    }
+  }
+
+  fn json(&mut self) -> Result<serde_json::Value, ParseError> {
+    self.skip_whitespace();
+    let start = self.pos;
+    let mut values = serde_json::Deserializer::from_str(&self.input[start..])
+      .into_iter::<serde_json::Value>();
+    match values.next() {
+      Some(Ok(value)) => {
+        self.pos = start + values.byte_offset();
+        Ok(value)
+      }
+      _ => Err(ParseError::new("expected a json value", self.here())),
+    }
+  }
+}
+
+fn parse_id<T: FromStr>(id: String, span: Range<usize>, what: &str) -> Result<T, ParseError> {
+  id.parse().map_err(|_| ParseError::new(format!("invalid {what} id \"{id}\""), span))
+}
+
+fn parse_ids<T: FromStr>(ids: Vec<(String, Range<usize>)>, what: &str) -> Result<Vec<T>, ParseError> {
+  ids.into_iter().map(|(id, span)| parse_id(id, span, what)).collect()
+}
+
+fn kind<V, E, P>(q: &Query<V, E, P>) -> &'static str {
+  match q {
+    BasicQuery::V(_) => "vertices",
+    BasicQuery::E(_) => "edges",
+    BasicQuery::P(_) => "properties",
+  }
+}
+
+fn combine_vertices<V, E, P>(op: SetOperation, l: VQuery<V, E, P>, r: VQuery<V, E, P>) -> VQuery<V, E, P> {
+  let (l, r) = (Box::new(l), Box::new(r));
+  match op {
+    SetOperation::Union => VertexQuery::Union(l, r),
+    SetOperation::Intersect => VertexQuery::Intersect(l, r),
+    SetOperation::Substract => VertexQuery::Substract(l, r),
+    SetOperation::DisjunctiveUnion => VertexQuery::DisjunctiveUnion(l, r),
+  }
+}
+
+fn combine_edges<V, E, P>(op: SetOperation, l: EQuery<V, E, P>, r: EQuery<V, E, P>) -> EQuery<V, E, P> {
+  let (l, r) = (Box::new(l), Box::new(r));
+  match op {
+    SetOperation::Union => EdgeQuery::Union(l, r),
+    SetOperation::Intersect => EdgeQuery::Intersect(l, r),
+    SetOperation::Substract => EdgeQuery::Substract(l, r),
+    SetOperation::DisjunctiveUnion => EdgeQuery::DisjunctiveUnion(l, r),
+  }
+}
+
+fn string(s: &str) -> String {
+  serde_json::to_string(s).unwrap()
+}
+
+fn ids<T: fmt::Display>(ids: &[T]) -> String {
+  ids.iter().map(|id| string(&id.to_string())).collect::<Vec<_>>().join(", ")
+}
+
+fn filter(filter: &ShellFilter) -> String {
+  format!(".filter({}, {})", string(&filter.interpreter), string(&filter.script))
+}
+
+fn direction(direction: Direction) -> &'static str {
+  match direction {
+    Direction::Outgoing => "->",
+    Direction::Ingoing => "<-",
+    Direction::Both => "<->",
+  }
+}
+
+fn traversal<P: fmt::Display>(traversal: &Traversal<P>) -> String {
+  let direction = direction(traversal.direction);
+  let max = match traversal.max {
+    Some(max) => max.to_string(),
+    None => "null".to_string(),
+  };
+  match &traversal.edges {
+    Some(edges) => format!(".traverse({direction}, {}, {max}, {edges})", traversal.min),
+    None => format!(".traverse({direction}, {}, {max})", traversal.min),
+  }
+}
+
+fn path_search<V, E, P>(to: &VQuery<V, E, P>, search: &PathSearch<P>) -> String
+where
+  V: fmt::Display,
+  E: fmt::Display,
+  P: fmt::Display,
+{
+  let mut text = format!(".shortest_path({}, {to}", direction(search.direction));
+  if let Some(edges) = &search.edges {
+    text += &format!(", along {edges}");
+  }
+  if let Some(pointer) = &search.weight {
+    text += &format!(", weight {}", string(pointer));
+  }
+  text + ")"
+}
+
+fn aggregation(name: &str, aggregation: &Aggregation) -> String {
+  match aggregation {
+    Aggregation::Count => format!(".count({})", string(name)),
+    Aggregation::GroupBy(pointer) => format!(".group_by({}, {})", string(name), string(pointer)),
+    Aggregation::GroupByNested(pointer) => format!(".group_by_nested({}, {})", string(name), string(pointer)),
+  }
+}
+
+fn sort_order(order: &SortOrder) -> String {
+  let field = match &order.field {
+    Some(pointer) => string(pointer),
+    None => "id".to_string(),
+  };
+  if order.descending {
+    format!(".order_by({field}, desc)")
+  } else {
+    format!(".order_by({field})")
+  }
+}
+
+fn degree<P: fmt::Display>(name: &str, count: &DegreeCount<P>) -> String {
+  let direction = direction(count.direction);
+  match &count.edges {
+    Some(edges) => format!(".degree({}, {direction}, {edges})", string(name)),
+    None => format!(".degree({}, {direction})", string(name)),
+  }
+}
+
+impl<V: fmt::Display, E: fmt::Display, P: fmt::Display> VertexQuery<V, E, P, ShellFilter, ShellFilter> {
+  fn is_set_operation(&self) -> bool {
+    use VertexQuery::*;
+    matches!(self, Union(..) | Intersect(..) | Substract(..) | DisjunctiveUnion(..))
+  }
+
+  /// The query as operand of a step
+  fn operand(&self) -> String {
+    if self.is_set_operation() { format!("({self})") } else { self.to_string() }
+  }
+}
+
+impl<V: fmt::Display, E: fmt::Display, P: fmt::Display> EdgeQuery<V, E, P, ShellFilter, ShellFilter> {
+  fn is_set_operation(&self) -> bool {
+    use EdgeQuery::*;
+    matches!(self, Union(..) | Intersect(..) | Substract(..) | DisjunctiveUnion(..))
+  }
+
+  /// The query as operand of a step
+  fn operand(&self) -> String {
+    if self.is_set_operation() { format!("({self})") } else { self.to_string() }
+  }
+}
+
+impl<V: fmt::Display, E: fmt::Display, P: fmt::Display> fmt::Display for VertexQuery<V, E, P, ShellFilter, ShellFilter> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use VertexQuery::*;
+
+    match self {
+      All => write!(f, "V"),
+      Specific(v) => write!(f, "V({})", ids(v)),
+      Property(p) => write!(f, "V[{p}]"),
+      Out(q) => write!(f, "{} ->", q.operand()),
+      In(q) => write!(f, "{} <-", q.operand()),
+      Union(l, r) => write!(f, "{l} | {}", r.operand()),
+      Intersect(l, r) => write!(f, "{l} & {}", r.operand()),
+      Substract(l, r) => write!(f, "{l} - {}", r.operand()),
+      DisjunctiveUnion(l, r) => write!(f, "{l} ^ {}", r.operand()),
+      Filter(q, shell) => write!(f, "{}{}", q.operand(), filter(shell)),
+      Store(q) => write!(f, "{}.store", q.operand()),
+      Traverse(q, t) => write!(f, "{}{}", q.operand(), traversal(t)),
+      ShortestPath(q, to, search) => write!(f, "{}{}", q.operand(), path_search(to, search)),
+      Aggregate(q, name, a) => write!(f, "{}{}", q.operand(), aggregation(name, a)),
+      Degree(q, name, count) => write!(f, "{}{}", q.operand(), degree(name, count)),
+      Order(q, order) => write!(f, "{}{}", q.operand(), sort_order(order)),
+      Skip(q, n) => write!(f, "{}.skip({n})", q.operand()),
+      Limit(q, n) => write!(f, "{}.limit({n})", q.operand()),
+    }
+  }
+}
+
+impl<V: fmt::Display, E: fmt::Display, P: fmt::Display> fmt::Display for EdgeQuery<V, E, P, ShellFilter, ShellFilter> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use EdgeQuery::*;
+
+    match self {
+      All => write!(f, "E"),
+      Specific(e) => write!(f, "E({})", ids(e)),
+      Property(p) => write!(f, "E[{p}]"),
+      Out(q) => write!(f, "{} ->", q.operand()),
+      In(q) => write!(f, "{} <-", q.operand()),
+      Union(l, r) => write!(f, "{l} | {}", r.operand()),
+      Intersect(l, r) => write!(f, "{l} & {}", r.operand()),
+      Substract(l, r) => write!(f, "{l} - {}", r.operand()),
+      DisjunctiveUnion(l, r) => write!(f, "{l} ^ {}", r.operand()),
+      Filter(q, shell) => write!(f, "{}{}", q.operand(), filter(shell)),
+      Store(q) => write!(f, "{}.store", q.operand()),
+      Aggregate(q, name, a) => write!(f, "{}{}", q.operand(), aggregation(name, a)),
+      Order(q, order) => write!(f, "{}{}", q.operand(), sort_order(order)),
+      Skip(q, n) => write!(f, "{}.skip({n})", q.operand()),
+      Limit(q, n) => write!(f, "{}.limit({n})", q.operand()),
+    }
+  }
+}
+
+impl<P: fmt::Display> fmt::Display for PropertyQuery<P> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use PropertyQuery::*;
+
+    match self {
+      Specific(id) => write!(f, "P({})", string(&id.to_string())),
+      FromTo(from, to) => write!(f, "P({}..{})", string(&from.0), string(&to.0)),
+      JsonField(path, value) => write!(f, "P({} = {value})", string(path)),
+      ReferencingProperties(q) => write!(f, "{q}.referencing"),
+      ReferencedProperties(q) => write!(f, "{q}.referenced"),
+    }
+  }
+}
+
+impl<V: fmt::Display, E: fmt::Display, P: fmt::Display> fmt::Display for BasicQuery<V, E, P, ShellFilter, ShellFilter> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      BasicQuery::V(q) => q.fmt(f),
+      BasicQuery::E(q) => q.fmt(f),
+      BasicQuery::P(q) => q.fmt(f),
+    }
+  }
+}
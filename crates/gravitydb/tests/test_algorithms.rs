@@ -0,0 +1,73 @@
+use gravitydb::{Graph, GraphFilter};
+use gravitydb::algorithms::*;
+use pretty_assertions::assert_eq;
+
+/// a -> b -> c -> a is a cycle, c -> d -> e a tail and f stands alone
+fn example() -> SubGraph<&'static str> {
+  let mut graph = SubGraph::new();
+  for (source, target) in [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d"), ("d", "e")] {
+    graph.add_edge(source, target);
+  }
+  graph.add_node("f");
+  graph
+}
+
+fn sorted(mut groups: Vec<Vec<&'static str>>) -> Vec<Vec<&'static str>> {
+  for group in groups.iter_mut() {
+    group.sort();
+  }
+  groups.sort();
+  groups
+}
+
+#[test]
+fn components() {
+  let graph = example();
+  assert_eq!(graph.order(), Ok(6));
+  assert_eq!(graph.size(), Ok(5));
+
+  let Ok(components) = ConnectedComponents.filter(&graph);
+  assert_eq!(sorted(components), vec![vec!["a", "b", "c", "d", "e"], vec!["f"]]);
+
+  let Ok(components) = StronglyConnectedComponents.filter(&graph);
+  // the tail is found before the cycle leading to it
+  let position = |node| components.iter().position(|c| c.contains(&node)).unwrap();
+  assert!(position("e") < position("d"));
+  assert!(position("d") < position("a"));
+  assert_eq!(sorted(components), vec![vec!["a", "b", "c"], vec!["d"], vec!["e"], vec!["f"]]);
+}
+
+#[test]
+fn ordering() {
+  let mut graph = example();
+  assert_eq!(TopologicalSort.filter(&graph), Ok(None));
+
+  graph = SubGraph::new();
+  for (source, target) in [("shirt", "tie"), ("tie", "jacket"), ("trousers", "shoes"), ("trousers", "jacket")] {
+    graph.add_edge(source, target);
+  }
+  let Ok(Some(order)) = TopologicalSort.filter(&graph) else {
+    panic!("the graph has no cycle");
+  };
+  assert_eq!(order, vec!["shirt", "trousers", "tie", "shoes", "jacket"]);
+}
+
+#[test]
+fn importance() {
+  let graph = example();
+
+  let Ok(rank) = PageRank::default().filter(&graph);
+  assert!((rank.values().sum::<f64>() - 1.0).abs() < 1e-9);
+  // the end of the tail collects the rank of the cycle
+  assert!(rank["e"] > rank["d"]);
+  assert!(rank["f"] < rank["a"]);
+
+  let Ok(centrality) = DegreeCentrality.filter(&graph);
+  assert_eq!(centrality["c"], 3.0 / 5.0);
+  assert_eq!(centrality["f"], 0.0);
+
+  let Ok(triangles) = TriangleCount.filter(&graph);
+  assert_eq!(triangles.values().sum::<usize>() / 3, 1);
+  assert_eq!(triangles["a"], 1);
+  assert_eq!(triangles["d"], 0);
+}
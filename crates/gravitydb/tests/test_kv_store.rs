@@ -163,6 +163,276 @@ fn create_two_nodes_with_connection() -> Result<(), Error> {
   Ok(assert_eq!(store.len(), 0))
 }
 
+#[test]
+fn failed_operations_leave_the_store_unchanged() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  // the second node does not exist, so the edge can not be created
+  match graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec()) {
+    Err(Error::KV(_)) => (),
+    _ => panic!("should fail because the second node does not exist"),
+  };
+
+  let mut store = get_kv_store(graph);
+  let node_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID
+    )
+  );
+  check_string(
+    store.remove(format!("props/{}", PROPERTY_EMPTY_ID).as_str()),
+    ""
+  );
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID).as_str()),
+    &node_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn nodes_with_edges_are_only_deleted_on_request() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  graph.create_edge(Uuid(uuid!(NODE1_UUID)), Uuid(uuid!(NODE2_UUID)), &PROPERTY_EMPTY.to_vec())?;
+
+  match graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject) {
+    Err(Error::NodeHasEdges(msg)) => assert_eq!(msg, NODE2_UUID),
+    _ => panic!("should fail because the node has an edge"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Cascade)?;
+
+  let mut store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  check_string(
+    store.remove(&node1_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_EMPTY_ID,
+    )
+  );
+  check_string(
+    store.remove(format!("props/{}", PROPERTY_EMPTY_ID).as_str()),
+    ""
+  );
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_EMPTY_ID, NODE1_UUID).as_str()),
+    &node1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn properties_can_be_found_by_indexed_json_fields() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_JOHN_DOE.to_vec())?;
+  // properties which exist before the index is declared are added
+  graph.create_json_index("/Person/surname")?;
+  graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_JANE_DOE.to_vec())?;
+
+  let q = ql::PropertyQuery::from_json_field("/Person/surname", "Doe")
+    .referencing_vertices();
+  let result = graph.query(q)?;
+  assert_eq!(result.vertices.len(), 2);
+
+  match graph.query(ql::PropertyQuery::from_json_field("/Person/name", "John")) {
+    Err(Error::MissingIndex(path)) => assert_eq!(path, "/Person/name"),
+    _ => panic!("should fail because the field has no index"),
+  };
+
+  graph.delete_node(Uuid(uuid!(NODE2_UUID)), DeletionMode::Reject)?;
+  let result = graph.query(ql::PropertyQuery::from_json_field("/Person/surname", "Doe"))?;
+  assert_eq!(result.properties, std::collections::HashSet::from([PROPERTY_JOHN_DOE_ID.to_string()]));
+
+  let mut store = get_kv_store(graph);
+  let node1_path = format!("nodes/{}", NODE1_UUID);
+  let props_path = format!("props/{}", PROPERTY_JOHN_DOE_ID);
+  check_string(
+    store.remove(format!("indexes/json/{}/{}_{}", SURNAME_PATH_ID, DOE_VALUE_ID, PROPERTY_JOHN_DOE_ID).as_str()),
+    &props_path
+  );
+  check_string(store.remove("indexes/json/paths"), r#"["/Person/surname"]"#);
+  check_string(
+    store.remove(&node1_path),
+    &format!(
+      "{{\"id\":\"{}\",\"properties\":\"{}\",\"incoming\":[],\"outgoing\":[]}}",
+        NODE1_UUID,
+        PROPERTY_JOHN_DOE_ID,
+    )
+  );
+  check_string(store.remove(&props_path), std::str::from_utf8(PROPERTY_JOHN_DOE).unwrap());
+  check_string(
+    store.remove(format!("indexes/{}/nodes_{}", PROPERTY_JOHN_DOE_ID, NODE1_UUID).as_str()),
+    &node1_path
+  );
+
+  assert_eq!(store.len(), 0);
+  Ok(())
+}
+
+#[test]
+fn shortest_paths_can_be_weighted() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+
+  let place = |name: &str| format!(r#"{{"Place":"{name}"}}"#).into_bytes();
+  let road = |length: u32| format!(r#"{{"Road":{{"length":{length}}}}}"#).into_bytes();
+  let a = graph.create_node(Uuid::new(), &place("a"))?;
+  let b = graph.create_node(Uuid::new(), &place("b"))?;
+  let c = graph.create_node(Uuid::new(), &place("c"))?;
+  let d = graph.create_node(Uuid::new(), &place("d"))?;
+  let a_to_b = graph.create_edge(a, b, &road(1))?;
+  let b_to_d = graph.create_edge(b, d, &road(2))?;
+  let a_to_d = graph.create_edge(a, d, &road(5))?;
+  graph.create_edge(a, c, &road(1))?;
+  graph.create_edge(c, d, &road(3))?;
+
+  let search = ql::PathSearch::new(ql::Direction::Outgoing);
+  let from = || ql::VertexQuery::from_ids(vec![a]);
+  let to = || ql::VertexQuery::from_ids(vec![d]);
+
+  // the fewest roads
+  let result = graph.query(from().shortest_path(to(), search.clone()))?;
+  assert_eq!(result.paths, vec![(None, vec![(a, a_to_d.clone())], Some(d))]);
+
+  // the shortest distance
+  let result = graph.query(from().shortest_path(to(), search.clone().weighted("/Road/length")))?;
+  assert_eq!(result.paths, vec![(None, vec![(a, a_to_b), (b, b_to_d)], Some(d))]);
+
+  // there is no way back
+  let result = graph.query(to().shortest_path(from(), search.clone().weighted("/Road/length")))?;
+  assert_eq!(result.paths, vec![]);
+
+  match graph.query(from().shortest_path(to(), search.weighted("/Road/speed"))) {
+    Err(Error::InvalidWeight(_, reason)) => assert_eq!(reason, "no number at /Road/speed"),
+    _ => panic!("should fail because the roads have no speed"),
+  };
+  Ok(())
+}
+
+#[test]
+fn query_results_contain_the_ids_of_the_properties() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+  let n1 = graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  let n2 = graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_EMPTY.to_vec())?;
+  graph.create_edge(n1, n2, &PROPERTY_EMPTY.to_vec())?;
+
+  let result = graph.query(ql::VertexQuery::from_ids(vec![n1]).outgoing().store().outgoing())?;
+  // a plain query does not read the elements only to find their properties
+  assert_eq!(result.vertices.iter().cloned().collect::<Vec<_>>(), vec![(n2, None)]);
+  let result = graph.with_property_ids(result)?;
+  assert_eq!(result.vertices.into_iter().collect::<Vec<_>>(), vec![(n2, Some(PROPERTY_EMPTY_ID.to_string()))]);
+  assert_eq!(result.edges.into_iter().collect::<Vec<_>>(), vec![(EDGE1_ID.to_string(), Some(PROPERTY_EMPTY_ID.to_string()))]);
+  Ok(())
+}
+
+#[test]
+fn resolved_results_only_read_the_properties() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+  let n1 = graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  let n2 = graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  graph.create_edge(n1, n2, &PROPERTY_EMPTY.to_vec())?;
+  let vertices = graph.with_property_ids(graph.query(ql::VertexQuery::All)?)?;
+  let edges = graph.with_property_ids(graph.query(ql::EdgeQuery::All)?)?;
+
+  // without the records of the elements only their properties can be read
+  let mut kv = graph.into_kv();
+  for key in [format!("nodes/{}", NODE1_UUID), format!("nodes/{}", NODE2_UUID), format!("edges/{}", EDGE1_ID)] {
+    kv.delete_record(key.as_bytes()).map_err(Error::KV)?;
+  }
+  let graph: GStore = kv_graph_store::KvGraphStore::from_kv(kv);
+
+  let data = graph.resolve(&vertices, |db, id| db.read_property_json(id))?;
+  assert_eq!(data.vertices.len(), 2);
+  assert!(data.vertices.iter().all(|v| v.property == serde_json::json!("simple text property")));
+  let data = graph.resolve(&edges, |db, id| db.read_property_json(id))?;
+  assert_eq!(data.edges.len(), 1);
+  assert_eq!(data.edges[0].property_id, PROPERTY_EMPTY_ID);
+  assert_eq!(data.edges[0].property, serde_json::json!(""));
+  Ok(())
+}
+
+#[test]
+fn records_can_be_scanned() -> Result<(), mem_kv_store::Error> {
+  gravitydb_test_utils::scan_records(mem_kv_store::MemoryKvStore::default())?;
+  // stores without a scan of their own use the default implementation
+  gravitydb_test_utils::scan_records(ListingOnly::default())
+}
+
+#[test]
+fn keys_can_be_arbitrary_bytes() -> Result<(), mem_kv_store::Error> {
+  gravitydb_test_utils::binary_keys(mem_kv_store::MemoryKvStore::default())
+}
+
+#[test]
+fn concurrent_explains_keep_their_own_trace() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+  let n1 = graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+  let n2 = graph.create_node(Uuid(uuid!(NODE2_UUID)), &PROPERTY_JOHN_DOE.to_vec())?;
+  graph.create_edge(n1, n2, &PROPERTY_EMPTY.to_vec())?;
+
+  let neighbours: kv_graph_store::BasicQuery = ql::VertexQuery::from_ids(vec![n1]).outgoing().outgoing().into();
+  let all: kv_graph_store::BasicQuery = ql::VertexQuery::All.into();
+  let expected = (graph.explain(neighbours.clone())?, graph.explain(all.clone())?);
+
+  let explain = |q: &kv_graph_store::BasicQuery, expected: &ql::QueryPlan| {
+    for _ in 0..100 {
+      assert_eq!(&graph.explain(q.clone())?, expected);
+    }
+    Ok::<_, Error>(())
+  };
+  std::thread::scope(|s| {
+    let first = s.spawn(|| explain(&neighbours, &expected.0));
+    let second = s.spawn(|| explain(&all, &expected.1));
+    // ordinary queries are not traced while the others explain
+    for _ in 0..100 {
+      graph.query(all.clone())?;
+    }
+    first.join().expect("the thread should not panic")?;
+    second.join().expect("the thread should not panic")
+  })
+}
+
+#[test]
+fn stores_without_transactions_can_be_queried() -> Result<(), Error> {
+  let mut graph = create_empty_graph();
+  graph.create_node(Uuid(uuid!(NODE1_UUID)), &PROPERTY_SIMPLE.to_vec())?;
+
+  let graph = kv_graph_store::KvGraphStore::<Vec<u8>, _, _>::from_kv(ListingOnly(graph.into_kv()));
+  let result = graph.query(ql::VertexQuery::from_ids(vec![Uuid(uuid!(NODE1_UUID))]))?;
+  assert_eq!(result.vertices.len(), 1);
+  Ok(())
+}
+
+#[test]
+fn prefixes_end_before_their_successor() {
+  assert_eq!(prefix_successor(b"nodes/"), Some(b"nodes0".to_vec()));
+  assert_eq!(prefix_successor(b"a\xFF\xFF"), Some(b"b".to_vec()));
+  assert_eq!(prefix_successor(b"\xFF\xFF"), None);
+  assert_eq!(prefix_successor(b""), None);
+
+  use std::ops::Bound::*;
+  assert_eq!(ScanRange::Prefix(b"a\xFF").bounds(), (Included(b"a\xFF".to_vec()), Excluded(b"b".to_vec())));
+  assert_eq!(ScanRange::Between(b"b", b"").bounds(), (Included(b"b".to_vec()), Excluded(b"c".to_vec())));
+  assert_eq!(ScanRange::Between(b"b", b"c").bounds(), (Included(b"b".to_vec()), Included(b"c".to_vec())));
+  // a range ending before it starts is empty
+  assert_eq!(ScanRange::Between(b"c", b"b").bounds(), (Included(b"c".to_vec()), Excluded(b"c".to_vec())));
+}
+
 fn check_string(left: Option<Vec<u8>>, right: &str) {
   let left = left.unwrap();
   let formatted = String::from_utf8(left).expect("should be an utf8 string");
@@ -176,6 +446,11 @@ const PROPERTY_EMPTY : &[u8] = "".as_bytes();
 const PROPERTY_EMPTY_ID: &str = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
 const PROPERTY_SIMPLE : &[u8] = "simple text property".as_bytes();
 const PROPERTY_SIMPLE_ID: &str = "4637D294486C315FC8D6C2F11742CBA4958CCB3F083656808C2B257D954DE631";
+const PROPERTY_JOHN_DOE : &[u8] = r#"{"Person":{"name":"John","surname":"Doe"}}"#.as_bytes();
+const PROPERTY_JOHN_DOE_ID: &str = "63AF565972F347A69CD5D3F4011CA40F70B2C474D3B66DE610675173DA76E7DC";
+const PROPERTY_JANE_DOE : &[u8] = r#"{"Person":{"name":"Jane","surname":"Doe"}}"#.as_bytes();
+const SURNAME_PATH_ID: &str = "98A0E50F672AF186AD5069AB18D1A78A5C47F71B1AFD30DC4B2463163CB59D66";
+const DOE_VALUE_ID: &str = "C827A65F67BE22B37B84EC305E59B3BB529A7F3F0F038FCE5F7AC69572ED2078";
 const EDGE1_ID : &str = "0B49457674D1B570400E6EC9E4B78F9C2C9B0721BA7C315BD0811E3059C3BBBA";
 const EDGE_N1_TO_SELF_ID : &str = "7622305FED0A357AF8AAE5ACC4110B8CAD7BDF2D67CAEA195BCDA0889A20FB8A";
 
@@ -185,9 +460,42 @@ fn create_empty_graph() -> GStore {
 }
 
 fn get_kv_store(graph: GStore) -> std::collections::BTreeMap<String, Vec<u8>> {
-  graph.into_kv().get_inner()
+  graph.into_kv()
+    .get_inner()
+    .into_iter()
+    .map(|(key, value)| (String::from_utf8(key).expect("keys are utf8"), value))
+    .collect()
 }
 
 type Error = kv_graph_store::Error<mem_kv_store::Error>;
 type GStore = kv_graph_store::KvGraphStore::<Vec<u8>, mem_kv_store::MemoryKvStore, mem_kv_store::Error>;
 
+/// A store which only implements the required functions
+#[derive(Default)]
+struct ListingOnly(mem_kv_store::MemoryKvStore);
+
+impl KVStore<mem_kv_store::Error> for ListingOnly {
+  fn create_bucket(&mut self, key: &[u8]) -> Result<(), mem_kv_store::Error> {
+    self.0.create_bucket(key)
+  }
+
+  fn delete_record(&mut self, key: &[u8]) -> Result<(), mem_kv_store::Error> {
+    self.0.delete_record(key)
+  }
+
+  fn list_records(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, mem_kv_store::Error> {
+    self.0.list_records(from, to)
+  }
+
+  fn store_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), mem_kv_store::Error> {
+    self.0.store_record(key, value)
+  }
+
+  fn fetch_record(&self, key: &[u8]) -> Result<Vec<u8>, mem_kv_store::Error> {
+    self.0.fetch_record(key)
+  }
+
+  fn exists(&self, key: &[u8]) -> Result<bool, mem_kv_store::Error> {
+    self.0.exists(key)
+  }
+}
@@ -0,0 +1,137 @@
+use gravitydb::ql;
+use gravitydb::kv_graph_store::{BasicQuery, QueryResult};
+use gravitydb::versioning::{self, UnsupportedStep, VersionError, FORMAT_VERSION};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn queries_keep_their_version() -> Result<(), VersionError> {
+  let query: BasicQuery = ql::PropertyQuery::from_id("GIN".to_string())
+    .referencing_vertices()
+    .ingoing()
+    .into();
+
+  let data = versioning::query_to_vec(&query)?;
+  let document: serde_json::Value = serde_json::from_slice(&data)?;
+  assert_eq!(document["version"], FORMAT_VERSION);
+  assert_eq!(document["data"], serde_json::to_value(&query)?);
+
+  let read: BasicQuery = versioning::query_from_slice(&data)?;
+  assert_eq!(read, query);
+  Ok(())
+}
+
+#[test]
+fn documents_without_envelope_are_migrated() -> Result<(), VersionError> {
+  let query: BasicQuery = versioning::query_from_slice(br#"{"E": {"Out": {"Property": {"Specific": "GIN"}}}}"#)?;
+  assert_eq!(
+    query,
+    ql::PropertyQuery::from_id("GIN".to_string()).referencing_vertices().outgoing().into()
+  );
+
+  // results of version 1 had no properties
+  let result: QueryResult = versioning::result_from_slice(br#"{
+    "vertices": [],
+    "edges": [["EDGE", null]],
+    "paths": [],
+    "variables": {}
+  }"#)?;
+  assert_eq!(result.edges.len(), 1);
+  assert!(result.properties.is_empty());
+
+  // ranges over property ids can not be expressed with ordered keys
+  let error = versioning::query_from_slice::<BasicQuery>(br#"{"V": {"Property": {"FromTo": ["A", "B"]}}}"#).unwrap_err();
+  match error {
+    VersionError::NotMigratable { version, step } => {
+      assert_eq!(version, 1);
+      assert_eq!(step, UnsupportedStep { path: "/V/Property".to_string(), step: "FromTo".to_string() });
+    }
+    e => panic!("unexpected error: {e}"),
+  }
+  let id = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+  let data = format!(r#"{{"V": {{"Property": {{"FromTo": ["{id}", "{id}"]}}}}}}"#);
+  let error = versioning::query_from_slice::<BasicQuery>(data.as_bytes()).unwrap_err();
+  assert!(matches!(error, VersionError::NotMigratable { version: 1, .. }));
+
+  // ordered keys were used before the envelope was introduced as well
+  let query: BasicQuery = versioning::query_from_slice(br#"{"V": {"Property": {"FromTo": ["0A", "0F00"]}}}"#)?;
+  assert_eq!(
+    query,
+    ql::PropertyQuery::from_to(ql::OrderedKey("0A".to_string()), ql::OrderedKey("0F00".to_string())).referencing_vertices().into()
+  );
+  let query = serde_json::json!({"V": {"Property": {"FromTo": ["0A", id]}}});
+  assert_eq!(versioning::bare_query_version(&query), 2);
+  Ok(())
+}
+
+#[test]
+fn unsupported_steps_are_reported() -> Result<(), VersionError> {
+  let error = versioning::query_from_slice::<BasicQuery>(br#"{"version": 99, "data": "All"}"#).unwrap_err();
+  assert!(matches!(error, VersionError::UnsupportedVersion(99)));
+
+  let data = format!(r#"{{"version": {FORMAT_VERSION}, "data": {{"V": {{"Union": ["All", {{"Teleport": "All"}}]}}}}}}"#);
+  let error = versioning::query_from_slice::<BasicQuery>(data.as_bytes()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    format!("steps not supported in version {FORMAT_VERSION}: 'Teleport' at '/V/Union/1'")
+  );
+
+  // newer steps can not be sent to older versions
+  let query: BasicQuery = ql::PropertyQuery::from_json_field("/Person/surname", "Doe")
+    .referencing_edges()
+    .store()
+    .into();
+  versioning::check_query(&query, FORMAT_VERSION)?;
+  match versioning::check_query(&query, 1) {
+    Err(VersionError::UnsupportedSteps { version, steps }) => {
+      assert_eq!(version, 1);
+      assert_eq!(steps, vec![UnsupportedStep { path: "/E/Store/Property".to_string(), step: "JsonField".to_string() }]);
+    }
+    result => panic!("unexpected result: {result:?}"),
+  }
+
+  let query: BasicQuery = ql::VertexQuery::all()
+    .traverse(ql::Traversal::new(ql::Direction::Outgoing).along(ql::PropertyQuery::from_json_field("/Includes", true)))
+    .into();
+  match versioning::check_query(&query, 2) {
+    Err(VersionError::UnsupportedSteps { steps, .. }) => {
+      assert_eq!(steps, vec![UnsupportedStep { path: "/V".to_string(), step: "Traverse".to_string() }]);
+    }
+    result => panic!("unexpected result: {result:?}"),
+  }
+  match versioning::check_query(&query, 1) {
+    Err(VersionError::UnsupportedSteps { steps, .. }) => {
+      assert_eq!(steps, vec![
+        UnsupportedStep { path: "/V".to_string(), step: "Traverse".to_string() },
+        UnsupportedStep { path: "/V/Traverse/1/edges".to_string(), step: "JsonField".to_string() },
+      ]);
+    }
+    result => panic!("unexpected result: {result:?}"),
+  }
+
+  let query: BasicQuery = ql::VertexQuery::all()
+    .degree("d", ql::DegreeCount::new(ql::Direction::Both).along(ql::PropertyQuery::from_json_field("/Includes", true)))
+    .into();
+  versioning::check_query(&query, FORMAT_VERSION)?;
+  match versioning::check_query(&query, 4) {
+    Err(VersionError::UnsupportedSteps { steps, .. }) => {
+      assert_eq!(steps, vec![UnsupportedStep { path: "/V".to_string(), step: "Degree".to_string() }]);
+    }
+    result => panic!("unexpected result: {result:?}"),
+  }
+
+  let query: BasicQuery = ql::EdgeQuery::all()
+    .order_by(ql::SortOrder::by_field("/weight"))
+    .limit(10)
+    .into();
+  versioning::check_query(&query, FORMAT_VERSION)?;
+  match versioning::check_query(&query, 5) {
+    Err(VersionError::UnsupportedSteps { steps, .. }) => {
+      assert_eq!(steps, vec![
+        UnsupportedStep { path: "/E".to_string(), step: "Limit".to_string() },
+        UnsupportedStep { path: "/E/Limit/0".to_string(), step: "Order".to_string() },
+      ]);
+    }
+    result => panic!("unexpected result: {result:?}"),
+  }
+  Ok(())
+}
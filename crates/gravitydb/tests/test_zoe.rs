@@ -0,0 +1,227 @@
+use gravitydb::ql::{self, ShellFilter};
+use gravitydb::kv_graph_store::{BasicQuery, Uuid};
+use gravitydb::zoe;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn start_points() {
+  assert_eq!(parse("V"), ql::VertexQuery::all().into());
+  assert_eq!(parse("E"), ql::EdgeQuery::all().into());
+  assert_eq!(
+    parse(&format!(r#"V("{NODE1_UUID}", "{NODE2_UUID}")"#)),
+    ql::VertexQuery::from_ids(vec![Uuid::from_key(NODE1_UUID).unwrap(), Uuid::from_key(NODE2_UUID).unwrap()]).into()
+  );
+  assert_eq!(parse("E()"), ql::EdgeQuery::from_ids(vec![]).into());
+  assert_eq!(
+    parse(r#"V["ABC"]"#),
+    ql::PropertyQuery::from_id("ABC".to_string()).referencing_vertices().into()
+  );
+  assert_eq!(
+    parse(r#"P("0A".."0F")"#),
+    ql::PropertyQuery::from_to(ql::OrderedKey("0A".to_string()), ql::OrderedKey("0F".to_string())).into()
+  );
+  assert_eq!(
+    parse(r#"P("/Person/surname" = "Doe")"#),
+    ql::PropertyQuery::from_json_field("/Person/surname", "Doe").into()
+  );
+}
+
+#[test]
+fn steps_and_set_operations() {
+  let gin = ql::PropertyQuery::from_id("GIN".to_string());
+  let includes = ql::PropertyQuery::from_id("INCLUDES".to_string());
+  let expected: BasicQuery = gin.referencing_vertices()
+    .ingoing()
+    .intersect(includes.referencing_edges())
+    .ingoing()
+    .into();
+
+  assert_eq!(parse(r#"(V["GIN"] <- & E["INCLUDES"]) <-"#), expected);
+  assert_eq!(parse(r#"(V[P("GIN")] <- & E[P("INCLUDES")]) <-"#), expected);
+
+  let filter = ShellFilter { interpreter: "lua".to_string(), script: "return \"x\"".to_string() };
+  assert_eq!(
+    parse(r#"V.filter("lua", "return \"x\"") -> .store"#),
+    ql::VertexQuery::all().filter(filter).outgoing().store().into()
+  );
+
+  let traversal = ql::Traversal::new(ql::Direction::Both)
+    .depth(0, None)
+    .along(ql::PropertyQuery::from_id("INCLUDES".to_string()));
+  assert_eq!(
+    parse(r#"V.traverse(<->, 0, null, "INCLUDES")"#),
+    ql::VertexQuery::all().traverse(traversal).into()
+  );
+
+  let search = ql::PathSearch::new(ql::Direction::Outgoing).weighted("/Road/length");
+  assert_eq!(
+    parse(r#"V["A"].shortest_path(->, V["B"] | V["C"], weight "/Road/length")"#),
+    ql::PropertyQuery::from_id("A".to_string()).referencing_vertices()
+      .shortest_path(
+        ql::PropertyQuery::from_id("B".to_string()).referencing_vertices()
+          .union(ql::PropertyQuery::from_id("C".to_string()).referencing_vertices()),
+        search,
+      )
+      .into()
+  );
+
+  assert_eq!(
+    parse(r#"V["C"].count("cocktails").degree("ingredients", ->, "INCLUDES") -> .group_by_nested("types", "/SchemaType")"#),
+    ql::PropertyQuery::from_id("C".to_string()).referencing_vertices()
+      .aggregate("cocktails", ql::Aggregation::Count)
+      .degree("ingredients", ql::DegreeCount::new(ql::Direction::Outgoing).along(ql::PropertyQuery::from_id("INCLUDES".to_string())))
+      .outgoing()
+      .aggregate("types", ql::Aggregation::GroupByNested("/SchemaType".to_string()))
+      .into()
+  );
+
+  assert_eq!(
+    parse(r#"V.order_by("/Cocktail", desc).skip(20).limit(10)"#),
+    ql::VertexQuery::all()
+      .order_by(ql::SortOrder::by_field("/Cocktail").descending())
+      .skip(20)
+      .limit(10)
+      .into()
+  );
+
+  // set operations are left associative
+  let v: ql::VertexQuery<Uuid, String, String, ShellFilter, ShellFilter> = ql::VertexQuery::all();
+  assert_eq!(
+    parse("V | V - V"),
+    v.clone().union(v.clone()).substract(v.clone()).into()
+  );
+  assert_eq!(
+    parse("V | (V - V)"),
+    v.clone().union(v.clone().substract(v)).into()
+  );
+}
+
+#[test]
+fn cocktails_containing_gin() {
+  // vertices are selected by the ids of their properties
+  let expected: BasicQuery = ql::PropertyQuery::from_id("GIN".to_string()).referencing_vertices()
+    .ingoing()
+    .intersect(ql::PropertyQuery::from_id("INCLUDES".to_string()).referencing_edges())
+    .ingoing()
+    .intersect(ql::PropertyQuery::from_id("COCKTAIL".to_string()).referencing_vertices())
+    .into();
+
+  assert_eq!(parse(r#"(V["GIN"] <- & E["INCLUDES"]) <- & V["COCKTAIL"]"#), expected);
+  assert_eq!(parse(r#"P("GIN") -> V <- E[P("INCLUDES")] <- V & P("COCKTAIL")"#), expected);
+
+  // with a schema the values of the properties can be given instead,
+  // strings stay ids
+  let resolve = |value: &serde_json::Value| match value.to_string().as_str() {
+    r#""Includes""# => Some("INCLUDES".to_string()),
+    r#"{"SchemaType":"Cocktail"}"# => Some("COCKTAIL".to_string()),
+    _ => None,
+  };
+  let text = r#"P("gin") -> V <- E[Includes] <- V & P(SchemaType:Cocktail)"#;
+  assert_eq!(zoe::parse_with(text, resolve), Ok(parse(r#"P("gin") -> V <- E[P("INCLUDES")] <- V & P("COCKTAIL")"#)));
+  assert_eq!(zoe::parse_with(r#"P({"SchemaType": "Cocktail"})"#, resolve), Ok(parse(r#"P("COCKTAIL")"#)));
+  assert_eq!(zoe::parse_with(r#"P(SchemaType:"Cocktail")"#, resolve), Ok(parse(r#"P("COCKTAIL")"#)));
+
+  let error = zoe::parse_with::<Uuid, String, String, _>("V[Glass]", resolve).unwrap_err();
+  assert_eq!(error.message, "\"Glass\" is no property of the schema");
+  assert_eq!(error.span, 2..7);
+
+  let error = zoe::parse::<Uuid, String, String>("V[Includes]").unwrap_err();
+  assert_eq!(error.message, "values of properties need a schema, give the id of the property instead");
+
+  let error = zoe::parse::<Uuid, String, String>(r#"P("GIN") ->"#).unwrap_err();
+  assert_eq!(error.message, "expected 'V' or 'E' after the properties");
+
+  let error = zoe::parse::<Uuid, String, String>(r#"P("GIN") <- V"#).unwrap_err();
+  assert_eq!(error.message, "the step '<-' can not be used on properties");
+  assert_eq!(error.span, 9..11);
+
+  let error = zoe::parse::<Uuid, String, String>("V -> V").unwrap_err();
+  assert_eq!(error.message, "expected edges after the arrow");
+  assert_eq!(error.span, 5..6);
+}
+
+#[test]
+fn queries_can_be_printed_and_parsed_again() {
+  let queries = [
+    "V",
+    r#"V("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8")"#,
+    r#"(V["GIN"] <- & E["INCLUDES"]) <-"#,
+    r#"V | (V - V ^ E ->) <- <- .store"#,
+    r#"E[P("X").referencing.referenced].filter("sh -c", "grep \"gin\"")"#,
+    r#"V[P("/Person" = {"surname": "Doe", "age": [1, 2.5]})]"#,
+    r#"P("00".."FF")"#,
+    r#"V.traverse(->, 1, 3) | V.traverse(<-, 2, null, P("X").referenced)"#,
+    r#"V.shortest_path(<->, V -> ->, along "X", weight "/length").store"#,
+    r#"(V | V).count("n") -> .group_by("kinds", "") -> .degree("d", <->)"#,
+    r#"V.degree("d", <-, P("X").referencing).group_by_nested("types", "/SchemaType")"#,
+    r#"(V | V).order_by(id).limit(3) -> .order_by("", desc).skip(1)"#,
+  ];
+
+  for text in queries {
+    let query = parse(text);
+    let printed = query.to_string();
+    assert_eq!(parse(&printed), query, "{text} was printed as {printed}");
+    assert_eq!(parse(&parse(&printed).to_string()).to_string(), printed);
+  }
+
+  assert_eq!(
+    parse(r#"(V["GIN"] <- & E["INCLUDES"]) <-"#).to_string(),
+    r#"(V[P("GIN")] <- & E[P("INCLUDES")]) <-"#
+  );
+}
+
+#[test]
+fn errors_point_to_the_problem() {
+  let error = zoe::parse::<Uuid, String, String>(r#"V("no uuid")"#).unwrap_err();
+  assert_eq!(error.message, "invalid vertex id \"no uuid\"");
+  assert_eq!(error.span, 2..11);
+
+  let error = zoe::parse::<Uuid, String, String>("V | E").unwrap_err();
+  assert_eq!(error.message, "expected vertices on both sides");
+  assert_eq!(error.span, 0..5);
+
+  let error = zoe::parse::<Uuid, String, String>("V.referencing").unwrap_err();
+  assert_eq!(error.message, "the step 'referencing' can not be used on vertices");
+
+  let error = zoe::parse::<Uuid, String, String>("V\n  -> .stroe").unwrap_err();
+  assert_eq!(error.message, "unknown step 'stroe'");
+  assert_eq!(error.report("V\n  -> .stroe"), [
+    "error: unknown step 'stroe'",
+    "   2 |   -> .stroe",
+    "     |       ^^^^^",
+  ].join("\n"));
+
+  let error = zoe::parse::<Uuid, String, String>("V.traverse(->, -1, 2)").unwrap_err();
+  assert_eq!(error.message, "expected the minimal number of hops");
+  assert_eq!(error.span, 15..16);
+
+  let error = zoe::parse::<Uuid, String, String>("V.shortest_path(->, V, via \"X\")").unwrap_err();
+  assert_eq!(error.message, "unknown option 'via'");
+
+  let error = zoe::parse::<Uuid, String, String>("E.degree(\"d\", ->)").unwrap_err();
+  assert_eq!(error.message, "the step 'degree' can not be used on edges");
+
+  let error = zoe::parse::<Uuid, String, String>("V.order_by(name)").unwrap_err();
+  assert_eq!(error.message, "expected 'id' or a json pointer");
+  assert_eq!(error.span, 11..12);
+
+  let error = zoe::parse::<Uuid, String, String>("V.limit(\"10\")").unwrap_err();
+  assert_eq!(error.message, "expected the number of elements");
+
+  let error = zoe::parse::<Uuid, String, String>("(V").unwrap_err();
+  assert_eq!(error.message, "expected ')'");
+  assert_eq!(error.span, 2..2);
+
+  let error = zoe::parse::<Uuid, String, String>("V V").unwrap_err();
+  assert_eq!(error.message, "expected the end of the query");
+}
+
+fn parse(text: &str) -> BasicQuery {
+  match zoe::parse(text) {
+    Ok(query) => query,
+    Err(e) => panic!("{}", e.report(text)),
+  }
+}
+
+const NODE1_UUID : &str = "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8";
+const NODE2_UUID : &str = "e1e2e3e4-f1f2-a1a2-b1b2-b3b4b5b6b7b8";
@@ -34,7 +34,9 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
           };
 
           let (additional_types, custom): (Vec<_>, Vec<_>) = get_attrs(&v)
-            .into_iter().partition(|(attr_name, _, _)| match attr_name.as_str() {
+            .into_iter()
+            .filter(|(attr_name, _, _)| attr_name != "ordered")
+            .partition(|(attr_name, _, _)| match attr_name.as_str() {
               "additional_types" => true,
               "custom" => false,
               _ => unimplemented!("attribute '{}' not supported", attr_name),
@@ -77,9 +79,45 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
       Data::Struct(_) | Data::Union(_) => unimplemented!(),
     };
 
+    let ordered_keys = match &input.data {
+      Data::Enum(ref data) => {
+        let is_ordered = |v: &Variant| get_attrs(v).iter().any(|(attr_name, _, _)| attr_name == "ordered");
+        // the index of the variant is the first byte of the key
+        if let Some(v) = data.variants.iter().skip(256).find(|v| is_ordered(v)) {
+          return syn::Error::new_spanned(v, "ordered variants need to be among the first 256")
+            .to_compile_error()
+            .into();
+        }
+        let ordered_keys = data.variants.iter().enumerate()
+          .filter(|(_, v)| is_ordered(v))
+          .map(|(idx, v)| {
+            let v_name = &v.ident;
+            let idx = idx as u8;
+            let base_selector = quote_spanned! {
+              v.span()=>
+                #name::#v_name
+            };
+            let fields = field_bindings(v);
+            let base_selector = base_selector_with_bindings(v, base_selector, &fields);
+            quote_spanned! {
+              v.span()=>
+                #base_selector => {
+                  let mut key = vec![#idx];
+                  #(gravitydb::schema::OrderedEncoding::encode_ordered(#fields, &mut key);)*
+                  Some(key)
+                },
+            }
+          });
+        quote! {
+          #(#ordered_keys)*
+        }
+      }
+      Data::Struct(_) | Data::Union(_) => unimplemented!(),
+    };
+
 
     let expanded = quote! {
-      use gravitydb::schema::{KeyAdressableElement, NestableProperty};
+      use gravitydb::schema::{KeyAdressableElement, NestableProperty, OrderedProperty};
 
       impl gravitydb::schema::NestableProperty for #name {
         fn nested(&self) -> Vec<Self> {
@@ -88,6 +126,16 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
           }
         }
       }
+
+      impl gravitydb::schema::OrderedProperty for #name {
+        #[allow(unreachable_patterns)]
+        fn ordered_key(&self) -> Option<Vec<u8>> {
+          match self {
+            #ordered_keys
+            _ => None,
+          }
+        }
+      }
     };
 
     TokenStream::from(expanded).into()
@@ -125,7 +173,7 @@ fn get_attrs(v: &Variant) -> Vec<(String, String, &MetaList)> {
     match &attr.meta {
       List(meta) => {
         let kv = meta.tokens.to_string();
-        let (attr_name, value) = kv.split_once("=").unwrap();
+        let (attr_name, value) = kv.split_once("=").unwrap_or((&kv, ""));
         (attr_name.trim().to_string(), value.trim().to_string(), meta)
       },
       _ => unimplemented!()
@@ -196,3 +244,32 @@ fn base_selector_with_fields(v: &Variant, base: TokenStream) -> TokenStream {
     Fields::Unit => base,
   }
 }
+
+/// Names to bind all fields of a variant to (the field names or
+/// positional names for tuple variants)
+fn field_bindings(v: &Variant) -> Vec<Ident> {
+  v.fields.iter().enumerate().map(|(idx, f)| {
+    match &f.ident {
+      Some(name) => name.clone(),
+      None => Ident::new(&format!("field_{idx}"), f.span()),
+    }
+  }).collect()
+}
+
+fn base_selector_with_bindings(v: &Variant, base: TokenStream, bindings: &[Ident]) -> TokenStream {
+  match &v.fields {
+    Fields::Named(_) => {
+      quote_spanned! {
+        v.span()=>
+          #base{#(#bindings,)*}
+      }
+    },
+    Fields::Unnamed(_) => {
+      quote_spanned! {
+        v.span()=>
+          #base(#(#bindings,)*)
+      }
+    }
+    Fields::Unit => base,
+  }
+}
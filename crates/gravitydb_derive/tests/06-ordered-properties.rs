@@ -0,0 +1,38 @@
+// Should generate order preserving keys for the marked variants
+use gravitydb_derive::Schema;
+
+#[derive(Schema)]
+#[derive(Debug, PartialEq)]
+pub enum BasicPimSchema {
+  #[schema(ordered)]
+  Person{ name: String, age: u8 },
+  #[schema(ordered)]
+  Birthday(i64),
+  Organisation(String),
+  // edge types
+  BelongsTo,
+  SchemaType(String),
+}
+
+fn main() {
+  use BasicPimSchema::*;
+
+  let alice = Person { name: "Alice".to_string(), age: 30 }.ordered_key().unwrap();
+  let bob = Person { name: "Bob".to_string(), age: 20 }.ordered_key().unwrap();
+  let bobby = Person { name: "Bobby".to_string(), age: 10 }.ordered_key().unwrap();
+  assert!(alice < bob);
+  assert!(bob < bobby);
+
+  let before_1970 = Birthday(-1000).ordered_key().unwrap();
+  let after_1970 = Birthday(1000).ordered_key().unwrap();
+  assert!(before_1970 < after_1970);
+  // every variant has its own range
+  assert!(bobby < before_1970);
+
+  assert_eq!(Organisation("ACME".to_string()).ordered_key(), None);
+  assert_eq!(BelongsTo.ordered_key(), None);
+  assert_eq!(
+    Person { name: "Alice".to_string(), age: 30 }.nested(),
+    vec![BasicPimSchema::SchemaType("Person".to_string())]
+  );
+}
@@ -6,6 +6,8 @@ fn tests() {
     t.pass("tests/03-schema_type_not_recursive.rs");
     t.pass("tests/04-additional-schema-types.rs");
     t.pass("tests/05-customize-schema-types.rs");
+    t.pass("tests/06-ordered-properties.rs");
+    t.compile_fail("tests/07-too-many-ordered-variants.rs");
 }
 
 include!("tutorial_designing_a_schema.rs");
@@ -60,7 +60,9 @@ fn t02_optimized_schema() {
     Workshop{ name: String },
     Tool(String),
     Location{ address: String },
+    #[schema(ordered)]
     Lattitude(u32),
+    #[schema(ordered)]
     Longitude(u32),
 
     // connections
@@ -133,7 +135,7 @@ fn t02_optimized_schema() {
   let result = db.extract_properties(&result).unwrap();
   assert_eq!(result, [Workshop { name: "Nick's Workspace".to_string() }]);
 
-  let result = db.query(Lattitude(30).from_to(&Lattitude(50))
+  let result = db.query(Lattitude(30).range_to(&Lattitude(50))
     .referencing_vertices()
     .ingoing()
     .intersect(LocatedAt.start().referencing_edges())
@@ -141,4 +143,16 @@ fn t02_optimized_schema() {
   ).unwrap();
   let result = db.extract_properties(&result).unwrap();
   assert_eq!(result, [Workshop { name: "Nick's Workspace".to_string() }]);
+
+  // The range follows the numbers, not their textual representation
+  let result = db.query(Lattitude(9).range_to(&Lattitude(100))
+    .referencing_vertices()
+  ).unwrap();
+  let result = db.extract_properties(&result).unwrap();
+  assert_eq!(result, [Lattitude(42)]);
+
+  let result = db.query(Longitude(43).range_to(&Longitude(100))
+    .referencing_vertices()
+  ).unwrap();
+  assert_eq!(result.vertices.len(), 0);
 }